@@ -0,0 +1,83 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ffmonitor::wire;
+
+/// Build a frame like the ones sent during large events: mostly player positions,
+/// with some chats, broadcasts and emails mixed in.
+fn big_frame(players: usize) -> (String, usize) {
+    let mut frame = String::from("begin\n");
+    let mut events = 0;
+    for i in 0..players {
+        frame += &format!(
+            "player {} {} Player{} Number{}\n",
+            i * 37,
+            -(i as i64) * 11,
+            i,
+            i % 97
+        );
+        events += 1;
+        if i % 10 == 0 {
+            frame += &format!(
+                "chat [Normal] Player{} Number{}: hello there {}\n",
+                i,
+                i % 97,
+                i
+            );
+            frame += &format!(
+                "chat [Buddy] Player{} Number{} (to Friend {}): meet at the plaza\n",
+                i,
+                i % 97,
+                i
+            );
+            events += 2;
+        }
+        if i % 100 == 0 {
+            frame += &format!("bcast 1 2 10 GM {}: Event starting soon\n", i);
+            frame += &format!(
+                "email [Email] Player{} Number{} (to Friend {}): <Hello>\n\tline one\n\t  line two\nendemail\n",
+                i,
+                i % 97,
+                i
+            );
+            frame += &format!("namereq {} Requested Name{}\n", i, i);
+            events += 3;
+        }
+    }
+    frame += "end";
+    (frame, events)
+}
+
+fn parse_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_frame");
+    for players in [100, 10_000, 50_000] {
+        let (frame, events) = big_frame(players);
+        group.throughput(Throughput::Elements(events as u64));
+        group.bench_function(format!("{}_players", players), |b| {
+            b.iter(|| wire::parse_frame(black_box(&frame)))
+        });
+    }
+    group.finish();
+}
+
+fn parse_event_lines(c: &mut Criterion) {
+    let lines = [
+        ("player", "player 123456 -654321 Some Player"),
+        ("chat", "chat [Normal] Some Player: hello there"),
+        (
+            "directed_chat",
+            "chat [Buddy] Some Player (to Other Player): hi",
+        ),
+        ("bcast", "bcast 1 2 10 Some GM: Event starting soon"),
+        ("namereq", "namereq 1234 Requested Name"),
+    ];
+    let mut group = c.benchmark_group("parse_event_line");
+    group.throughput(Throughput::Elements(1));
+    for (name, line) in lines {
+        group.bench_function(name, |b| b.iter(|| wire::parse_event_line(black_box(line))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_frames, parse_event_lines);
+criterion_main!(benches);
@@ -13,7 +13,7 @@ fn callback(notifcation: MonitorNotification) {
         }
         MonitorNotification::Updated(update) => {
             println!("Player count: {}", update.get_player_count());
-            let events = update.get_events();
+            let events = update.events();
             if events.is_empty() {
                 println!("\tNo events");
             } else {
@@ -22,6 +22,7 @@ fn callback(notifcation: MonitorNotification) {
                 }
             }
         }
+        _ => {}
     }
 }
 
@@ -0,0 +1,23 @@
+use std::{env, time::Duration};
+
+use ffmonitor::{discord::DiscordRelay, EventKind, Monitor};
+use log::LevelFilter;
+
+fn main() {
+    env_logger::builder()
+        .format_timestamp(None)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let webhook_url = env::args().nth(1).expect("Usage: discord <webhook url>");
+    let address = "127.0.0.1:8003";
+    println!("Connecting to monitor at {}", address);
+    let monitor = Monitor::new(address).expect("Bad address");
+
+    let relay = DiscordRelay::new(&webhook_url)
+        .with_username("OpenFusion")
+        .with_kinds(&[EventKind::Broadcast, EventKind::NameRequest])
+        .with_template(EventKind::Broadcast, ":loudspeaker: {from}: {message}")
+        .with_rate_limit(Duration::from_secs(2));
+    relay.spawn(monitor.subscribe()).join().unwrap();
+}
@@ -26,7 +26,7 @@ fn main() {
 
         while let Some(update) = monitor.poll() {
             println!("Player count: {}", update.get_player_count());
-            let events = update.get_events();
+            let events = update.events();
             if events.is_empty() {
                 println!("\tNo events");
             } else {
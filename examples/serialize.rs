@@ -1,6 +1,6 @@
 use ffmonitor::{
-    BroadcastEvent, BroadcastScope, ChatEvent, ChatKind, EmailEvent, Event, MonitorUpdate,
-    NameRequestEvent, PlayerEvent,
+    AnnouncementType, BroadcastEvent, BroadcastScope, ChatEvent, ChatKind, EmailEvent, EmailItem,
+    Event, MonitorUpdate, NameRequestEvent, PlayerEvent,
 };
 
 fn main() {
@@ -11,6 +11,7 @@ fn main() {
         x_coord: 10,
         y_coord: -20,
         name: "Captain Courage".to_string(),
+        extra: Vec::new(),
     }));
 
     // Chat event
@@ -19,6 +20,7 @@ fn main() {
         from: "Captain Courage".to_string(),
         to: None,
         message: "Hello world!".to_string(),
+        extra: Vec::new(),
     }));
 
     monitor_update.add_event(Event::Chat(ChatEvent {
@@ -26,15 +28,17 @@ fn main() {
         from: "Captain Courage".to_string(),
         to: Some("Corporal Cautious".to_string()),
         message: "Hello friend!".to_string(),
+        extra: Vec::new(),
     }));
 
     // Broadcast event
     monitor_update.add_event(Event::Broadcast(BroadcastEvent {
         scope: BroadcastScope::Local,
-        announcement_type: 1,
+        announcement_type: AnnouncementType::Ticker,
         duration_secs: 5,
         from: "Captain Courage".to_string(),
         message: "Brace for impact!".to_string(),
+        extra: Vec::new(),
     }));
 
     // Email event
@@ -48,6 +52,13 @@ fn main() {
             "".to_string(),
             "-Captain Courage".to_string(),
         ],
+        taros: Some(5000),
+        items: vec![EmailItem {
+            item_type: 0,
+            item_id: 42,
+            quantity: 1,
+        }],
+        extra: Vec::new(),
     }));
 
     monitor_update.add_event(Event::Email(EmailEvent {
@@ -55,12 +66,16 @@ fn main() {
         to: "Captain Courage".to_string(),
         subject: None,
         body: vec!["Roger that.".to_string()],
+        taros: None,
+        items: Vec::new(),
+        extra: Vec::new(),
     }));
 
     // Name request event
     monitor_update.add_event(Event::NameRequest(NameRequestEvent {
         player_uid: 123,
         requested_name: "Colonel Catastrophe".to_string(),
+        extra: Vec::new(),
     }));
 
     println!("{}", monitor_update);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use ffmonitor::wire;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|frame: &str| {
+    let _ = wire::parse_frame(frame);
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use ffmonitor::wire;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|line: &str| {
+    if let Ok(event) = wire::parse_line(line) {
+        let _ = wire::format_event(&event);
+    }
+});
@@ -0,0 +1,46 @@
+#![no_main]
+
+use ffmonitor::{
+    wire::{self, Tokenizer},
+    Event,
+};
+use libfuzzer_sys::fuzz_target;
+
+const VECTORS: &str = include_str!("../../proto/tests/vectors/tokenizer.txt");
+
+/// Parse an event line, or an email header as an email with no body.
+fn parse(line: &str, tokenizer: Tokenizer) -> Vec<Event> {
+    match line.starts_with("email") {
+        true => wire::parse_frame_with(&format!("{}\nendemail", line), tokenizer)
+            .events()
+            .to_vec(),
+        false => wire::parse_line_with(line, tokenizer).into_iter().collect(),
+    }
+}
+
+fuzz_target!(
+    init: {
+        let vectors = VECTORS
+            .lines()
+            .filter(|vector| !vector.is_empty() && !vector.starts_with('#'));
+        for vector in vectors {
+            let (expected, line) = vector.split_at(2);
+            let events = parse(line, Tokenizer::Strict);
+            assert_eq!(events, parse(line, Tokenizer::Regex), "{}", line);
+            match expected {
+                "+ " => assert!(
+                    events
+                        .first()
+                        .is_some_and(|event| event.to_string().lines().next() == Some(line)),
+                    "{}",
+                    line
+                ),
+                "- " => assert!(events.is_empty(), "{}", line),
+                _ => panic!("Bad vector: {}", vector),
+            }
+        }
+    },
+    |line: &str| {
+        assert_eq!(parse(line, Tokenizer::Strict), parse(line, Tokenizer::Regex));
+    }
+);
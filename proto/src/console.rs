@@ -0,0 +1,83 @@
+//! Turning events into the commands admins send to an OpenFusion server.
+//!
+//! Events seen on the monitor can be sent back as `Command`s over a live connection,
+//! or formatted as the GM chat commands admins paste into the game, such as
+//! `/announce <scope> <type> <duration> <message>` for broadcasts. Scopes and
+//! announcement types are written as the same numbers used on the wire.
+
+use crate::{BroadcastEvent, Command, NameRequestEvent};
+
+/// Format an announcement as a single `/announce` command.
+/// Line breaks can't be pasted into the chat box, so they are replaced by spaces.
+fn announce_command(
+    scope: usize,
+    announcement_type: usize,
+    duration: usize,
+    message: &str,
+) -> String {
+    let message = message.replace("\r\n", " ").replace(['\r', '\n'], " ");
+    format!(
+        "/announce {} {} {} {}",
+        scope, announcement_type, duration, message
+    )
+}
+
+impl BroadcastEvent {
+    /// Get the Command that repeats the broadcast over the monitor connection.
+    /// The sender isn't part of the command, so the server picks who it's from.
+    pub fn to_command(&self) -> Command {
+        Command::Broadcast {
+            scope: self.scope.clone(),
+            announcement_type: self.announcement_type.clone(),
+            duration_secs: self.duration_secs,
+            message: self.message.clone(),
+        }
+    }
+
+    /// Format the broadcast as the `/announce` command that repeats it from the game.
+    pub fn to_announce_command(&self) -> String {
+        announce_command(
+            self.scope.clone().into(),
+            self.announcement_type.clone().into(),
+            self.duration_secs,
+            &self.message,
+        )
+    }
+}
+
+impl NameRequestEvent {
+    /// Get the Command approving the requested name.
+    pub fn to_approve_command(&self) -> Command {
+        Command::ApproveName {
+            player_uid: self.player_uid,
+        }
+    }
+
+    /// Get the Command rejecting the requested name.
+    pub fn to_reject_command(&self) -> Command {
+        Command::RejectName {
+            player_uid: self.player_uid,
+        }
+    }
+}
+
+impl Command {
+    /// Format the command as the GM chat command doing the same from the game, if any.
+    /// Only broadcasts have one; names are approved and rejected from the name queue.
+    pub fn to_console_command(&self) -> Option<String> {
+        match self {
+            Self::Broadcast {
+                scope,
+                announcement_type,
+                duration_secs,
+                message,
+            } => Some(announce_command(
+                scope.clone().into(),
+                announcement_type.clone().into(),
+                *duration_secs,
+                message,
+            )),
+            Self::ApproveName { .. } | Self::RejectName { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::{Event, MonitorUpdate, PlayerEvent};
+
+/// A player whose position changed between two updates.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerMove {
+    pub name: String,
+    pub from_x: i32,
+    pub from_y: i32,
+    pub to_x: i32,
+    pub to_y: i32,
+}
+
+/// The changes between two MonitorUpdates, as returned by `MonitorUpdate::diff()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateDiff {
+    /// Players present in the new update but not in the previous one.
+    pub added: Vec<PlayerEvent>,
+    /// Players present in the previous update but not in the new one.
+    pub removed: Vec<PlayerEvent>,
+    /// Players present in both updates at different positions.
+    pub moved: Vec<PlayerMove>,
+    /// Non-player events of the new update that weren't in the previous one.
+    pub new_events: Vec<Event>,
+}
+impl UpdateDiff {
+    /// Check if the two updates were equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.new_events.is_empty()
+    }
+}
+
+impl MonitorUpdate {
+    /// Compute what changed in this MonitorUpdate relative to a previous one.
+    pub fn diff(&self, previous: &MonitorUpdate) -> UpdateDiff {
+        let mut diff = UpdateDiff::default();
+
+        let mut previous_players: HashMap<&str, &PlayerEvent> = HashMap::new();
+        let mut previous_events: HashMap<&Event, usize> = HashMap::new();
+        for event in &previous.events {
+            match event {
+                Event::Player(player) => {
+                    previous_players.insert(&player.name, player);
+                }
+                other => *previous_events.entry(other).or_default() += 1,
+            }
+        }
+
+        for event in &self.events {
+            match event {
+                Event::Player(player) => match previous_players.remove(player.name.as_str()) {
+                    None => diff.added.push(player.clone()),
+                    Some(old) if (old.x_coord, old.y_coord) != (player.x_coord, player.y_coord) => {
+                        diff.moved.push(PlayerMove {
+                            name: player.name.clone(),
+                            from_x: old.x_coord,
+                            from_y: old.y_coord,
+                            to_x: player.x_coord,
+                            to_y: player.y_coord,
+                        })
+                    }
+                    Some(_) => {}
+                },
+                other => match previous_events.get_mut(other) {
+                    // identical events are matched up one for one
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => diff.new_events.push(other.clone()),
+                },
+            }
+        }
+
+        // keep the order of the previous update for removed players
+        diff.removed = previous
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Player(player) if previous_players.contains_key(player.name.as_str()) => {
+                    Some(player.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        diff
+    }
+}
@@ -0,0 +1,159 @@
+//! Readable formatting of events, for terminals, logs and chat bots.
+//!
+//! The `Display` impls of the event types produce the wire format. Their `human()`
+//! methods wrap them in a `Human`, which displays them the way a person would rather
+//! read them instead, e.g. `[FreeChat] Captain Courage: Hello`.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    BroadcastEvent, ChatEvent, CustomEvent, EmailEvent, Event, MonitorUpdate, NameRequestEvent,
+    PlayerEvent,
+};
+
+/// Displays the wrapped event in a readable form, as returned by its `human()` method.
+/// Emails and updates are displayed over several lines.
+#[derive(Debug, Clone, Copy)]
+pub struct Human<'a, T: ?Sized>(&'a T);
+
+macro_rules! impl_human {
+    ($($event:ty),*) => {$(
+        impl $event {
+            /// Display the event in a readable form rather than the wire format.
+            pub fn human(&self) -> Human<'_, Self> {
+                Human(self)
+            }
+        }
+    )*};
+}
+impl_human!(
+    PlayerEvent,
+    ChatEvent,
+    BroadcastEvent,
+    EmailEvent,
+    NameRequestEvent,
+    CustomEvent,
+    Event
+);
+
+impl Event {
+    /// Format the event in a readable form rather than the wire format.
+    pub fn to_human_string(&self) -> String {
+        self.human().to_string()
+    }
+}
+
+impl MonitorUpdate {
+    /// Display the player count of the update and each of its events in a readable form.
+    pub fn human(&self) -> Human<'_, Self> {
+        Human(self)
+    }
+}
+
+impl Display for Human<'_, PlayerEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let player = self.0;
+        write!(
+            f,
+            "{} at ({}, {})",
+            player.name, player.x_coord, player.y_coord
+        )
+    }
+}
+
+impl Display for Human<'_, ChatEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let chat = self.0;
+        match &chat.to {
+            Some(to) => write!(
+                f,
+                "[{}] {} to {}: {}",
+                chat.kind, chat.from, to, chat.message
+            ),
+            None => write!(f, "[{}] {}: {}", chat.kind, chat.from, chat.message),
+        }
+    }
+}
+
+impl Display for Human<'_, BroadcastEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let bcast = self.0;
+        write!(
+            f,
+            "[{:?} {}, {}s] {}: {}",
+            bcast.scope, bcast.announcement_type, bcast.duration_secs, bcast.from, bcast.message
+        )
+    }
+}
+
+impl Display for Human<'_, EmailEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let email = self.0;
+        match email.subject_text() {
+            Some(subject) => write!(f, "Email from {} to {}: {}", email.from, email.to, subject)?,
+            None => write!(f, "Email from {} to {} (no subject)", email.from, email.to)?,
+        }
+        for line in &email.body {
+            write!(f, "\n    {}", line)?;
+        }
+        let mut attachments: Vec<String> = email
+            .taros
+            .map(|taros| format!("{} taros", taros))
+            .into_iter()
+            .collect();
+        attachments.extend(email.items.iter().map(|item| {
+            format!(
+                "{}x item {}:{}",
+                item.quantity, item.item_type, item.item_id
+            )
+        }));
+        if !attachments.is_empty() {
+            write!(f, "\n    Attached: {}", attachments.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Human<'_, NameRequestEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let namereq = self.0;
+        write!(
+            f,
+            "Player {} requested the name {}",
+            namereq.player_uid, namereq.requested_name
+        )
+    }
+}
+
+impl Display for Human<'_, CustomEvent> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.line)
+    }
+}
+
+impl Display for Human<'_, Event> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Event::Player(event) => event.human().fmt(f),
+            Event::Chat(event) => event.human().fmt(f),
+            Event::Broadcast(event) => event.human().fmt(f),
+            Event::Email(event) => event.human().fmt(f),
+            Event::NameRequest(event) => event.human().fmt(f),
+            Event::Custom(event) => event.human().fmt(f),
+        }
+    }
+}
+
+impl Display for Human<'_, MonitorUpdate> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let update = self.0;
+        match update.get_player_count() {
+            1 => write!(f, "1 player online")?,
+            count => write!(f, "{} players online", count)?,
+        }
+        for event in update.events() {
+            write!(f, "\n{}", event.human())?;
+        }
+        Ok(())
+    }
+}
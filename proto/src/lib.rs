@@ -0,0 +1,805 @@
+//! The OpenFusion monitor protocol: events, and parsing and formatting them.
+//!
+//! This crate holds everything about the wire format and nothing about connections, so
+//! it builds for targets without sockets or threads, such as `wasm32-unknown-unknown`.
+//! The `ffmonitor` crate re-exports all of it along with the Monitor client.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+
+pub mod console;
+mod diff;
+pub mod human;
+mod logging;
+pub mod map;
+pub mod name;
+pub mod protocol;
+pub mod wire;
+
+pub use diff::{PlayerMove, UpdateDiff};
+
+type Error = Box<dyn std::error::Error>;
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ViolationKind {
+    /// `end` line without a matching `begin`. The frame is dropped.
+    EndWithoutBegin,
+    /// `begin` line inside a frame. The frame restarts from this line.
+    NestedBegin,
+    /// Event line outside of a frame. The line is dropped.
+    LineOutsideFrame,
+    /// `endemail` line without a matching `email` event.
+    StrayEndEmail,
+    /// `email` event not terminated by an `endemail` line. The email is dropped.
+    UnterminatedEmail,
+    /// Line that is not valid UTF-8. The line is dropped unless lossy decoding is enabled.
+    InvalidUtf8,
+    /// JSON frame that can't be decoded. The frame is dropped.
+    InvalidJsonFrame,
+    /// Line longer than the maximum line length of the `MonitorConfig`. The line is
+    /// dropped along with the frame it is in, and the violation holds its start only.
+    LineTooLong,
+    /// Frame with more lines than the maximum of the `MonitorConfig`. The frame is dropped.
+    FrameTooLong,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolViolation {
+    pub kind: ViolationKind,
+    /// The offending line, lossily decoded if it isn't valid UTF-8.
+    pub line: String,
+}
+impl Display for ProtocolViolation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Protocol violation ({:?}): {}", self.kind, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayerEvent {
+    pub x_coord: i32,
+    pub y_coord: i32,
+    pub name: String,
+    /// Fields sent after the known ones by servers newer than this version, each after
+    /// a tab (see `protocol::EXTRA_SEPARATOR`). Empty for lines without any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<String>,
+}
+
+/// The kind of a chat message, as written between brackets in `chat` lines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ChatKind {
+    FreeChat,
+    MenuChat,
+    BuddyChat,
+    BuddyMenuChat,
+    GroupChat,
+    GroupMenuChat,
+    TradeChat,
+    /// An emote, e.g. `/wave`, sent by newer servers.
+    Emote,
+    /// A message shouted to everyone nearby, sent by newer servers.
+    Shout,
+    /// A message from the server itself rather than a player, sent by newer servers.
+    System,
+    /// A kind this version doesn't know, holding its name as sent.
+    Unknown(String),
+}
+impl ChatKind {
+    /// Every kind this version knows, in the order they're documented.
+    pub const KNOWN: [ChatKind; 10] = [
+        Self::FreeChat,
+        Self::MenuChat,
+        Self::BuddyChat,
+        Self::BuddyMenuChat,
+        Self::GroupChat,
+        Self::GroupMenuChat,
+        Self::TradeChat,
+        Self::Emote,
+        Self::Shout,
+        Self::System,
+    ];
+
+    /// Get the ChatKind of the given name, such as a kind only sent by a
+    /// particular server build. Known names give their own variant, so kinds compare
+    /// equal however they were created; any other name is kept as is.
+    pub fn other(name: &str) -> Self {
+        name.into()
+    }
+
+    /// Get the name of the kind, as written in `chat` lines.
+    /// Unknown kinds give back the name they were created with.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::FreeChat => "FreeChat",
+            Self::MenuChat => "MenuChat",
+            Self::BuddyChat => "BuddyChat",
+            Self::BuddyMenuChat => "BuddyMenuChat",
+            Self::GroupChat => "GroupChat",
+            Self::GroupMenuChat => "GroupMenuChat",
+            Self::TradeChat => "TradeChat",
+            Self::Emote => "Emote",
+            Self::Shout => "Shout",
+            Self::System => "System",
+            Self::Unknown(name) => name,
+        }
+    }
+
+    /// Check if the kind is one this version knows.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Self::Unknown(_))
+    }
+}
+impl From<&str> for ChatKind {
+    fn from(s: &str) -> Self {
+        Self::KNOWN
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s))
+            .unwrap_or_else(|| Self::Unknown(s.to_string()))
+    }
+}
+impl FromStr for ChatKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(s.into())
+    }
+}
+impl Display for ChatKind {
+    /// Writes the name of the kind, so chats of unknown kinds format back into the
+    /// lines they were parsed from.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChatEvent {
+    pub kind: ChatKind,
+    pub from: String,
+    pub to: Option<String>,
+    pub message: String,
+    /// Fields sent after the message, as in `PlayerEvent::extra`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BroadcastScope {
+    Local,
+    Channel,
+    Shard,
+    Global,
+}
+impl TryFrom<usize> for BroadcastScope {
+    type Error = Error;
+
+    fn try_from(value: usize) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Local),
+            1 => Ok(Self::Channel),
+            2 => Ok(Self::Shard),
+            3 => Ok(Self::Global),
+            other => Err(format!("Unknown broadcast scope {}", other).into()),
+        }
+    }
+}
+impl From<BroadcastScope> for usize {
+    fn from(scope: BroadcastScope) -> usize {
+        match scope {
+            BroadcastScope::Local => 0,
+            BroadcastScope::Channel => 1,
+            BroadcastScope::Shard => 2,
+            BroadcastScope::Global => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum AnnouncementType {
+    /// Dialog box in the middle of the screen
+    Box,
+    /// Scrolling text along the top of the screen
+    Ticker,
+    Unknown(usize),
+}
+impl From<usize> for AnnouncementType {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Self::Box,
+            1 => Self::Ticker,
+            other => Self::Unknown(other),
+        }
+    }
+}
+impl From<AnnouncementType> for usize {
+    fn from(announcement_type: AnnouncementType) -> usize {
+        match announcement_type {
+            AnnouncementType::Box => 0,
+            AnnouncementType::Ticker => 1,
+            AnnouncementType::Unknown(other) => other,
+        }
+    }
+}
+impl Display for AnnouncementType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown(n) => write!(f, "{}*", n),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastEvent {
+    pub scope: BroadcastScope,
+    pub announcement_type: AnnouncementType,
+    pub duration_secs: usize,
+    pub from: String,
+    pub message: String,
+    /// Fields sent after the message, as in `PlayerEvent::extra`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmailEvent {
+    pub from: String,
+    pub to: String,
+    /// `None` for emails without a subject, which servers send as
+    /// `wire::NO_SUBJECT_IDENTIFIER` and which is formatted back as it.
+    /// `has_subject()` and `subject_text()` also skip a subject set to the placeholder.
+    pub subject: Option<String>,
+    /// Lines of the body, without the tab each line is framed with.
+    /// Any other whitespace is preserved as sent.
+    pub body: Vec<String>,
+    /// Taros attached to the email, if any.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub taros: Option<u64>,
+    /// Items attached to the email.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub items: Vec<EmailItem>,
+    /// Fields sent after the subject on the first line, as in `PlayerEvent::extra`.
+    /// Extra fields of attachment lines are ignored.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<String>,
+}
+
+/// An item attached to an email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmailItem {
+    /// The inventory slot type of the item, e.g. weapon or torso.
+    pub item_type: u32,
+    pub item_id: u32,
+    /// Stack size, for stackable items.
+    pub quantity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NameRequestEvent {
+    pub player_uid: u64,
+    pub requested_name: String,
+    /// Fields sent after the name, as in `PlayerEvent::extra`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<String>,
+}
+
+type CustomParser = dyn Fn(&str) -> Result<Arc<dyn Any + Send + Sync>> + Send + Sync;
+
+/// An event parsed by a parser registered with `ParserRegistry::register()`,
+/// e.g. through `MonitorConfig::register_parser()`.
+/// Two CustomEvents are considered equal if they were parsed from the same line.
+#[derive(Clone)]
+pub struct CustomEvent {
+    /// The first token of the line, which selected the parser.
+    pub token: String,
+    /// The line the event was parsed from.
+    pub line: String,
+    value: Arc<dyn Any + Send + Sync>,
+}
+impl CustomEvent {
+    /// Get the value produced by the parser, if it is of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+impl fmt::Debug for CustomEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CustomEvent")
+            .field("token", &self.token)
+            .field("line", &self.line)
+            .finish_non_exhaustive()
+    }
+}
+impl PartialEq for CustomEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.line == other.line
+    }
+}
+impl Eq for CustomEvent {}
+impl Hash for CustomEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.line.hash(state);
+    }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for CustomEvent {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct as _;
+        let mut state = serializer.serialize_struct("CustomEvent", 2)?;
+        state.serialize_field("token", &self.token)?;
+        state.serialize_field("line", &self.line)?;
+        state.end()
+    }
+}
+impl Display for CustomEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.line)
+    }
+}
+
+/// Parsers for custom event lines, selected by the first token of the line.
+/// Registered parsers take precedence over the built-in ones.
+#[derive(Clone, Default)]
+pub struct ParserRegistry {
+    parsers: HashMap<String, Arc<CustomParser>>,
+}
+impl ParserRegistry {
+    /// Create a ParserRegistry with no parsers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse lines whose first token is `token` with the given parser,
+    /// producing an `Event::Custom` holding the parsed value.
+    /// A parser already registered for the token is replaced.
+    pub fn register<T, F>(&mut self, token: &str, parser: F)
+    where
+        T: Any + Send + Sync,
+        F: Fn(&str) -> Result<T> + Send + Sync + 'static,
+    {
+        let parser: Arc<CustomParser> = Arc::new(move |line| {
+            let value: Arc<dyn Any + Send + Sync> = Arc::new(parser(line)?);
+            Ok(value)
+        });
+        self.parsers.insert(token.to_string(), parser);
+    }
+
+    /// Check if no parsers are registered.
+    pub fn is_empty(&self) -> bool {
+        self.parsers.is_empty()
+    }
+
+    fn parse(&self, token: &str, line: &str) -> Option<Result<CustomEvent>> {
+        let parser = self.parsers.get(token)?;
+        Some(parser(line).map(|value| CustomEvent {
+            token: token.to_string(),
+            line: line.to_string(),
+            value,
+        }))
+    }
+}
+impl fmt::Debug for ParserRegistry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_set().entries(self.parsers.keys()).finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Event {
+    Player(PlayerEvent),
+    Chat(ChatEvent),
+    Broadcast(BroadcastEvent),
+    Email(EmailEvent),
+    NameRequest(NameRequestEvent),
+    /// Serialized as its token and line. Can't be deserialized, since the parsed value is lost.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
+    Custom(CustomEvent),
+}
+impl Event {
+    /// Get the kind of the Event, without its contents.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Player(_) => EventKind::Player,
+            Self::Chat(_) => EventKind::Chat,
+            Self::Broadcast(_) => EventKind::Broadcast,
+            Self::Email(_) => EventKind::Email,
+            Self::NameRequest(_) => EventKind::NameRequest,
+            Self::Custom(_) => EventKind::Custom,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum EventKind {
+    Player,
+    Chat,
+    Broadcast,
+    Email,
+    NameRequest,
+    Custom,
+}
+impl EventKind {
+    /// Every kind of event, in the order they're documented.
+    pub const ALL: [EventKind; 6] = [
+        Self::Player,
+        Self::Chat,
+        Self::Broadcast,
+        Self::Email,
+        Self::NameRequest,
+        Self::Custom,
+    ];
+
+    /// Get the name of the kind in snake case, e.g. `name_request`, as in the `type` of
+    /// events serialized with the `serde` feature.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Player => "player",
+            Self::Chat => "chat",
+            Self::Broadcast => "broadcast",
+            Self::Email => "email",
+            Self::NameRequest => "name_request",
+            Self::Custom => "custom",
+        }
+    }
+}
+
+/// A command sent from the client to the server over the monitor connection.
+/// Commands are written as single lines, mirroring the format of the matching events.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Command {
+    /// approvename <player_uid>
+    ApproveName { player_uid: u64 },
+    /// rejectname <player_uid>
+    RejectName { player_uid: u64 },
+    /// bcast <scope> <announcement_type> <duration> <message...>
+    Broadcast {
+        scope: BroadcastScope,
+        announcement_type: AnnouncementType,
+        duration_secs: usize,
+        message: String,
+    },
+}
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::ApproveName { player_uid } => write!(f, "approvename {}", player_uid),
+            Self::RejectName { player_uid } => write!(f, "rejectname {}", player_uid),
+            Self::Broadcast {
+                scope,
+                announcement_type,
+                duration_secs,
+                message,
+            } => {
+                let scope_ord: usize = scope.clone().into();
+                let type_ord: usize = announcement_type.clone().into();
+                write!(
+                    f,
+                    "bcast {} {} {} {}",
+                    scope_ord, type_ord, duration_secs, message
+                )
+            }
+        }
+    }
+}
+
+/// An Event tagged with its position in the stream of events received by a Monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequencedEvent {
+    /// Sequence number of the MonitorUpdate the Event was part of.
+    pub frame_sequence: u64,
+    /// Sequence number of the Event itself, counting every event across all updates.
+    pub sequence: u64,
+    pub event: Event,
+}
+
+/// The number of events of each kind in a MonitorUpdate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventCounts {
+    pub players: usize,
+    pub chats: usize,
+    pub broadcasts: usize,
+    pub emails: usize,
+    pub name_requests: usize,
+    pub custom: usize,
+}
+impl EventCounts {
+    /// Get the number of events of every kind.
+    pub fn total(&self) -> usize {
+        self.players + self.chats + self.broadcasts + self.emails + self.name_requests + self.custom
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorUpdate {
+    events: Vec<Event>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    sequence: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    first_event_sequence: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    server_time: Option<SystemTime>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    received_at: Option<SystemTime>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    metadata: HashMap<String, String>,
+    /// Set when parsing a frame holding nothing but blank lines.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    heartbeat: bool,
+}
+impl MonitorUpdate {
+    /// Decompose the MonitorUpdate into a Vec of Events
+    pub fn get_events(self) -> Vec<Event> {
+        self.events
+    }
+
+    /// Get the Events of the MonitorUpdate without consuming it,
+    /// e.g. from the shared update of a `MonitorNotification::Updated`.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Decompose the MonitorUpdate into a Vec of Events tagged with sequence numbers.
+    pub fn get_sequenced_events(self) -> Vec<SequencedEvent> {
+        let frame_sequence = self.sequence;
+        let first_event_sequence = self.first_event_sequence;
+        self.events
+            .into_iter()
+            .zip(first_event_sequence..)
+            .map(|(event, sequence)| SequencedEvent {
+                frame_sequence,
+                sequence,
+                event,
+            })
+            .collect()
+    }
+
+    /// Get the sequence number of the MonitorUpdate.
+    /// A Monitor numbers the updates it delivers starting from 1, increasing by 1
+    /// for each update and continuing across reconnects.
+    /// Updates that were not received by a Monitor have sequence number 0.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Get the time the server sent the MonitorUpdate at, if the server timestamps its frames.
+    pub fn server_time(&self) -> Option<SystemTime> {
+        self.server_time
+    }
+
+    /// Get the time a Monitor received the MonitorUpdate at.
+    /// Updates that were not received by a Monitor have no receive time.
+    pub fn received_at(&self) -> Option<SystemTime> {
+        self.received_at
+    }
+
+    /// Get the values of the `meta <key> <value>` lines of the frame, by key, such as
+    /// server stats like uptime. Later lines win over earlier ones with the same key.
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// Get the number the server gave the frame in a `meta seq <n>` line, if any.
+    /// Unlike `sequence()`, it's kept across reconnects, so a frame sent again has the
+    /// same one.
+    pub fn server_sequence(&self) -> Option<u64> {
+        self.metadata.get(wire::SEQUENCE_METADATA_KEY)?.parse().ok()
+    }
+
+    /// Get the best known time of the MonitorUpdate:
+    /// the server's timestamp if it sent one, or else the time it was received.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.server_time.or(self.received_at)
+    }
+
+    /// Get the number of players online in the MonitorUpdate.
+    /// This value is equal to the number of PlayerEvents in the update.
+    pub fn get_player_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event, Event::Player(_)))
+            .count()
+    }
+
+    /// Count the events of each kind in the MonitorUpdate, in a single pass.
+    pub fn counts_by_kind(&self) -> EventCounts {
+        let mut counts = EventCounts::default();
+        for event in &self.events {
+            let count = match event {
+                Event::Player(_) => &mut counts.players,
+                Event::Chat(_) => &mut counts.chats,
+                Event::Broadcast(_) => &mut counts.broadcasts,
+                Event::Email(_) => &mut counts.emails,
+                Event::NameRequest(_) => &mut counts.name_requests,
+                Event::Custom(_) => &mut counts.custom,
+            };
+            *count += 1;
+        }
+        counts
+    }
+
+    /// Check if the MonitorUpdate has no events at all, not even players.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Check if the MonitorUpdate is a heartbeat: a frame with nothing between `begin`
+    /// and `end` but one or more blank lines, which some servers send to show the
+    /// connection is alive. Heartbeats don't list players, so they don't mean nobody is
+    /// online. A frame with nothing at all between `begin` and `end` isn't a heartbeat:
+    /// it's a server with nobody online.
+    pub fn is_heartbeat(&self) -> bool {
+        self.heartbeat
+            && self.events.is_empty()
+            && self.server_time.is_none()
+            && self.metadata.is_empty()
+    }
+
+    /// Merge a later MonitorUpdate into this one.
+    /// Players already present are moved to their new position instead of being duplicated.
+    pub fn coalesce_with(&mut self, later: MonitorUpdate) {
+        self.heartbeat &= later.heartbeat;
+        self.server_time = later.server_time;
+        self.received_at = later.received_at;
+        self.metadata.extend(later.metadata);
+        let mut players: HashMap<String, usize> = self
+            .events
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, event)| match event {
+                Event::Player(player) => Some((player.name.clone(), idx)),
+                _ => None,
+            })
+            .collect();
+        for event in later.events {
+            match event {
+                Event::Player(player) => match players.get(&player.name) {
+                    Some(&idx) => self.events[idx] = Event::Player(player),
+                    None => {
+                        players.insert(player.name.clone(), self.events.len());
+                        self.events.push(Event::Player(player));
+                    }
+                },
+                other => self.events.push(other),
+            }
+        }
+    }
+
+    /// Keep only the Events the predicate returns `true` for, in order.
+    pub fn retain_events(&mut self, keep: impl FnMut(&Event) -> bool) {
+        self.events.retain(keep);
+    }
+
+    /// Add an Event to the MonitorUpdate.
+    /// See `try_add_event()` to make sure the update can be formatted into a valid frame.
+    pub fn add_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Add an Event to the MonitorUpdate if it can be formatted into lines that parse
+    /// back to the same Event, as checked by `wire::validate_event()`.
+    pub fn try_add_event(&mut self, event: Event) -> std::result::Result<(), wire::FormatError> {
+        wire::validate_event(&event)?;
+        self.events.push(event);
+        Ok(())
+    }
+
+    /// Set the time the server sent the MonitorUpdate at.
+    pub fn set_server_time(&mut self, server_time: Option<SystemTime>) {
+        self.server_time = server_time;
+    }
+
+    /// Set a metadata value, sent as a `meta <key> <value>` line.
+    /// Keys can't hold whitespace, and neither keys nor values line breaks.
+    pub fn set_metadata(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> std::result::Result<(), wire::FormatError> {
+        if key.is_empty() {
+            return Err(wire::FormatError::EmptyField {
+                field: "metadata key",
+            });
+        }
+        if key.contains(char::is_whitespace) {
+            return Err(wire::FormatError::Whitespace {
+                field: "metadata key",
+            });
+        }
+        if value.contains(['\n', '\r']) {
+            return Err(wire::FormatError::LineBreak {
+                field: "metadata value",
+            });
+        }
+        self.metadata.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Set the time the MonitorUpdate was received at,
+    /// e.g. when replaying updates captured elsewhere.
+    pub fn set_received_at(&mut self, received_at: Option<SystemTime>) {
+        self.received_at = received_at;
+    }
+
+    /// Number the MonitorUpdate and its events, as a Monitor does when delivering it.
+    pub fn set_sequence(&mut self, sequence: u64, first_event_sequence: u64) {
+        self.sequence = sequence;
+        self.first_event_sequence = first_event_sequence;
+    }
+
+    /// Parse a MonitorUpdate from a vector of lines.
+    /// The lines are consumed and removed from the input vector.
+    pub fn from_lines(lines: &mut Vec<String>) -> Self {
+        let update = wire::parse_lines(lines, &ParserRegistry::default(), None, &mut Vec::new());
+        lines.clear();
+        update
+    }
+}
+#[cfg(feature = "chrono")]
+impl MonitorUpdate {
+    /// Get `server_time()` as a chrono DateTime.
+    pub fn server_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.server_time.map(Into::into)
+    }
+
+    /// Get `timestamp()` as a chrono DateTime.
+    pub fn datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.timestamp().map(Into::into)
+    }
+}
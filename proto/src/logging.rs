@@ -0,0 +1,6 @@
+//! The logging backend: `log` by default, or `tracing` with the tracing feature.
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use log::{debug, warn};
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, warn};
@@ -0,0 +1,104 @@
+//! Positions in the game world and conversions to map units.
+//!
+//! The OpenFusion world map is a square grid of `MAP_TILES` by `MAP_TILES` tiles,
+//! each `TILE_SIZE` units wide, with its origin at coordinates (0, 0).
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::PlayerEvent;
+
+/// Width of a map tile, in game units.
+pub const TILE_SIZE: i32 = 51200;
+/// Number of tiles along each side of the world map.
+pub const MAP_TILES: i32 = 16;
+/// Width of the whole world map, in game units.
+pub const MAP_SIZE: i32 = TILE_SIZE * MAP_TILES;
+
+/// A point in the game world, in raw game units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Get the straight-line distance to another Position, in game units.
+    pub fn distance_to(&self, other: &Position) -> f64 {
+        let dx = (self.x as i64 - other.x as i64) as f64;
+        let dy = (self.y as i64 - other.y as i64) as f64;
+        dx.hypot(dy)
+    }
+
+    /// Get the map tile the Position lies in.
+    /// Positions outside of the world map give tiles outside of `0..MAP_TILES`.
+    pub fn tile(&self) -> MapTile {
+        MapTile {
+            x: self.x.div_euclid(TILE_SIZE),
+            y: self.y.div_euclid(TILE_SIZE),
+        }
+    }
+
+    /// Get the Position relative to the whole world map, scaled to `0.0..=1.0` on both axes.
+    /// This is the coordinate space of web maps using a flat, non-geographic projection.
+    pub fn normalized(&self) -> (f64, f64) {
+        (
+            self.x as f64 / MAP_SIZE as f64,
+            self.y as f64 / MAP_SIZE as f64,
+        )
+    }
+
+    /// Get the Position matching normalized map coordinates, as returned by `normalized()`.
+    pub fn from_normalized(x: f64, y: f64) -> Self {
+        Self {
+            x: (x * MAP_SIZE as f64).round() as i32,
+            y: (y * MAP_SIZE as f64).round() as i32,
+        }
+    }
+}
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+/// A tile of the world map, counted from the map origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapTile {
+    pub x: i32,
+    pub y: i32,
+}
+impl MapTile {
+    /// Check if the tile is part of the world map.
+    pub fn is_on_map(&self) -> bool {
+        (0..MAP_TILES).contains(&self.x) && (0..MAP_TILES).contains(&self.y)
+    }
+
+    /// Get the Position of the corner of the tile closest to the map origin.
+    pub fn origin(&self) -> Position {
+        Position::new(
+            self.x.saturating_mul(TILE_SIZE),
+            self.y.saturating_mul(TILE_SIZE),
+        )
+    }
+
+    /// Get the Position of the center of the tile.
+    pub fn center(&self) -> Position {
+        let origin = self.origin();
+        Position::new(
+            origin.x.saturating_add(TILE_SIZE / 2),
+            origin.y.saturating_add(TILE_SIZE / 2),
+        )
+    }
+}
+
+impl PlayerEvent {
+    /// Get the position of the player.
+    pub fn position(&self) -> Position {
+        Position::new(self.x_coord, self.y_coord)
+    }
+}
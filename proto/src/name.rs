@@ -0,0 +1,170 @@
+//! Player names with OpenFusion's matching semantics.
+//!
+//! OpenFusion names are a first name and a last name separated by a space. The server
+//! treats names case-insensitively, so `PlayerName` compares, hashes and orders names
+//! ignoring case and runs of whitespace, while keeping the original spelling for display.
+
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+};
+
+use crate::{BroadcastEvent, ChatEvent, EmailEvent, Event, PlayerEvent};
+
+/// Trim a name and collapse every run of whitespace inside it into a single space.
+pub fn normalize_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check whether two names refer to the same player, ignoring case and whitespace.
+pub fn names_match(a: &str, b: &str) -> bool {
+    PlayerName::new(a) == PlayerName::new(b)
+}
+
+/// The name of a player, compared ignoring case and whitespace.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "String", into = "String")
+)]
+pub struct PlayerName {
+    name: String,
+    key: String,
+}
+impl PlayerName {
+    /// Create a PlayerName, normalizing its whitespace.
+    pub fn new(name: &str) -> Self {
+        let name = normalize_whitespace(name);
+        let key = name.to_lowercase();
+        Self { name, key }
+    }
+
+    /// Get the name as spelled by the server, with normalized whitespace.
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the first name, i.e. everything before the first space.
+    pub fn first_name(&self) -> &str {
+        self.split().0
+    }
+
+    /// Get the last name, i.e. everything after the first space.
+    /// Empty if the name is a single word.
+    pub fn last_name(&self) -> &str {
+        self.split().1
+    }
+
+    /// Get the first and last name.
+    pub fn split(&self) -> (&str, &str) {
+        self.name.split_once(' ').unwrap_or((&self.name, ""))
+    }
+
+    /// Check whether this is the name of the given player, ignoring case and whitespace.
+    pub fn matches(&self, name: &str) -> bool {
+        names_match(&self.name, name)
+    }
+}
+impl PartialEq for PlayerName {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+impl Eq for PlayerName {}
+impl Hash for PlayerName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+impl PartialOrd for PlayerName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for PlayerName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+impl Display for PlayerName {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+impl From<&str> for PlayerName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+impl From<String> for PlayerName {
+    fn from(name: String) -> Self {
+        Self::new(&name)
+    }
+}
+impl From<PlayerName> for String {
+    fn from(name: PlayerName) -> Self {
+        name.name
+    }
+}
+
+impl PlayerEvent {
+    /// Get the name of the player.
+    pub fn player_name(&self) -> PlayerName {
+        PlayerName::new(&self.name)
+    }
+}
+
+impl ChatEvent {
+    /// Get the name of the player who sent the chat.
+    pub fn sender(&self) -> PlayerName {
+        PlayerName::new(&self.from)
+    }
+
+    /// Get the name of the player the chat was directed at, if any.
+    pub fn recipient(&self) -> Option<PlayerName> {
+        self.to.as_deref().map(PlayerName::new)
+    }
+}
+
+impl BroadcastEvent {
+    /// Get the name of the player who sent the broadcast.
+    pub fn sender(&self) -> PlayerName {
+        PlayerName::new(&self.from)
+    }
+}
+
+impl EmailEvent {
+    /// Get the name of the player who sent the email.
+    pub fn sender(&self) -> PlayerName {
+        PlayerName::new(&self.from)
+    }
+
+    /// Get the name of the player the email was sent to.
+    pub fn recipient(&self) -> PlayerName {
+        PlayerName::new(&self.to)
+    }
+}
+
+impl Event {
+    /// Get the names of every player the event is about: the player of a player event,
+    /// and the sender and recipient of chats, broadcasts and emails.
+    pub fn player_names(&self) -> Vec<PlayerName> {
+        match self {
+            Self::Player(player) => vec![player.player_name()],
+            Self::Chat(chat) => std::iter::once(chat.sender())
+                .chain(chat.recipient())
+                .collect(),
+            Self::Broadcast(broadcast) => vec![broadcast.sender()],
+            Self::Email(email) => vec![email.sender(), email.recipient()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check whether the event is about the given player, ignoring case and whitespace.
+    pub fn involves(&self, name: &str) -> bool {
+        let name = PlayerName::new(name);
+        self.player_names().contains(&name)
+    }
+}
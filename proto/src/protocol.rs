@@ -0,0 +1,682 @@
+//! A machine-readable description of the monitor protocol, for implementations in
+//! other languages and for documentation.
+//!
+//! The tokens and enumerated values described by `spec()` are the ones the parsers in
+//! `wire` use. Of the patterns, only those of chat lines and email headers are compiled
+//! into a parser (see `wire::Tokenizer`); the other lines are parsed by hand, and the
+//! `wire` tests check those parsers against the patterns line by line. Frame keywords
+//! are matched leniently by the monitor by default, tolerating padded keywords (see
+//! `wire::is_keyword()`).
+//! Fields are named like the fields of the events they end up in, as serialized with the
+//! `serde` feature.
+//!
+//! So that servers can add fields to events without breaking older clients, event lines
+//! may end in extra fields, each after a tab (`EXTRA_SEPARATOR`). Parsers keep them in
+//! the `extra` field of the event, in order, and format them back.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    wire::{ProtocolFeature, ProtocolVersion},
+    AnnouncementType, BroadcastScope, ChatKind,
+};
+
+/// The tokens lines start with.
+pub mod token {
+    pub const BEGIN: &str = "begin";
+    pub const END: &str = "end";
+    pub const TIME: &str = "time";
+    pub const META: &str = "meta";
+    pub const VERSION: &str = "version";
+    pub const PLAYER: &str = "player";
+    pub const CHAT: &str = "chat";
+    pub const BROADCAST: &str = "bcast";
+    pub const EMAIL: &str = "email";
+    /// Email body lines start with a tab rather than a word.
+    pub const EMAIL_BODY: &str = "\t";
+    pub const TAROS: &str = "taros";
+    pub const ITEM: &str = "item";
+    pub const END_EMAIL: &str = "endemail";
+    pub const NAME_REQUEST: &str = "namereq";
+    pub const APPROVE_NAME: &str = "approvename";
+    pub const REJECT_NAME: &str = "rejectname";
+}
+
+/// The placeholder servers send as the subject of emails without one, as a macro so it
+/// can be spliced into descriptions.
+macro_rules! no_subject {
+    () => {
+        "No subject."
+    };
+}
+pub(crate) use no_subject;
+
+/// What each extra field at the end of an event line comes after.
+pub const EXTRA_SEPARATOR: &str = "\t";
+
+/// Where a line appears in the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineContext {
+    /// Sent by the server outside of frames.
+    Connection,
+    /// Part of the structure of a frame rather than an event.
+    Frame,
+    /// An event within a frame.
+    Event,
+    /// Within an email event, between its header and its `endemail` line.
+    Email,
+    /// Sent by the client.
+    Command,
+}
+impl Display for LineContext {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Connection => "Connection",
+            Self::Frame => "Frames",
+            Self::Event => "Events",
+            Self::Email => "Within emails",
+            Self::Command => "Commands",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A value an enumerated field may take.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnumValue {
+    /// The value as written in lines.
+    pub value: String,
+    /// The name of the value, e.g. the enum variant it's parsed as.
+    pub name: String,
+}
+
+/// The type of a field of a line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FieldType {
+    /// Decimal digits, preceded by an optional minus sign if `signed`.
+    Integer { signed: bool },
+    /// Hexadecimal digits.
+    Hex { digits: usize },
+    /// A run of characters without spaces.
+    Word,
+    /// Any text, ended by what follows the field in the syntax.
+    Text,
+    /// Any text, running to the end of the line.
+    Rest,
+    /// One of the listed values. If `open`, other values are accepted and kept as is.
+    Enum { values: Vec<EnumValue>, open: bool },
+}
+impl Display for FieldType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Integer { signed: true } => write!(f, "signed integer"),
+            Self::Integer { signed: false } => write!(f, "integer"),
+            Self::Hex { digits } => write!(f, "{} hex digits", digits),
+            Self::Word => write!(f, "word"),
+            Self::Text => write!(f, "text"),
+            Self::Rest => write!(f, "text to the end of the line"),
+            Self::Enum { values, open } => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| match value.value == value.name {
+                        true => format!("`{}`", value.value),
+                        false => format!("`{}` ({})", value.value, value.name),
+                    })
+                    .collect();
+                write!(f, "one of {}", values.join(", "))?;
+                if *open {
+                    write!(f, ", or any other")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A field of a line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldSpec {
+    pub name: String,
+    pub field_type: FieldType,
+    pub optional: bool,
+    pub description: String,
+}
+
+/// A kind of line of the protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineSpec {
+    /// The token the line starts with.
+    pub token: String,
+    pub context: LineContext,
+    /// The layout of the line, with fields between angle brackets, e.g.
+    /// `player <x> <y> <name...>`, and optional parts between square brackets.
+    pub syntax: String,
+    /// A regular expression every line that parses matches, with a capture group per
+    /// field, in order. Numbers also have to fit their fields, and enumerated fields that
+    /// aren't open have to hold one of their values.
+    pub pattern: String,
+    pub fields: Vec<FieldSpec>,
+    /// Whether the line may end in extra fields, each after an `EXTRA_SEPARATOR`.
+    /// The pattern is matched by what comes before them.
+    pub extra_fields: bool,
+    /// The first protocol version with the line.
+    pub since: ProtocolVersion,
+    pub description: String,
+}
+
+/// The monitor protocol, as returned by `spec()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolSpec {
+    /// The latest protocol version described.
+    pub version: ProtocolVersion,
+    /// Every kind of line, grouped by context.
+    pub lines: Vec<LineSpec>,
+}
+impl ProtocolSpec {
+    /// Get the lines with the given token, e.g. both `bcast` events and commands.
+    pub fn lines_for(&self, token: &str) -> Vec<&LineSpec> {
+        self.lines
+            .iter()
+            .filter(|line| line.token == token)
+            .collect()
+    }
+
+    /// Render the description as a Markdown document.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!(
+            "# Monitor protocol {}.{}\n",
+            self.version.major, self.version.minor
+        );
+        let mut context = None;
+        for line in &self.lines {
+            if context != Some(line.context) {
+                context = Some(line.context);
+                markdown.push_str(&format!("\n## {}\n", line.context));
+            }
+            markdown.push_str(&format!("\n### `{}`\n\n", line.syntax.replace('\t', "\\t")));
+            markdown.push_str(&format!(
+                "{} Since {}.{}.",
+                line.description, line.since.major, line.since.minor
+            ));
+            if line.extra_fields {
+                markdown.push_str(" May end in extra fields, each after a tab.");
+            }
+            markdown.push('\n');
+            if !line.fields.is_empty() {
+                markdown.push_str("\n| Field | Type | Description |\n|---|---|---|\n");
+                for field in &line.fields {
+                    let optional = match field.optional {
+                        true => " (optional)",
+                        false => "",
+                    };
+                    markdown.push_str(&format!(
+                        "| `{}`{} | {} | {} |\n",
+                        field.name, optional, field.field_type, field.description
+                    ));
+                }
+            }
+        }
+        markdown
+    }
+}
+
+enum Type {
+    Integer,
+    Signed,
+    Hex(usize),
+    Word,
+    Text,
+    Rest,
+    Enum(fn() -> Vec<EnumValue>, bool),
+}
+
+struct Field {
+    name: &'static str,
+    ty: Type,
+    optional: bool,
+    description: &'static str,
+}
+
+const fn field(name: &'static str, ty: Type, description: &'static str) -> Field {
+    Field {
+        name,
+        ty,
+        optional: false,
+        description,
+    }
+}
+
+const fn optional(name: &'static str, ty: Type, description: &'static str) -> Field {
+    Field {
+        name,
+        ty,
+        optional: true,
+        description,
+    }
+}
+
+pub(crate) struct Line {
+    token: &'static str,
+    context: LineContext,
+    syntax: &'static str,
+    pub(crate) pattern: &'static str,
+    fields: &'static [Field],
+    since: Option<ProtocolFeature>,
+    description: &'static str,
+}
+
+pub(crate) const BEGIN: Line = Line {
+    token: token::BEGIN,
+    context: LineContext::Frame,
+    syntax: "begin",
+    pattern: r"^begin$",
+    fields: &[],
+    since: None,
+    description: "Starts a frame. Some modified servers send it in uppercase or with \
+        surrounding whitespace.",
+};
+
+pub(crate) const TIME: Line = Line {
+    token: token::TIME,
+    context: LineContext::Frame,
+    syntax: "time <unix_millis>",
+    pattern: r"^time (\d+)$",
+    fields: &[field(
+        "unix_millis",
+        Type::Integer,
+        "When the server sent the frame, in milliseconds since the unix epoch.",
+    )],
+    since: Some(ProtocolFeature::FrameTime),
+    description: "Timestamps the frame it's in.",
+};
+
+pub(crate) const META: Line = Line {
+    token: token::META,
+    context: LineContext::Frame,
+    syntax: "meta <key> [<value...>]",
+    pattern: r"^meta ([^ ]+)(?: (.*))?$",
+    fields: &[
+        field("key", Type::Word, "The name of the stat."),
+        optional(
+            "value",
+            Type::Rest,
+            "The value of the stat, empty if left out.",
+        ),
+    ],
+    since: None,
+    description: "A stat about the server, such as its uptime.",
+};
+
+pub(crate) const END: Line = Line {
+    token: token::END,
+    context: LineContext::Frame,
+    syntax: "end [<line_count> [<checksum>]]",
+    pattern: r"^end(?: (\d+)(?: ([0-9a-fA-F]{8}))?)?$",
+    fields: &[
+        optional(
+            "line_count",
+            Type::Integer,
+            "The number of lines between `begin` and `end`.",
+        ),
+        optional(
+            "checksum",
+            Type::Hex(8),
+            "The CRC-32 of those lines, each followed by a `\\n`.",
+        ),
+    ],
+    since: None,
+    description: "Ends a frame. Recognized like `begin`.",
+};
+
+pub(crate) const VERSION: Line = Line {
+    token: token::VERSION,
+    context: LineContext::Connection,
+    syntax: "version <major>.<minor> [<build...>]",
+    pattern: r"^version (\d+)\.(\d+)(?: (.*))?$",
+    fields: &[
+        field("major", Type::Integer, "The major version of the protocol."),
+        field("minor", Type::Integer, "The minor version of the protocol."),
+        optional("build", Type::Rest, "A description of the server build."),
+    ],
+    since: None,
+    description: "Announces the version of the protocol the server speaks, usually right \
+        after connecting. Servers that don't are assumed to support everything.",
+};
+
+pub(crate) const PLAYER: Line = Line {
+    token: token::PLAYER,
+    context: LineContext::Event,
+    syntax: "player <x> <y> <name...>",
+    pattern: r"^player (-?\d+) (-?\d+) (.+)$",
+    fields: &[
+        field(
+            "x_coord",
+            Type::Signed,
+            "The x coordinate of the player in the world.",
+        ),
+        field(
+            "y_coord",
+            Type::Signed,
+            "The y coordinate of the player in the world.",
+        ),
+        field("name", Type::Rest, "The name of the player."),
+    ],
+    since: None,
+    description: "A player online, sent for every player in every frame.",
+};
+
+fn chat_kinds() -> Vec<EnumValue> {
+    ChatKind::KNOWN
+        .iter()
+        .map(|kind| EnumValue {
+            value: kind.as_str().to_string(),
+            name: kind.as_str().to_string(),
+        })
+        .collect()
+}
+
+pub(crate) const CHAT: Line = Line {
+    token: token::CHAT,
+    context: LineContext::Event,
+    syntax: "chat [<kind>] <from>[ (to <to>)]: <message...>",
+    pattern: r"^chat \[(.+?)\] (.+?)(?: \(to (.+)\))?: (.*)$",
+    fields: &[
+        field(
+            "kind",
+            Type::Enum(chat_kinds, true),
+            "The kind of chat, ignoring case.",
+        ),
+        field("from", Type::Text, "The name of the sender."),
+        optional(
+            "to",
+            Type::Text,
+            "The name of the recipient of directed chats.",
+        ),
+        field(
+            "message",
+            Type::Rest,
+            "The message. Since 2.1, line breaks and tabs are escaped as `\\n`, `\\r` \
+                and `\\t`, and backslashes starting an escape are doubled.",
+        ),
+    ],
+    since: None,
+    description: "A chat message.",
+};
+
+fn broadcast_scopes() -> Vec<EnumValue> {
+    (0..)
+        .map_while(|value| {
+            BroadcastScope::try_from(value)
+                .ok()
+                .map(|scope| (value, scope))
+        })
+        .map(|(value, scope)| EnumValue {
+            value: value.to_string(),
+            name: format!("{:?}", scope),
+        })
+        .collect()
+}
+
+fn announcement_types() -> Vec<EnumValue> {
+    (0..)
+        .map(|value| (value, AnnouncementType::from(value)))
+        .take_while(|(_, announcement_type)| {
+            !matches!(announcement_type, AnnouncementType::Unknown(_))
+        })
+        .map(|(value, announcement_type)| EnumValue {
+            value: value.to_string(),
+            name: format!("{:?}", announcement_type),
+        })
+        .collect()
+}
+
+pub(crate) const BROADCAST: Line = Line {
+    token: token::BROADCAST,
+    context: LineContext::Event,
+    syntax: "bcast <scope> <announcement_type> <duration> <from>: <message...>",
+    pattern: r"^bcast (\d+) (\d+) (\d+) (.+?): (.*)$",
+    fields: &[
+        field(
+            "scope",
+            Type::Enum(broadcast_scopes, false),
+            "Who the broadcast is shown to.",
+        ),
+        field(
+            "announcement_type",
+            Type::Enum(announcement_types, true),
+            "How the broadcast is shown.",
+        ),
+        field(
+            "duration_secs",
+            Type::Integer,
+            "How long the broadcast is shown for, in seconds.",
+        ),
+        field("from", Type::Text, "The name of the sender."),
+        field(
+            "message",
+            Type::Rest,
+            "The message. Since 2.1, line breaks and tabs are escaped as `\\n`, `\\r` \
+                and `\\t`, and backslashes starting an escape are doubled.",
+        ),
+    ],
+    since: None,
+    description: "An announcement sent by a GM.",
+};
+
+pub(crate) const EMAIL: Line = Line {
+    token: token::EMAIL,
+    context: LineContext::Event,
+    syntax: "email [Email] <from> (to <to>): <<subject>>",
+    pattern: r"^email \[Email\] (.+?) \(to (.+?)\): <(.+)>$",
+    fields: &[
+        field("from", Type::Text, "The name of the sender."),
+        field("to", Type::Text, "The name of the recipient."),
+        field(
+            "subject",
+            Type::Text,
+            concat!(
+                "The subject, or `",
+                no_subject!(),
+                "` for emails without one."
+            ),
+        ),
+    ],
+    since: None,
+    description: "Starts an email, followed by its body lines, its attachment lines, if \
+        any, and an `endemail` line.",
+};
+
+pub(crate) const EMAIL_BODY: Line = Line {
+    token: token::EMAIL_BODY,
+    context: LineContext::Email,
+    syntax: "\t<line...>",
+    pattern: r"^\t(.*)$",
+    fields: &[field(
+        "body",
+        Type::Rest,
+        "A line of the body. Whitespace after the tab is part of it.",
+    )],
+    since: None,
+    description: "A line of the body of an email.",
+};
+
+pub(crate) const TAROS: Line = Line {
+    token: token::TAROS,
+    context: LineContext::Email,
+    syntax: "taros <amount>",
+    pattern: r"^taros (\d+)$",
+    fields: &[field("taros", Type::Integer, "The taros attached.")],
+    since: Some(ProtocolFeature::EmailAttachments),
+    description: "Taros attached to an email, at most once per email.",
+};
+
+pub(crate) const ITEM: Line = Line {
+    token: token::ITEM,
+    context: LineContext::Email,
+    syntax: "item <type> <id> <quantity>",
+    pattern: r"^item (\d+) (\d+) (\d+)$",
+    fields: &[
+        field(
+            "item_type",
+            Type::Integer,
+            "The inventory slot type of the item.",
+        ),
+        field("item_id", Type::Integer, "The id of the item."),
+        field(
+            "quantity",
+            Type::Integer,
+            "How many of the item are attached.",
+        ),
+    ],
+    since: Some(ProtocolFeature::EmailAttachments),
+    description: "An item attached to an email.",
+};
+
+pub(crate) const END_EMAIL: Line = Line {
+    token: token::END_EMAIL,
+    context: LineContext::Email,
+    syntax: "endemail",
+    pattern: r"^endemail",
+    fields: &[],
+    since: None,
+    description: "Ends an email.",
+};
+
+pub(crate) const NAME_REQUEST: Line = Line {
+    token: token::NAME_REQUEST,
+    context: LineContext::Event,
+    syntax: "namereq <player_uid> <requested_name...>",
+    pattern: r"^namereq (\d+) (.+)$",
+    fields: &[
+        field("player_uid", Type::Integer, "The UID of the player."),
+        field("requested_name", Type::Rest, "The name requested."),
+    ],
+    since: None,
+    description: "A player asking for a name, awaiting approval.",
+};
+
+pub(crate) const APPROVE_NAME: Line = Line {
+    token: token::APPROVE_NAME,
+    context: LineContext::Command,
+    syntax: "approvename <player_uid>",
+    pattern: r"^approvename (\d+)$",
+    fields: &[field("player_uid", Type::Integer, "The UID of the player.")],
+    since: Some(ProtocolFeature::Commands),
+    description: "Approves the name requested by a player.",
+};
+
+pub(crate) const REJECT_NAME: Line = Line {
+    token: token::REJECT_NAME,
+    context: LineContext::Command,
+    syntax: "rejectname <player_uid>",
+    pattern: r"^rejectname (\d+)$",
+    fields: &[field("player_uid", Type::Integer, "The UID of the player.")],
+    since: Some(ProtocolFeature::Commands),
+    description: "Rejects the name requested by a player.",
+};
+
+pub(crate) const BROADCAST_COMMAND: Line = Line {
+    token: token::BROADCAST,
+    context: LineContext::Command,
+    syntax: "bcast <scope> <announcement_type> <duration> <message...>",
+    pattern: r"^bcast (\d+) (\d+) (\d+) (.*)$",
+    fields: &[
+        field(
+            "scope",
+            Type::Enum(broadcast_scopes, false),
+            "Who to show the broadcast to.",
+        ),
+        field(
+            "announcement_type",
+            Type::Enum(announcement_types, true),
+            "How to show the broadcast.",
+        ),
+        field(
+            "duration_secs",
+            Type::Integer,
+            "How long to show the broadcast for, in seconds.",
+        ),
+        field("message", Type::Rest, "The message."),
+    ],
+    since: Some(ProtocolFeature::Commands),
+    description: "Sends an announcement.",
+};
+
+const LINES: [&Line; 17] = [
+    &VERSION,
+    &BEGIN,
+    &TIME,
+    &META,
+    &END,
+    &PLAYER,
+    &CHAT,
+    &BROADCAST,
+    &EMAIL,
+    &NAME_REQUEST,
+    &EMAIL_BODY,
+    &TAROS,
+    &ITEM,
+    &END_EMAIL,
+    &APPROVE_NAME,
+    &REJECT_NAME,
+    &BROADCAST_COMMAND,
+];
+
+impl Line {
+    fn to_spec(&self) -> LineSpec {
+        let (major, minor) = self.since.map_or((1, 0), |feature| feature.since());
+        LineSpec {
+            token: self.token.to_string(),
+            context: self.context,
+            syntax: self.syntax.to_string(),
+            pattern: self.pattern.to_string(),
+            fields: self.fields.iter().map(Field::to_spec).collect(),
+            extra_fields: self.context == LineContext::Event,
+            since: ProtocolVersion::new(major, minor),
+            description: self.description.to_string(),
+        }
+    }
+}
+
+impl Field {
+    fn to_spec(&self) -> FieldSpec {
+        let field_type = match self.ty {
+            Type::Integer => FieldType::Integer { signed: false },
+            Type::Signed => FieldType::Integer { signed: true },
+            Type::Hex(digits) => FieldType::Hex { digits },
+            Type::Word => FieldType::Word,
+            Type::Text => FieldType::Text,
+            Type::Rest => FieldType::Rest,
+            Type::Enum(values, open) => FieldType::Enum {
+                values: values(),
+                open,
+            },
+        };
+        FieldSpec {
+            name: self.name.to_string(),
+            field_type,
+            optional: self.optional,
+            description: self.description.to_string(),
+        }
+    }
+}
+
+/// Get a description of every line of the monitor protocol.
+pub fn spec() -> ProtocolSpec {
+    let lines: Vec<LineSpec> = LINES.iter().map(|line| line.to_spec()).collect();
+    let version = ProtocolFeature::ALL
+        .iter()
+        .map(ProtocolFeature::since)
+        .max()
+        .unwrap_or((1, 0));
+    ProtocolSpec {
+        version: ProtocolVersion::new(version.0, version.1),
+        lines,
+    }
+}
@@ -0,0 +1,1870 @@
+//! Parsing and formatting of the monitor protocol, independent of any connection.
+//!
+//! A monitor frame is a `begin` line, one line per event, and an `end` line.
+//! Email events span several lines: a header, body lines each starting with a tab,
+//! attachment lines (`taros <amount>` and `item <type> <id> <quantity>`), if any,
+//! and an `endemail` line.
+//! Event lines may end in extra fields, each after a tab, which servers newer than this
+//! version may add; they're kept in the `extra` field of events (see
+//! `protocol::EXTRA_SEPARATOR`).
+//! Since protocol 2.1, chat and broadcast messages can span several lines, sent with
+//! their line breaks and tabs escaped as `\n`, `\r` and `\t` (see `escape_message()`).
+//! Servers that timestamp their frames send a `time <unix_millis>` line within the frame.
+//! Servers may also send `meta <key> <value>` lines within frames, with stats such as
+//! their uptime, available from `MonitorUpdate::metadata()`. Keys are single words,
+//! and values run to the end of the line.
+//!
+//! Servers may add integrity fields to `end` lines, so clients can tell frames that were
+//! cut short or corrupted in transit from intact ones: `end <line_count> <checksum>`,
+//! where `line_count` is the number of lines between `begin` and `end` in decimal,
+//! counting every line of multi-line events and the `time` line, and `checksum` is the
+//! CRC-32 of those lines in 8 hexadecimal digits, each line followed by a single `\n`
+//! (see `frame_checksum()`). The checksum may be left out, keeping `end <line_count>`.
+//! Clients not verifying frames ignore the fields. See `FrameTrailer`.
+//!
+//! Servers may announce the version of the protocol they speak with a
+//! `version <major>.<minor>` line, optionally followed by a build description, outside
+//! of any frame, usually right after connecting. See `ProtocolVersion` for what each
+//! version adds. Servers that don't announce a version are assumed to support everything.
+//!
+//! With the `json` feature, frames can also be encoded as single-line JSON objects
+//! holding the events in their serde representation, an optional timestamp, and the
+//! metadata, if any, as a `meta` object:
+//! ```text
+//! {"time":1718000000000,"events":[{"type":"player","x_coord":10,"y_coord":-20,"name":"Captain Courage"}]}
+//! ```
+
+#[cfg(feature = "regex")]
+use std::sync::LazyLock;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::{
+    logging::*,
+    protocol::{self, token},
+    BroadcastEvent, ChatEvent, EmailEvent, EmailItem, Event, EventKind, MonitorUpdate,
+    NameRequestEvent, ParserRegistry, PlayerEvent, Result, ViolationKind,
+};
+
+/// The placeholder servers send as the subject of emails without one.
+pub const NO_SUBJECT_IDENTIFIER: &str = protocol::no_subject!();
+
+/// The metadata key under which servers may number their frames, as `meta seq <n>`.
+/// See `MonitorUpdate::server_sequence()`.
+pub const SEQUENCE_METADATA_KEY: &str = "seq";
+
+/// How chat lines and email headers, the lines with free-form fields that can hold the
+/// delimiters around them, are split into fields. Both tokenizers split every line the
+/// same way, following the patterns of `protocol::CHAT` and `protocol::EMAIL`: the
+/// first field that can be split off is as short as it can be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Tokenizer {
+    /// With the patterns compiled by the regex crate.
+    /// The default with the `regex` feature.
+    #[cfg(feature = "regex")]
+    #[default]
+    Regex,
+    /// By hand, without the regex crate, for binaries that have to stay small.
+    /// The default without the `regex` feature.
+    #[cfg_attr(not(feature = "regex"), default)]
+    Strict,
+}
+
+/// Parse a single frame.
+/// The `begin` and `end` lines around the events are optional, and recognized
+/// regardless of case and surrounding whitespace.
+/// Malformed and unknown events are skipped.
+pub fn parse_frame(frame: &str) -> MonitorUpdate {
+    parse_frame_with(frame, Tokenizer::default())
+}
+
+/// Parse a single frame like `parse_frame()`, splitting chat lines and email headers
+/// with the given tokenizer.
+pub fn parse_frame_with(frame: &str, tokenizer: Tokenizer) -> MonitorUpdate {
+    let mut lines: Vec<&str> = frame.lines().collect();
+    if lines
+        .first()
+        .is_some_and(|line| is_keyword(line, token::BEGIN, true))
+    {
+        lines.remove(0);
+    }
+    if lines
+        .last()
+        .is_some_and(|line| FrameTrailer::parse(line, true).is_some())
+    {
+        lines.pop();
+    }
+    parse_lines_with(
+        &lines,
+        &ParserRegistry::default(),
+        None,
+        tokenizer,
+        &mut Vec::new(),
+        &mut ParseStats::default(),
+    )
+}
+
+/// A part of the protocol that not every server version supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ProtocolFeature {
+    /// `time <unix_millis>` lines in frames. Since 1.1.
+    FrameTime,
+    /// `taros` and `item` attachment lines in emails. Since 1.2.
+    EmailAttachments,
+    /// Commands sent by the client, see `Command`. Since 2.0.
+    Commands,
+    /// Line breaks and tabs in chat and broadcast messages, escaped as in
+    /// `escape_message()`. Since 2.1, and only assumed for servers announcing it.
+    EscapedMessages,
+}
+impl ProtocolFeature {
+    /// Every feature, in the order they were added.
+    pub const ALL: [ProtocolFeature; 4] = [
+        Self::FrameTime,
+        Self::EmailAttachments,
+        Self::Commands,
+        Self::EscapedMessages,
+    ];
+
+    /// Get the first protocol version supporting the feature, as `(major, minor)`.
+    pub fn since(&self) -> (u32, u32) {
+        match self {
+            Self::FrameTime => (1, 1),
+            Self::EmailAttachments => (1, 2),
+            Self::Commands => (2, 0),
+            Self::EscapedMessages => (2, 1),
+        }
+    }
+}
+
+/// The version of the monitor protocol announced by a server.
+///
+/// | Version | Adds |
+/// |---------|------|
+/// | 1.0     | `player`, `chat`, `bcast`, `email` and `namereq` events |
+/// | 1.1     | `time` lines |
+/// | 1.2     | email attachments |
+/// | 2.0     | client commands |
+/// | 2.1     | escaped line breaks and tabs in messages |
+///
+/// Lines of features the announced version doesn't support are treated like any other
+/// unknown line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// The rest of the version line, e.g. the name and build of the server.
+    pub build: Option<String>,
+}
+impl ProtocolVersion {
+    /// Create a ProtocolVersion with no build description.
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self {
+            major,
+            minor,
+            build: None,
+        }
+    }
+
+    /// Parse a `version <major>.<minor> [<build...>]` line.
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = strip_token(line, token::VERSION)?;
+        let (number, build) = match rest.split_once(' ') {
+            Some((number, build)) => (number, Some(build.trim().to_string())),
+            None => (rest, None),
+        };
+        let (major, minor) = number.split_once('.')?;
+        Some(Self {
+            major: parse_digits(major)?,
+            minor: parse_digits(minor)?,
+            build: build.filter(|build| !build.is_empty()),
+        })
+    }
+
+    /// Check whether servers speaking this version support the given feature.
+    pub fn supports(&self, feature: ProtocolFeature) -> bool {
+        (self.major, self.minor) >= feature.since()
+    }
+}
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "version {}.{}", self.major, self.minor)?;
+        if let Some(build) = &self.build {
+            write!(f, " {}", build)?;
+        }
+        Ok(())
+    }
+}
+
+/// The reason a line couldn't be parsed by `parse_line()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The line is empty or only holds whitespace.
+    EmptyLine,
+    /// The line doesn't start with the token of a known event. Holds the token.
+    UnknownEvent(String),
+    /// The line starts an email, which spans several lines and can only be parsed as
+    /// part of a frame.
+    MultiLineEvent,
+    /// The line doesn't follow the format of its event.
+    Malformed {
+        /// The token of the event, e.g. `player`.
+        token: &'static str,
+        /// What is wrong with the line.
+        reason: String,
+    },
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::EmptyLine => write!(f, "Empty line"),
+            Self::UnknownEvent(token) => write!(f, "Unknown event {}", token),
+            Self::MultiLineEvent => write!(f, "Email events span several lines"),
+            Self::Malformed { token, reason } => write!(f, "Bad {} event: {}", token, reason),
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+
+/// How many failed lines `ParseStats` keeps samples of.
+pub const MAX_FAILURE_SAMPLES: usize = 8;
+/// How many characters of a failed line its sample keeps.
+const SAMPLE_LENGTH: usize = 80;
+
+/// How the lines of one kind of event went, as counted by `ParseStats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineStats {
+    /// Lines parsed into events.
+    pub parsed: u64,
+    /// Lines dropped because they couldn't be parsed.
+    pub failed: u64,
+    /// Time spent parsing lines, only measured for chats and emails, the lines split up
+    /// by the `Tokenizer`. Always zero on targets without a clock, such as
+    /// `wasm32-unknown-unknown`.
+    pub tokenizer_time: Duration,
+}
+impl LineStats {
+    /// Get the average time spent parsing a line, if any was measured.
+    pub fn mean_tokenizer_time(&self) -> Option<Duration> {
+        let lines = u32::try_from(self.parsed + self.failed).ok()?;
+        match self.tokenizer_time.is_zero() || lines == 0 {
+            true => None,
+            false => Some(self.tokenizer_time / lines),
+        }
+    }
+}
+
+/// A line dropped by the parser, as kept by `ParseStats`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseFailure {
+    /// The kind of event the line is, or `None` if its token isn't known.
+    pub kind: Option<EventKind>,
+    /// Why the line was dropped.
+    pub reason: String,
+    /// The start of the line, with every letter after its token replaced with `x` so
+    /// names and messages aren't kept.
+    pub sample: String,
+    /// The length of the whole line, in bytes.
+    pub length: usize,
+}
+impl ParseFailure {
+    fn new(kind: Option<EventKind>, line: &str, reason: &str) -> Self {
+        let token_len = get_first_token(line).map_or(0, |token| {
+            line.find(token).unwrap_or_default() + token.len()
+        });
+        let (token, rest) = line.split_at(token_len);
+        let mut sample = token.chars().take(SAMPLE_LENGTH).collect::<String>();
+        let rest_len = SAMPLE_LENGTH.saturating_sub(sample.chars().count());
+        sample.extend(
+            rest.chars()
+                .take(rest_len)
+                .map(|c| match c.is_alphabetic() {
+                    true => 'x',
+                    false => c,
+                }),
+        );
+        if line[token_len..].chars().count() > rest_len {
+            sample.push_str("...");
+        }
+        Self {
+            kind,
+            reason: reason.to_string(),
+            sample,
+            length: line.len(),
+        }
+    }
+}
+
+/// Counts of the event lines parsed and dropped by kind, and samples of the last
+/// lines dropped, as collected by `parse_lines_with_stats()`. Displays as a short
+/// report, e.g. to find out why a kind of event never shows up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseStats {
+    kinds: HashMap<EventKind, LineStats>,
+    unknown: u64,
+    failures: Vec<ParseFailure>,
+}
+impl ParseStats {
+    /// Get the counts for one kind of event.
+    pub fn lines(&self, kind: EventKind) -> LineStats {
+        self.kinds.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// Get the number of lines dropped because their token isn't known.
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Get the number of lines parsed into events, of any kind.
+    pub fn total_parsed(&self) -> u64 {
+        self.kinds.values().map(|lines| lines.parsed).sum()
+    }
+
+    /// Get the number of lines dropped, unknown ones included.
+    pub fn total_failed(&self) -> u64 {
+        self.unknown + self.kinds.values().map(|lines| lines.failed).sum::<u64>()
+    }
+
+    /// Get the last lines dropped, at most `MAX_FAILURE_SAMPLES`, oldest first.
+    pub fn failures(&self) -> &[ParseFailure] {
+        &self.failures
+    }
+
+    /// Add the counts and samples of other stats to these, e.g. those of a frame.
+    pub fn merge(&mut self, other: ParseStats) {
+        for (kind, lines) in other.kinds {
+            let total = self.kinds.entry(kind).or_default();
+            total.parsed += lines.parsed;
+            total.failed += lines.failed;
+            total.tokenizer_time += lines.tokenizer_time;
+        }
+        self.unknown += other.unknown;
+        for failure in other.failures {
+            self.push_failure(failure);
+        }
+    }
+
+    /// Forget every count and sample.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn parsed(&mut self, kind: EventKind) {
+        self.kinds.entry(kind).or_default().parsed += 1;
+    }
+
+    fn failed(&mut self, kind: EventKind, line: &str, reason: &str) {
+        self.kinds.entry(kind).or_default().failed += 1;
+        self.push_failure(ParseFailure::new(Some(kind), line, reason));
+    }
+
+    fn unknown_line(&mut self, line: &str) {
+        self.unknown += 1;
+        self.push_failure(ParseFailure::new(None, line, "Unknown event"));
+    }
+
+    fn push_failure(&mut self, failure: ParseFailure) {
+        if self.failures.len() == MAX_FAILURE_SAMPLES {
+            self.failures.remove(0);
+        }
+        self.failures.push(failure);
+    }
+
+    /// Run the parser of a line, adding the time it took to its kind.
+    fn timed<T>(&mut self, kind: EventKind, parse: impl FnOnce() -> T) -> T {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        {
+            let start = std::time::Instant::now();
+            let result = parse();
+            self.kinds.entry(kind).or_default().tokenizer_time += start.elapsed();
+            result
+        }
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        {
+            let _ = kind;
+            parse()
+        }
+    }
+}
+impl Display for ParseStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} lines parsed, {} dropped",
+            self.total_parsed(),
+            self.total_failed()
+        )?;
+        for kind in EventKind::ALL {
+            let lines = self.lines(kind);
+            write!(
+                f,
+                "  {:?}: {} parsed, {} dropped",
+                kind, lines.parsed, lines.failed
+            )?;
+            if let Some(mean) = lines.mean_tokenizer_time() {
+                write!(f, ", {:?} per line", mean)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "  Unknown: {} dropped", self.unknown)?;
+        for failure in &self.failures {
+            let kind = failure
+                .kind
+                .map_or_else(|| "Unknown".to_string(), |kind| format!("{:?}", kind));
+            writeln!(
+                f,
+                "  {} ({}, {} bytes): {:?}",
+                kind, failure.reason, failure.length, failure.sample
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a single event line.
+/// Email events span several lines and can only be parsed as part of a frame.
+///
+/// This is a pure function of its input: it doesn't log, and it returns an error rather
+/// than panicking on any input, which makes it a good target for fuzzing.
+pub fn parse_line(line: &str) -> std::result::Result<Event, ParseError> {
+    parse_line_with(line, Tokenizer::default())
+}
+
+/// Parse a single event line like `parse_line()`, splitting chat lines with the given
+/// tokenizer.
+pub fn parse_line_with(line: &str, tokenizer: Tokenizer) -> std::result::Result<Event, ParseError> {
+    fn malformed(token: &'static str) -> impl Fn(crate::Error) -> ParseError {
+        move |err| ParseError::Malformed {
+            token,
+            reason: err.to_string(),
+        }
+    }
+    let event = match get_first_token(line) {
+        Some(token::PLAYER) => {
+            Event::Player(PlayerEvent::parse(line).map_err(malformed(token::PLAYER))?)
+        }
+        Some(token::CHAT) => {
+            Event::Chat(ChatEvent::parse(line, tokenizer, true).map_err(malformed(token::CHAT))?)
+        }
+        Some(token::BROADCAST) => Event::Broadcast(
+            BroadcastEvent::parse(line, true).map_err(malformed(token::BROADCAST))?,
+        ),
+        Some(token::NAME_REQUEST) => Event::NameRequest(
+            NameRequestEvent::parse(line).map_err(malformed(token::NAME_REQUEST))?,
+        ),
+        Some(token::EMAIL) => return Err(ParseError::MultiLineEvent),
+        Some(token) => return Err(ParseError::UnknownEvent(token.to_string())),
+        None => return Err(ParseError::EmptyLine),
+    };
+    Ok(event)
+}
+
+/// Parse a single event line.
+/// Email events span several lines and can only be parsed as part of a frame.
+pub fn parse_event_line(line: &str) -> Result<Event> {
+    Ok(parse_line(line)?)
+}
+
+#[cfg(feature = "json")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JsonFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time: Option<u64>,
+    events: Vec<Event>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    meta: HashMap<String, String>,
+}
+
+/// Parse a single JSON frame, as described in the module documentation.
+#[cfg(feature = "json")]
+pub fn parse_json_frame(line: &str) -> Result<MonitorUpdate> {
+    let frame: JsonFrame = serde_json::from_str(line)?;
+    let server_time = match frame.time {
+        Some(millis) => Some(
+            UNIX_EPOCH
+                .checked_add(Duration::from_millis(millis))
+                .ok_or("Frame time out of range")?,
+        ),
+        None => None,
+    };
+    Ok(MonitorUpdate {
+        events: frame.events,
+        metadata: frame.meta,
+        server_time,
+        ..Default::default()
+    })
+}
+
+/// Format a MonitorUpdate as a single-line JSON frame.
+#[cfg(feature = "json")]
+pub fn format_json_frame(update: &MonitorUpdate) -> String {
+    let frame = JsonFrame {
+        time: update.server_time.map(|time| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64
+        }),
+        events: update.events.clone(),
+        meta: update.metadata.clone(),
+    };
+    serde_json::to_string(&frame).expect("Events always serialize")
+}
+
+/// Format an Event the way the server sends it.
+/// Email events are formatted over several lines.
+pub fn format_event(event: &Event) -> String {
+    event.to_string()
+}
+
+/// Format a MonitorUpdate as a frame, including the `begin` and `end` lines.
+pub fn format_frame(update: &MonitorUpdate) -> String {
+    update.to_string()
+}
+
+/// The reason an Event can't be written to the wire as is, as returned by `validate_event()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FormatError {
+    /// A field holds a line break, which would split the event over several lines.
+    LineBreak { field: &'static str },
+    /// A field that can't be empty is.
+    EmptyField { field: &'static str },
+    /// A field that has to be a single word holds whitespace.
+    Whitespace { field: &'static str },
+    /// The sender of a message holds `: `, which the message is split off at.
+    SenderSeparator { field: &'static str },
+    /// The formatted event parses back to a different event, e.g. because a name
+    /// holds part of the format such as ` (to `.
+    Ambiguous,
+}
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::LineBreak { field } => write!(f, "{} holds a line break", field),
+            Self::EmptyField { field } => write!(f, "{} is empty", field),
+            Self::Whitespace { field } => write!(f, "{} holds whitespace", field),
+            Self::SenderSeparator { field } => write!(f, "{} holds \": \"", field),
+            Self::Ambiguous => write!(f, "Event doesn't parse back to itself"),
+        }
+    }
+}
+impl std::error::Error for FormatError {}
+
+/// Check that an Event can be formatted into lines that parse back to the same Event,
+/// e.g. before adding a synthetic event to a frame.
+/// Chat and broadcast messages may hold line breaks, since they're escaped.
+/// Custom events can only be checked for line breaks, since their parser isn't known.
+pub fn validate_event(event: &Event) -> std::result::Result<(), FormatError> {
+    fn single_line(field: &'static str, value: &str) -> std::result::Result<(), FormatError> {
+        match value.contains(['\n', '\r']) {
+            true => Err(FormatError::LineBreak { field }),
+            false => Ok(()),
+        }
+    }
+    fn non_empty(field: &'static str, value: &str) -> std::result::Result<(), FormatError> {
+        single_line(field, value)?;
+        match value.is_empty() {
+            true => Err(FormatError::EmptyField { field }),
+            false => Ok(()),
+        }
+    }
+    fn sender(field: &'static str, value: &str) -> std::result::Result<(), FormatError> {
+        non_empty(field, value)?;
+        match value.contains(": ") {
+            true => Err(FormatError::SenderSeparator { field }),
+            false => Ok(()),
+        }
+    }
+
+    match event {
+        Event::Player(player) => non_empty("player name", &player.name)?,
+        Event::Chat(chat) => {
+            non_empty("chat kind", &chat.kind.to_string())?;
+            sender("chat sender", &chat.from)?;
+            if let Some(to) = &chat.to {
+                non_empty("chat recipient", to)?;
+            }
+        }
+        Event::Broadcast(bcast) => sender("broadcast sender", &bcast.from)?,
+        Event::Email(email) => {
+            non_empty("email sender", &email.from)?;
+            non_empty("email recipient", &email.to)?;
+            if let Some(subject) = &email.subject {
+                non_empty("email subject", subject)?;
+            }
+            for line in &email.body {
+                single_line("email body", line)?;
+            }
+        }
+        Event::NameRequest(namereq) => non_empty("requested name", &namereq.requested_name)?,
+        Event::Custom(custom) => return single_line("custom event line", &custom.line),
+    }
+
+    let formatted = format_event(event);
+    let round_trips = match event {
+        Event::Email(_) => {
+            let lines: Vec<&str> = formatted.lines().collect();
+            let parsed = parse_lines(&lines, &ParserRegistry::default(), None, &mut Vec::new());
+            parsed.events() == [event.clone()]
+        }
+        _ => parse_line(&formatted).is_ok_and(|parsed| parsed == *event),
+    };
+    match round_trips {
+        true => Ok(()),
+        false => Err(FormatError::Ambiguous),
+    }
+}
+
+/// Hash bytes with 64-bit FNV-1a.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// An Event formatted as on the wire, but with messages as they are instead of escaped.
+struct CanonicalLine<'a>(&'a Event);
+impl Display for CanonicalLine<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Event::Chat(event) => event.write(f, false),
+            Event::Broadcast(event) => event.write(f, false),
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
+impl Event {
+    /// Get a stable hash of the Event, for use as a dedup key or database key.
+    ///
+    /// The fingerprint is the 64-bit FNV-1a hash of the UTF-8 bytes of the Event
+    /// formatted as it is sent on the wire (see `format_event()`), except that chat and
+    /// broadcast messages aren't escaped, with the lines of multi-line events joined by
+    /// `\n` and no trailing line break. A message gets the same fingerprint whether it
+    /// came from a server escaping messages or not, and other tools can compute it from
+    /// the wire line, after undoing `escape_message()` for servers speaking 2.1 or later.
+    ///
+    /// It only depends on the contents of the Event, so it is the same across processes
+    /// and platforms. It isn't promised to stay the same across versions of this crate,
+    /// since the way an event is formatted may change, so keys computed by different
+    /// versions shouldn't be mixed.
+    ///
+    /// Identical events get identical fingerprints: a player standing still has the same
+    /// fingerprint in every frame, and so does a chat message repeated word for word.
+    /// Note that the hash is unsigned; store it as its two's complement in databases
+    /// that only have signed 64-bit integers.
+    pub fn fingerprint(&self) -> u64 {
+        fnv1a_64(CanonicalLine(self).to_string().as_bytes())
+    }
+}
+
+/// Check whether a line is the given frame keyword, e.g. `begin`.
+/// If `lenient`, case and surrounding whitespace are ignored,
+/// since some modified servers send `BEGIN` or `end `.
+pub fn is_keyword(line: &str, keyword: &str, lenient: bool) -> bool {
+    match lenient {
+        true => line.trim().eq_ignore_ascii_case(keyword),
+        false => line == keyword,
+    }
+}
+
+/// The CRC-32 lookup table, for the IEEE polynomial in its reflected form.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+};
+
+/// Get the checksum of the lines of a frame, as sent in its `end` line: the CRC-32
+/// (as computed by zlib's `crc32()`) of the UTF-8 bytes of the lines between `begin`
+/// and `end`, each followed by a single `\n`.
+pub fn frame_checksum<S: AsRef<str>>(lines: &[S]) -> u32 {
+    let bytes = lines
+        .iter()
+        .flat_map(|line| line.as_ref().bytes().chain([b'\n']));
+    !bytes.fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The integrity fields of an `end` line, as described in the module documentation.
+/// Frames ended by a plain `end` line have neither.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameTrailer {
+    /// The number of lines between `begin` and `end`.
+    pub line_count: Option<usize>,
+    /// The checksum of those lines, see `frame_checksum()`.
+    pub checksum: Option<u32>,
+}
+impl FrameTrailer {
+    /// Get the trailer to end a frame of the given lines with, holding both fields.
+    pub fn for_lines<S: AsRef<str>>(lines: &[S]) -> Self {
+        Self {
+            line_count: Some(lines.len()),
+            checksum: Some(frame_checksum(lines)),
+        }
+    }
+
+    /// Parse an `end` line, with or without integrity fields.
+    /// Returns `None` if the line isn't an `end` line, or its fields are malformed.
+    /// If `lenient`, the keyword is recognized like `is_keyword()` does, and fields may
+    /// be separated by any whitespace.
+    pub fn parse(line: &str, lenient: bool) -> Option<Self> {
+        let mut fields: Vec<&str> = match lenient {
+            true => line.split_whitespace().collect(),
+            false => line.split(' ').collect(),
+        };
+        let keyword = fields.first()?;
+        let is_end = match lenient {
+            true => keyword.eq_ignore_ascii_case(token::END),
+            false => *keyword == token::END,
+        };
+        if !is_end || fields.len() > 3 {
+            return None;
+        }
+        fields.remove(0);
+        let line_count = match fields.first() {
+            Some(count) if count.bytes().all(|byte| byte.is_ascii_digit()) => {
+                Some(count.parse().ok()?)
+            }
+            Some(_) => return None,
+            None => None,
+        };
+        let checksum = match fields.get(1) {
+            Some(checksum)
+                if checksum.len() == 8 && checksum.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+            {
+                Some(u32::from_str_radix(checksum, 16).ok()?)
+            }
+            Some(_) => return None,
+            None => None,
+        };
+        Some(Self {
+            line_count,
+            checksum,
+        })
+    }
+
+    /// Check whether the trailer holds any integrity fields.
+    pub fn is_empty(&self) -> bool {
+        self.line_count.is_none() && self.checksum.is_none()
+    }
+
+    /// Check the lines of a frame against the fields of the trailer.
+    /// Frames without fields always pass.
+    pub fn verify<S: AsRef<str>>(&self, lines: &[S]) -> std::result::Result<(), IntegrityError> {
+        let line_count_matches = self.line_count.is_none_or(|count| count == lines.len());
+        let checksum = self.checksum.map(|_| frame_checksum(lines));
+        match line_count_matches && checksum == self.checksum {
+            true => Ok(()),
+            false => Err(IntegrityError {
+                expected: *self,
+                actual: Self {
+                    line_count: self.line_count.map(|_| lines.len()),
+                    checksum,
+                },
+            }),
+        }
+    }
+}
+impl Display for FrameTrailer {
+    /// Formats the trailer as the `end` line holding it.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "end")?;
+        // the checksum is positional, so it needs a line count before it
+        if let Some(count) = self.line_count {
+            write!(f, " {}", count)?;
+            if let Some(checksum) = self.checksum {
+                write!(f, " {:08x}", checksum)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A frame that doesn't match the integrity fields of its `end` line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IntegrityError {
+    /// The fields announced in the `end` line.
+    pub expected: FrameTrailer,
+    /// The fields computed from the lines received, for the fields announced.
+    pub actual: FrameTrailer,
+}
+impl Display for IntegrityError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Frame failed verification: expected \"{}\", got \"{}\"",
+            self.expected, self.actual
+        )
+    }
+}
+impl std::error::Error for IntegrityError {}
+
+/// Format a MonitorUpdate as a frame like `format_frame()`, ending it with an `end` line
+/// with both integrity fields.
+pub fn format_checked_frame(update: &MonitorUpdate) -> String {
+    let frame = update.to_string();
+    let lines: Vec<&str> = frame.lines().collect();
+    // every line but begin and end
+    let trailer = FrameTrailer::for_lines(&lines[1..lines.len() - 1]);
+    let mut checked = lines[..lines.len() - 1].join("\n");
+    checked.push('\n');
+    checked.push_str(&trailer.to_string());
+    checked.push('\n');
+    checked
+}
+
+fn get_first_token(line: &str) -> Option<&str> {
+    line.split_whitespace().next()
+}
+
+/// Strip the token of a line and the space after it.
+fn strip_token<'a>(line: &'a str, token: &str) -> Option<&'a str> {
+    line.strip_prefix(token)?.strip_prefix(' ')
+}
+
+/// Split the extra fields at the end of an event line off it.
+fn split_extra(line: &str) -> (&str, Vec<String>) {
+    match line.split_once(protocol::EXTRA_SEPARATOR) {
+        Some((line, extra)) => (
+            line,
+            extra
+                .split(protocol::EXTRA_SEPARATOR)
+                .map(str::to_string)
+                .collect(),
+        ),
+        None => (line, Vec::new()),
+    }
+}
+
+/// Escape the line breaks and tabs of a chat or broadcast message, so it fits on its
+/// line: `\n`, `\r` and `\t` stand for them, and backslashes that would be read as
+/// part of an escape, before `n`, `r`, `t`, another backslash or an escaped character,
+/// are doubled. Other backslashes are kept as they are, so messages without line breaks
+/// usually come out unchanged.
+pub fn escape_message(message: &str) -> Cow<'_, str> {
+    if !message.contains(['\\', '\n', '\r', '\t']) {
+        return Cow::Borrowed(message);
+    }
+    let mut escaped = String::with_capacity(message.len() + 2);
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' if matches!(
+                chars.peek(),
+                Some('\\' | 'n' | 'r' | 't' | '\n' | '\r' | '\t')
+            ) =>
+            {
+                escaped.push_str("\\\\")
+            }
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Undo `escape_message()`. Backslashes not starting an escape are kept as they are.
+pub fn unescape_message(message: &str) -> Cow<'_, str> {
+    if !message.contains('\\') {
+        return Cow::Borrowed(message);
+    }
+    let mut unescaped = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some('t')) => '\t',
+            ('\\', Some('\\')) => '\\',
+            (c, _) => {
+                unescaped.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        unescaped.push(escaped);
+    }
+    Cow::Owned(unescaped)
+}
+
+/// Write the extra fields of an event back after its line.
+fn write_extra(f: &mut Formatter, extra: &[String]) -> fmt::Result {
+    for field in extra {
+        write!(f, "{}{}", protocol::EXTRA_SEPARATOR, field)?;
+    }
+    Ok(())
+}
+
+// The fixed-shape events (players, which make up most of every frame, broadcasts and
+// name requests) are split by hand rather than matched with a regex, to keep up with
+// frames of tens of thousands of lines. The results are the same as the patterns
+// of their lines in `protocol`.
+
+/// Split off the next space-separated field, which has to be a non-empty run of
+/// ASCII digits, preceded by an optional minus sign if `signed`.
+/// Parse a field of decimal digits, without the sign `str::parse()` allows.
+fn parse_digits<T: FromStr>(field: &str) -> Option<T> {
+    match !field.is_empty() && field.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => field.parse().ok(),
+        false => None,
+    }
+}
+
+fn next_number(rest: &str, signed: bool) -> Option<(&str, &str)> {
+    let (field, rest) = rest.split_once(' ')?;
+    let digits = match signed {
+        true => field.strip_prefix('-').unwrap_or(field),
+        false => field,
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((field, rest))
+}
+
+/// Split `<from>: <message...>` at the first `: ` that leaves a non-empty sender.
+fn split_sender(rest: &str) -> Option<(&str, &str)> {
+    let first_len = rest.chars().next()?.len_utf8();
+    let idx = first_len + rest[first_len..].find(": ")?;
+    Some((&rest[..idx], &rest[idx + 2..]))
+}
+
+// The strict tokenizer follows the patterns of `protocol::CHAT` and `protocol::EMAIL`
+// as the regex crate matches them: lazy fields take the first split that lets the rest
+// of the line match, greedy ones the last, and no field holds a line break.
+
+/// Split `chat [<kind>] <from>[ (to <to>)]: <message...>` into its fields.
+fn split_chat(line: &str) -> Option<(&str, &str, Option<&str>, &str)> {
+    if line.contains('\n') {
+        return None;
+    }
+    let rest = line.strip_prefix("chat [")?;
+    rest.match_indices("] ")
+        .filter(|(idx, _)| *idx > 0)
+        .find_map(|(idx, _)| {
+            let (from, to, message) = split_chat_sender(&rest[idx + 2..])?;
+            Some((&rest[..idx], from, to, message))
+        })
+}
+
+/// Split `<from>[ (to <to>)]: <message...>`, trying each end of the sender in turn,
+/// with a recipient before without one.
+fn split_chat_sender(rest: &str) -> Option<(&str, Option<&str>, &str)> {
+    const TO: &str = " (to ";
+    // the recipient runs to the last `): ` it can
+    let last_close = rest.rfind("): ");
+    let first_len = rest.chars().next()?.len_utf8();
+    rest.char_indices()
+        .skip_while(|(idx, _)| *idx < first_len)
+        .find_map(|(idx, _)| {
+            let tail = &rest[idx..];
+            if tail.starts_with(TO) {
+                let to_start = idx + TO.len();
+                if let Some(close) = last_close.filter(|close| *close > to_start) {
+                    return Some((
+                        &rest[..idx],
+                        Some(&rest[to_start..close]),
+                        &rest[close + 3..],
+                    ));
+                }
+            }
+            let message = tail.strip_prefix(": ")?;
+            Some((&rest[..idx], None, message))
+        })
+}
+
+/// Split `email [Email] <from> (to <to>): <<subject>>` into its fields.
+fn split_email_header(header: &str) -> Option<(&str, &str, &str)> {
+    if header.contains('\n') {
+        return None;
+    }
+    let rest = header.strip_prefix("email [Email] ")?;
+    // the subject runs to the `>` ending the line
+    let subject_end = rest.len().checked_sub(1).filter(|_| rest.ends_with('>'))?;
+    // ` (to ` can overlap itself, so every position is tried
+    rest.char_indices()
+        .skip(1)
+        .filter(|(idx, _)| rest[*idx..].starts_with(" (to "))
+        .find_map(|(idx, _)| {
+            let to_start = idx + " (to ".len();
+            let to_len = rest[to_start..].chars().next()?.len_utf8();
+            let close = to_start + to_len + rest[to_start + to_len..].find("): <")?;
+            let subject_start = close + 4;
+            match subject_start < subject_end {
+                true => Some((
+                    &rest[..idx],
+                    &rest[to_start..close],
+                    &rest[subject_start..subject_end],
+                )),
+                false => None,
+            }
+        })
+}
+
+/// Parse a MonitorUpdate from the lines of a frame,
+/// collecting structural problems found along the way.
+/// Lines are borrowed, so only the parts kept in events are copied.
+/// Without a version, every feature of the protocol is recognized but escaped messages,
+/// which are only unescaped for servers announcing protocol 2.1 or later.
+pub fn parse_lines<S: AsRef<str>>(
+    lines: &[S],
+    parsers: &ParserRegistry,
+    version: Option<&ProtocolVersion>,
+    violations: &mut Vec<(ViolationKind, String)>,
+) -> MonitorUpdate {
+    let mut stats = ParseStats::default();
+    parse_lines_with(
+        lines,
+        parsers,
+        version,
+        Tokenizer::default(),
+        violations,
+        &mut stats,
+    )
+}
+
+/// Parse a MonitorUpdate from the lines of a frame like `parse_lines()`, also counting
+/// the lines parsed and dropped in `stats`.
+pub fn parse_lines_with_stats<S: AsRef<str>>(
+    lines: &[S],
+    parsers: &ParserRegistry,
+    version: Option<&ProtocolVersion>,
+    violations: &mut Vec<(ViolationKind, String)>,
+    stats: &mut ParseStats,
+) -> MonitorUpdate {
+    parse_lines_with(
+        lines,
+        parsers,
+        version,
+        Tokenizer::default(),
+        violations,
+        stats,
+    )
+}
+
+fn parse_lines_with<S: AsRef<str>>(
+    lines: &[S],
+    parsers: &ParserRegistry,
+    version: Option<&ProtocolVersion>,
+    tokenizer: Tokenizer,
+    violations: &mut Vec<(ViolationKind, String)>,
+    stats: &mut ParseStats,
+) -> MonitorUpdate {
+    let supports = |feature| version.is_none_or(|version| version.supports(feature));
+    let frame_time = supports(ProtocolFeature::FrameTime);
+    let email_attachments = supports(ProtocolFeature::EmailAttachments);
+    // servers that don't announce a version send backslashes as they are
+    let escapes = version.is_some_and(|version| version.supports(ProtocolFeature::EscapedMessages));
+    let mut events = Vec::with_capacity(lines.len());
+    let mut server_time = None;
+    let mut metadata = HashMap::new();
+    // an empty frame is a server with nobody online, not a heartbeat
+    let heartbeat = !lines.is_empty() && lines.iter().all(|line| line.as_ref().trim().is_empty());
+    let mut idx = 0;
+    while idx < lines.len() {
+        let first_line = lines[idx].as_ref();
+        idx += 1;
+        let token = get_first_token(first_line);
+        let custom = match token {
+            Some(token) if !parsers.is_empty() => parsers.parse(token, first_line),
+            _ => None,
+        };
+        if let Some(result) = custom {
+            match result {
+                Ok(event) => {
+                    stats.parsed(EventKind::Custom);
+                    events.push(Event::Custom(event));
+                }
+                Err(err) => {
+                    warn!("Bad custom event ({}): {}", err, first_line);
+                    stats.failed(EventKind::Custom, first_line, &err.to_string());
+                }
+            }
+            continue;
+        }
+        let event = match token {
+            Some(token::PLAYER) => match PlayerEvent::parse(first_line) {
+                Ok(event) => Event::Player(event),
+                Err(err) => {
+                    warn!("Bad player event ({}): {}", err, first_line);
+                    stats.failed(EventKind::Player, first_line, &err.to_string());
+                    continue;
+                }
+            },
+            Some(token::CHAT) => {
+                match stats.timed(EventKind::Chat, || {
+                    ChatEvent::parse(first_line, tokenizer, escapes)
+                }) {
+                    Ok(event) => Event::Chat(event),
+                    Err(err) => {
+                        warn!("Bad chat event ({}): {}", err, first_line);
+                        stats.failed(EventKind::Chat, first_line, &err.to_string());
+                        continue;
+                    }
+                }
+            }
+            Some(token::BROADCAST) => match BroadcastEvent::parse(first_line, escapes) {
+                Ok(event) => Event::Broadcast(event),
+                Err(err) => {
+                    warn!("Bad broadcast event ({}): {}", err, first_line);
+                    stats.failed(EventKind::Broadcast, first_line, &err.to_string());
+                    continue;
+                }
+            },
+            Some(token::EMAIL) => {
+                // next lines with tabs at the beginning are part of the email body;
+                // only the framing tab is stripped, so indentation within the body survives
+                let body_start = idx;
+                while idx < lines.len() && lines[idx].as_ref().starts_with(token::EMAIL_BODY) {
+                    idx += 1;
+                }
+                let body = &lines[body_start..idx];
+                let attachments_start = idx;
+                while idx < lines.len() && email_attachments && is_attachment(lines[idx].as_ref()) {
+                    idx += 1;
+                }
+                let attachments = &lines[attachments_start..idx];
+                if idx == lines.len() || !lines[idx].as_ref().starts_with(token::END_EMAIL) {
+                    warn!("Malformed email event (no endemail)");
+                    violations.push((ViolationKind::UnterminatedEmail, first_line.to_string()));
+                    stats.failed(EventKind::Email, first_line, "No endemail");
+                    continue;
+                }
+                idx += 1; // skip endemail
+                let body = body
+                    .iter()
+                    .map(|line| {
+                        line.as_ref()
+                            .strip_prefix(token::EMAIL_BODY)
+                            .unwrap_or_default()
+                            .to_string()
+                    })
+                    .collect();
+                match stats.timed(EventKind::Email, || {
+                    EmailEvent::parse(first_line, body, attachments, tokenizer)
+                }) {
+                    Ok(event) => Event::Email(event),
+                    Err(err) => {
+                        warn!("Bad email event ({}): {}", err, first_line);
+                        stats.failed(EventKind::Email, first_line, &err.to_string());
+                        continue;
+                    }
+                }
+            }
+            Some(token::NAME_REQUEST) => match NameRequestEvent::parse(first_line) {
+                Ok(event) => Event::NameRequest(event),
+                Err(err) => {
+                    warn!("Bad name request event ({}): {}", err, first_line);
+                    stats.failed(EventKind::NameRequest, first_line, &err.to_string());
+                    continue;
+                }
+            },
+            Some(token::TIME) if frame_time => {
+                match parse_time(first_line) {
+                    Ok(time) => server_time = Some(time),
+                    Err(err) => warn!("Bad frame time ({}): {}", err, first_line),
+                }
+                continue;
+            }
+            Some(token::META) => {
+                match parse_meta(first_line) {
+                    Some((key, value)) => {
+                        metadata.insert(key.to_string(), value.to_string());
+                    }
+                    None => warn!("Bad metadata line: {}", first_line),
+                }
+                continue;
+            }
+            Some(token::END_EMAIL) => {
+                warn!("Stray endemail in monitor update");
+                violations.push((ViolationKind::StrayEndEmail, first_line.to_string()));
+                continue;
+            }
+            Some(_) => {
+                warn!("Unknown event: {}", first_line);
+                stats.unknown_line(first_line);
+                continue;
+            }
+            None => {
+                // blank lines are harmless, and fill heartbeat frames on some servers
+                debug!("Empty line in monitor update");
+                continue;
+            }
+        };
+        stats.parsed(event.kind());
+        events.push(event);
+    }
+    MonitorUpdate {
+        events,
+        server_time,
+        metadata,
+        heartbeat,
+        ..Default::default()
+    }
+}
+
+fn parse_meta(line: &str) -> Option<(&str, &str)> {
+    // meta <key> [<value...>]
+    let rest = strip_token(line, token::META)?;
+    let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+    match key.is_empty() {
+        true => None,
+        false => Some((key, value)),
+    }
+}
+
+fn parse_time(line: &str) -> Result<SystemTime> {
+    // time <unix_millis>
+    let millis = strip_token(line, token::TIME).ok_or("Malformed")?;
+    let millis = parse_digits(millis).ok_or("Malformed")?;
+    // SystemTime is narrower than u64 millis on some platforms
+    Ok(UNIX_EPOCH
+        .checked_add(Duration::from_millis(millis))
+        .ok_or("Time out of range")?)
+}
+
+impl PlayerEvent {
+    fn parse(line: &str) -> Result<Self> {
+        // player <x> <y> <name...>
+        let (line, extra) = split_extra(line);
+        let rest = strip_token(line, token::PLAYER).ok_or("Malformed")?;
+        let (x, rest) = next_number(rest, true).ok_or("Malformed")?;
+        let (y, name) = next_number(rest, true).ok_or("Malformed")?;
+        if name.is_empty() {
+            return Err("Malformed".into());
+        }
+        let x_coord = x.parse().map_err(|_| "Invalid x coordinate")?;
+        let y_coord = y.parse().map_err(|_| "Invalid y coordinate")?;
+        let name = name.to_string();
+        Ok(Self {
+            x_coord,
+            y_coord,
+            name,
+            extra,
+        })
+    }
+}
+impl Display for PlayerEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "player {} {} {}", self.x_coord, self.y_coord, self.name)?;
+        write_extra(f, &self.extra)
+    }
+}
+
+impl ChatEvent {
+    fn parse(line: &str, tokenizer: Tokenizer, escapes: bool) -> Result<Self> {
+        // chat [<kind>] <from>: <message...>
+        // chat [<kind>] <from> (to <to>): <message...>
+        let (line, extra) = split_extra(line);
+        let (kind, from, to, message) = match tokenizer {
+            #[cfg(feature = "regex")]
+            Tokenizer::Regex => {
+                static REGEX: LazyLock<Regex> =
+                    LazyLock::new(|| Regex::new(protocol::CHAT.pattern).unwrap());
+
+                let captures = REGEX.captures(line).ok_or("Malformed")?;
+                let group = |idx| captures.get(idx).map(|m| m.as_str()).ok_or("Malformed");
+                (group(1)?, group(2)?, group(3).ok(), group(4)?)
+            }
+            Tokenizer::Strict => split_chat(line).ok_or("Malformed")?,
+        };
+        let kind = kind.parse()?;
+        let from = from.to_string();
+        let to = to.map(str::to_string);
+        let message = match escapes {
+            true => unescape_message(message).into_owned(),
+            false => message.to_string(),
+        };
+        Ok(Self {
+            kind,
+            from,
+            to,
+            message,
+            extra,
+        })
+    }
+}
+impl ChatEvent {
+    fn write(&self, f: &mut Formatter, escape: bool) -> fmt::Result {
+        let message = match escape {
+            true => escape_message(&self.message),
+            false => Cow::Borrowed(self.message.as_str()),
+        };
+        write!(
+            f,
+            "chat [{}] {}{}: {}",
+            self.kind,
+            self.from,
+            self.to
+                .as_ref()
+                .map(|to| format!(" (to {})", to))
+                .unwrap_or_default(),
+            message
+        )?;
+        write_extra(f, &self.extra)
+    }
+}
+impl Display for ChatEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write(f, true)
+    }
+}
+
+impl BroadcastEvent {
+    fn parse(line: &str, escapes: bool) -> Result<Self> {
+        // bcast <scope> <announcement_type> <duration> <from>: <message...>
+        let (line, extra) = split_extra(line);
+        let rest = strip_token(line, token::BROADCAST).ok_or("Malformed")?;
+        let (scope, rest) = next_number(rest, false).ok_or("Malformed")?;
+        let (announcement_type, rest) = next_number(rest, false).ok_or("Malformed")?;
+        let (duration_secs, rest) = next_number(rest, false).ok_or("Malformed")?;
+        let (from, message) = split_sender(rest).ok_or("Malformed")?;
+        let scope = scope.parse::<usize>()?.try_into()?;
+        let announcement_type = announcement_type.parse::<usize>()?.into();
+        let duration_secs = duration_secs.parse()?;
+        let from = from.to_string();
+        let message = match escapes {
+            true => unescape_message(message).into_owned(),
+            false => message.to_string(),
+        };
+        Ok(Self {
+            scope,
+            announcement_type,
+            duration_secs,
+            from,
+            message,
+            extra,
+        })
+    }
+}
+impl BroadcastEvent {
+    fn write(&self, f: &mut Formatter, escape: bool) -> fmt::Result {
+        let scope_ord: usize = self.scope.clone().into();
+        let type_ord: usize = self.announcement_type.clone().into();
+        let message = match escape {
+            true => escape_message(&self.message),
+            false => Cow::Borrowed(self.message.as_str()),
+        };
+        write!(
+            f,
+            "bcast {} {} {} {}: {}",
+            scope_ord, type_ord, self.duration_secs, self.from, message
+        )?;
+        write_extra(f, &self.extra)
+    }
+}
+impl Display for BroadcastEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write(f, true)
+    }
+}
+
+fn is_attachment(line: &str) -> bool {
+    strip_token(line, token::TAROS).is_some() || strip_token(line, token::ITEM).is_some()
+}
+
+impl EmailEvent {
+    fn parse<S: AsRef<str>>(
+        header: &str,
+        body: Vec<String>,
+        attachments: &[S],
+        tokenizer: Tokenizer,
+    ) -> Result<Self> {
+        // email [Email] <from> (to <to>): <<subject>>
+        let (header, extra) = split_extra(header);
+        let (from, to, subject) = match tokenizer {
+            #[cfg(feature = "regex")]
+            Tokenizer::Regex => {
+                static REGEX: LazyLock<Regex> =
+                    LazyLock::new(|| Regex::new(protocol::EMAIL.pattern).unwrap());
+
+                let captures = REGEX.captures(header).ok_or("Malformed")?;
+                let group = |idx| captures.get(idx).map(|m| m.as_str()).ok_or("Malformed");
+                (group(1)?, group(2)?, group(3)?)
+            }
+            Tokenizer::Strict => split_email_header(header).ok_or("Malformed")?,
+        };
+        let from = from.to_string();
+        let to = to.to_string();
+        // the placeholder formats back to itself, so the line still round-trips
+        let subject = match subject {
+            NO_SUBJECT_IDENTIFIER => None,
+            other => Some(other.to_string()),
+        };
+
+        // taros <amount>
+        // item <type> <id> <quantity>
+        let mut taros = None;
+        let mut items = Vec::new();
+        for line in attachments {
+            let (line, _) = split_extra(line.as_ref());
+            if let Some(amount) = strip_token(line, token::TAROS) {
+                let amount = parse_digits(amount).ok_or("Malformed taros")?;
+                if taros.replace(amount).is_some() {
+                    return Err("Taros attached twice".into());
+                }
+            } else if let Some(fields) = strip_token(line, token::ITEM) {
+                let fields = fields
+                    .split(' ')
+                    .map(parse_digits)
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or("Malformed item")?;
+                let [item_type, item_id, quantity] = fields[..] else {
+                    return Err("Malformed item".into());
+                };
+                items.push(EmailItem {
+                    item_type,
+                    item_id,
+                    quantity,
+                });
+            }
+        }
+
+        Ok(Self {
+            from,
+            to,
+            subject,
+            body,
+            taros,
+            items,
+            extra,
+        })
+    }
+
+    /// Check if the email has a subject. A subject set to the placeholder servers send
+    /// for emails without one can't be told apart from it on the wire, and counts as none.
+    pub fn has_subject(&self) -> bool {
+        self.subject_text().is_some()
+    }
+
+    /// Get the subject of the email, if it has one.
+    pub fn subject_text(&self) -> Option<&str> {
+        self.subject
+            .as_deref()
+            .filter(|subject| *subject != NO_SUBJECT_IDENTIFIER)
+    }
+}
+impl Display for EmailEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "email [Email] {} (to {}): <{}>",
+            self.from,
+            self.to,
+            self.subject.as_deref().unwrap_or(NO_SUBJECT_IDENTIFIER)
+        )?;
+        write_extra(f, &self.extra)?;
+        for line in &self.body {
+            write!(f, "\n\t{}", line)?;
+        }
+        if let Some(taros) = self.taros {
+            write!(f, "\ntaros {}", taros)?;
+        }
+        for item in &self.items {
+            write!(
+                f,
+                "\nitem {} {} {}",
+                item.item_type, item.item_id, item.quantity
+            )?;
+        }
+        write!(f, "\nendemail")
+    }
+}
+
+impl NameRequestEvent {
+    fn parse(line: &str) -> Result<Self> {
+        // namereq <player_uid> <requested_name>
+        let (line, extra) = split_extra(line);
+        let rest = strip_token(line, token::NAME_REQUEST).ok_or("Malformed")?;
+        let (player_uid, requested_name) = next_number(rest, false).ok_or("Malformed")?;
+        if requested_name.is_empty() {
+            return Err("Malformed".into());
+        }
+        let player_uid = player_uid.parse()?;
+        let requested_name = requested_name.to_string();
+        Ok(Self {
+            player_uid,
+            requested_name,
+            extra,
+        })
+    }
+}
+impl Display for NameRequestEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "namereq {} {}", self.player_uid, self.requested_name)?;
+        write_extra(f, &self.extra)
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Player(event) => write!(f, "{}", event),
+            Self::Chat(event) => write!(f, "{}", event),
+            Self::Broadcast(event) => write!(f, "{}", event),
+            Self::Email(event) => write!(f, "{}", event),
+            Self::NameRequest(event) => write!(f, "{}", event),
+            Self::Custom(event) => write!(f, "{}", event),
+        }
+    }
+}
+
+impl Display for MonitorUpdate {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "begin")?;
+        if let Some(time) = self.server_time {
+            let millis = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(f, "time {}", millis.as_millis())?;
+        }
+        let mut metadata: Vec<_> = self.metadata.iter().collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            match value.is_empty() {
+                true => writeln!(f, "meta {}", key)?,
+                false => writeln!(f, "meta {} {}", key, value)?,
+            }
+        }
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        if self.is_heartbeat() {
+            // keep it from parsing back as an empty server
+            writeln!(f)?;
+        }
+        writeln!(f, "end")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_frames_hold_only_blank_lines() {
+        let heartbeat = parse_frame("begin\n\n  \nend\n");
+        assert!(heartbeat.is_heartbeat());
+        assert!(parse_frame(&heartbeat.to_string()).is_heartbeat());
+
+        // the server is empty, not idle
+        assert!(!parse_frame("begin\nend\n").is_heartbeat());
+        // nothing parsed, but something was sent
+        assert!(!parse_frame("begin\nbogus line\nend\n").is_heartbeat());
+    }
+
+    #[test]
+    fn huge_times_dont_panic() {
+        let update = parse_frame("begin\ntime 18446744073709551615\nend\n");
+        // representable on some platforms but not others
+        let representable = UNIX_EPOCH.checked_add(Duration::from_millis(u64::MAX));
+        assert_eq!(update.server_time(), representable);
+    }
+
+    #[test]
+    fn multiline_messages_validate() {
+        let mut update = MonitorUpdate::default();
+        let chat = parse_line("chat [FreeChat] Bob: placeholder").unwrap();
+        let Event::Chat(mut chat) = chat else {
+            panic!("not a chat");
+        };
+        chat.message = "two\nlines\tand a tab".to_string();
+        assert_eq!(update.try_add_event(Event::Chat(chat)), Ok(()));
+        let line = update.events()[0].to_string();
+        let version = ProtocolVersion::new(2, 1);
+        let parsed = parse_lines(
+            &[line],
+            &ParserRegistry::default(),
+            Some(&version),
+            &mut Vec::new(),
+        );
+        assert_eq!(parsed.events(), update.events());
+    }
+
+    #[test]
+    fn backslashes_are_kept_without_a_version() {
+        let line = r"chat [FreeChat] Bob: C:\new\temp";
+        match parse_frame(line).events() {
+            [Event::Chat(chat)] => assert_eq!(chat.message, r"C:\new\temp"),
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn fingerprints_dont_depend_on_escaping() {
+        let old = ProtocolVersion::new(2, 0);
+        let new = ProtocolVersion::new(2, 1);
+        let parse = |line: &str, version| {
+            let update = parse_lines(
+                &[line],
+                &ParserRegistry::default(),
+                Some(&version),
+                &mut Vec::new(),
+            );
+            update.events()[0].clone()
+        };
+        // a literal backslash-n, sent as it is before 2.1 and escaped since
+        let before = parse(r"chat [FreeChat] Bob: C:\new", old);
+        let after = parse(r"chat [FreeChat] Bob: C:\\new", new);
+        assert_eq!(before, after);
+        assert_eq!(before.fingerprint(), after.fingerprint());
+        assert_eq!(
+            before.fingerprint(),
+            fnv1a_64(r"chat [FreeChat] Bob: C:\new".as_bytes())
+        );
+    }
+
+    fn parse_email(frame: &str) -> EmailEvent {
+        match parse_frame(frame).events() {
+            [Event::Email(email)] => email.clone(),
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    fn email_frame(email: &EmailEvent) -> String {
+        format!("begin\n{}\nend\n", email)
+    }
+
+    #[test]
+    fn email_subjects_round_trip() {
+        let frame = "begin\nemail [Email] Bob (to Alice): <No subject.>\n\thi\nendemail\nend\n";
+        let placeholder = parse_email(frame);
+        assert_eq!(placeholder.subject, None);
+        assert!(!placeholder.has_subject());
+        assert_eq!(placeholder.subject_text(), None);
+        assert_eq!(parse_frame(frame).to_string(), frame);
+
+        let subject = parse_email(&frame.replace("No subject.", "Hello"));
+        assert!(subject.has_subject());
+        assert_eq!(subject.subject_text(), Some("Hello"));
+        assert_eq!(parse_email(&email_frame(&subject)), subject);
+
+        // a subject set to the placeholder is sent, and parsed back, as none
+        let literal = EmailEvent {
+            subject: Some(NO_SUBJECT_IDENTIFIER.to_string()),
+            ..subject
+        };
+        assert!(!literal.has_subject());
+        assert_eq!(email_frame(&literal), frame);
+        assert_eq!(parse_email(&email_frame(&literal)), placeholder);
+    }
+
+    /// Parse an event line, or an email header as an email with no body.
+    #[cfg(feature = "regex")]
+    fn parse_vector(line: &str, tokenizer: Tokenizer) -> Vec<Event> {
+        match line.starts_with("email") {
+            true => parse_frame_with(&format!("{}\nendemail", line), tokenizer)
+                .events()
+                .to_vec(),
+            false => parse_line_with(line, tokenizer).into_iter().collect(),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn tokenizers_agree_on_vectors() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors/tokenizer.txt");
+        let vectors = std::fs::read_to_string(path).unwrap();
+        let vectors = vectors
+            .lines()
+            .filter(|vector| !vector.is_empty() && !vector.starts_with('#'));
+        for vector in vectors {
+            let (expected, line) = vector.split_at(2);
+            let events = parse_vector(line, Tokenizer::Strict);
+            assert_eq!(events, parse_vector(line, Tokenizer::Regex), "{}", line);
+            match expected {
+                "+ " => assert!(
+                    events
+                        .first()
+                        .is_some_and(|event| event.to_string().lines().next() == Some(line)),
+                    "{}",
+                    line
+                ),
+                "- " => assert!(events.is_empty(), "{}", line),
+                _ => panic!("Bad vector: {}", vector),
+            }
+        }
+    }
+
+    /// Parse an email with the given line between its header and its `endemail`.
+    #[cfg(feature = "regex")]
+    fn email_with(line: &str) -> Option<EmailEvent> {
+        let frame = format!("email [Email] Alice (to Bob): <Hi>\n{}\nendemail", line);
+        match parse_frame(&frame).events() {
+            [Event::Email(email)] => Some(email.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn parsers_follow_the_spec_patterns() {
+        type Parses = fn(&str) -> bool;
+        let event: Parses = |line| !parse_vector(line, Tokenizer::default()).is_empty();
+        let checks: [(&protocol::Line, Parses, &[&str]); 14] = [
+            (
+                &protocol::BEGIN,
+                |line| is_keyword(line, token::BEGIN, false),
+                &["begin", "begin ", "Begin", "begin 1"],
+            ),
+            (
+                &protocol::TIME,
+                |line| parse_time(line).is_ok(),
+                &[
+                    "time 1718000000000",
+                    "time +5",
+                    "time -1",
+                    "time ",
+                    "time 1 2",
+                ],
+            ),
+            (
+                &protocol::META,
+                |line| parse_meta(line).is_some(),
+                &[
+                    "meta uptime 5",
+                    "meta uptime",
+                    "meta uptime ",
+                    "meta  5",
+                    "meta ",
+                ],
+            ),
+            (
+                &protocol::END,
+                |line| FrameTrailer::parse(line, false).is_some(),
+                &[
+                    "end",
+                    "end 3",
+                    "end 3 0000abCD",
+                    "end +3",
+                    "end 3 +000abcd",
+                    "end 3 abc",
+                    "end  3",
+                    "end 3 0000abcd x",
+                ],
+            ),
+            (
+                &protocol::VERSION,
+                |line| ProtocolVersion::parse(line).is_some(),
+                &[
+                    "version 2.1",
+                    "version 2.1 beta 5",
+                    "version 2.1 ",
+                    "version +2.1",
+                    "version 2.+1",
+                    "version 2",
+                    "version 2.1.3",
+                ],
+            ),
+            (
+                &protocol::PLAYER,
+                event,
+                &[
+                    "player 1 -2 Bob",
+                    "player 1 2 Bob Smith",
+                    "player 1 2  Bob",
+                    "player +1 2 Bob",
+                    "player - 2 Bob",
+                    "player 1 2 ",
+                    "player 1 2",
+                ],
+            ),
+            (
+                &protocol::CHAT,
+                event,
+                &["chat [FreeChat] Alice: hi", "chat [FreeChat] Alice hi"],
+            ),
+            (
+                &protocol::BROADCAST,
+                event,
+                &[
+                    "bcast 1 2 10 GM: hello",
+                    "bcast +1 2 10 GM: hi",
+                    "bcast 1 2 10 GM:hi",
+                    "bcast 1 2 GM: hi",
+                ],
+            ),
+            (
+                &protocol::EMAIL,
+                event,
+                &[
+                    "email [Email] Alice (to Bob): <Hi>",
+                    "email [Email] Alice (to Bob): Hi",
+                ],
+            ),
+            (
+                &protocol::EMAIL_BODY,
+                |line| email_with(line).is_some_and(|email| !email.body.is_empty()),
+                &["\thello", "\t", "hello"],
+            ),
+            (
+                &protocol::TAROS,
+                |line| email_with(line).is_some_and(|email| email.taros.is_some()),
+                &["taros 5", "taros +5", "taros "],
+            ),
+            (
+                &protocol::ITEM,
+                |line| email_with(line).is_some_and(|email| !email.items.is_empty()),
+                &["item 1 2 3", "item +1 2 3", "item 1 2", "item 1 2 3 4"],
+            ),
+            (
+                &protocol::END_EMAIL,
+                |line| {
+                    let frame = format!("email [Email] Alice (to Bob): <Hi>\n{}", line);
+                    !parse_frame(&frame).is_empty()
+                },
+                &["endemail", "endemails", "end"],
+            ),
+            (
+                &protocol::NAME_REQUEST,
+                event,
+                &[
+                    "namereq 5 Bob",
+                    "namereq +5 Bob",
+                    "namereq 5 ",
+                    "namereq Bob",
+                ],
+            ),
+        ];
+        for (spec, parses, lines) in checks {
+            let pattern = Regex::new(spec.pattern).unwrap();
+            for line in lines {
+                assert_eq!(parses(line), pattern.is_match(line), "{:?}", line);
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Alerting when the player count crosses configured thresholds.
+
+#[cfg(feature = "net")]
+use std::sync::mpsc::{self, Receiver};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, SystemTime},
+};
+
+use crate::MonitorUpdate;
+#[cfg(feature = "net")]
+use crate::{Monitor, MonitorNotification};
+
+/// The player counts a Threshold is crossed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    /// More than the given number of players.
+    Above(usize),
+    /// Fewer than the given number of players.
+    Below(usize),
+    /// Exactly the given number of players, e.g. 0 for an empty server.
+    Equal(usize),
+}
+impl Condition {
+    fn is_met(&self, count: usize) -> bool {
+        match *self {
+            Self::Above(limit) => count > limit,
+            Self::Below(limit) => count < limit,
+            Self::Equal(limit) => count == limit,
+        }
+    }
+
+    /// Check whether the count is far enough from the threshold for a triggered alert
+    /// to be resolved.
+    fn is_cleared(&self, count: usize, hysteresis: usize) -> bool {
+        match *self {
+            Self::Above(limit) => count <= limit.saturating_sub(hysteresis),
+            Self::Below(limit) => count >= limit.saturating_add(hysteresis),
+            Self::Equal(limit) => count.abs_diff(limit) > hysteresis,
+        }
+    }
+}
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Above(limit) => write!(f, "players > {}", limit),
+            Self::Below(limit) => write!(f, "players < {}", limit),
+            Self::Equal(limit) => write!(f, "players == {}", limit),
+        }
+    }
+}
+
+/// A player count condition to alert on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Threshold {
+    id: String,
+    condition: Condition,
+    duration: Duration,
+    hysteresis: usize,
+}
+impl Threshold {
+    /// Create a Threshold with the given id, triggered as soon as the condition is met
+    /// and resolved as soon as it no longer is.
+    pub fn new(id: &str, condition: Condition) -> Self {
+        Self {
+            id: id.to_string(),
+            condition,
+            duration: Duration::ZERO,
+            hysteresis: 0,
+        }
+    }
+
+    /// Only trigger once the condition has been met for the given time,
+    /// e.g. an empty server for 10 minutes. Defaults to zero.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Only resolve a triggered alert once the count is more than this many players past
+    /// the threshold, so a count hovering around it doesn't raise an alert on every update.
+    /// Defaults to zero.
+    pub fn with_hysteresis(mut self, hysteresis: usize) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Get the id of the Threshold.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the condition of the Threshold.
+    pub fn condition(&self) -> Condition {
+        self.condition
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlertState {
+    /// The condition has been met for long enough.
+    Triggered,
+    /// The count moved back past the threshold after the alert was triggered.
+    Resolved,
+}
+impl Display for AlertState {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Triggered => write!(f, "triggered"),
+            Self::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+/// A Threshold that was triggered or resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alert {
+    pub threshold_id: String,
+    pub condition: Condition,
+    pub state: AlertState,
+    /// The player count that caused the change.
+    pub player_count: usize,
+    /// When the condition started being met, for triggered alerts,
+    /// or when the alert was triggered, for resolved ones.
+    pub since: SystemTime,
+    pub time: SystemTime,
+}
+impl Display for Alert {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Alert {} ({}) {} with {} players",
+            self.threshold_id, self.condition, self.state, self.player_count
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ThresholdState {
+    threshold: Threshold,
+    /// When the condition started being met, while it is.
+    met_since: Option<SystemTime>,
+    triggered_at: Option<SystemTime>,
+}
+
+/// Watches the player count of updates and reports thresholds being crossed.
+///
+/// Thresholds are only checked when an update is received, so a Threshold with a
+/// duration triggers on the first update after the duration has passed.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerCountAlerts {
+    thresholds: Vec<ThresholdState>,
+}
+impl PlayerCountAlerts {
+    /// Create a PlayerCountAlerts with no thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a Threshold. A Threshold with the same id is replaced.
+    pub fn add_threshold(&mut self, threshold: Threshold) {
+        self.remove_threshold(&threshold.id);
+        self.thresholds.push(ThresholdState {
+            threshold,
+            met_since: None,
+            triggered_at: None,
+        });
+    }
+
+    /// Remove the Threshold with the given id.
+    pub fn remove_threshold(&mut self, id: &str) {
+        self.thresholds.retain(|state| state.threshold.id != id);
+    }
+
+    /// Get the ids of the thresholds currently triggered.
+    pub fn triggered(&self) -> impl Iterator<Item = &str> {
+        self.thresholds
+            .iter()
+            .filter(|state| state.triggered_at.is_some())
+            .map(|state| state.threshold.id.as_str())
+    }
+
+    /// Process a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<Alert> {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> Vec<Alert> {
+        self.check_count_at(update.get_player_count(), time)
+    }
+
+    /// Check a player count seen at the given time against every Threshold.
+    pub fn check_count_at(&mut self, player_count: usize, time: SystemTime) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for state in &mut self.thresholds {
+            let threshold = &state.threshold;
+            let alert = |state: AlertState, since| Alert {
+                threshold_id: threshold.id.clone(),
+                condition: threshold.condition,
+                state,
+                player_count,
+                since,
+                time,
+            };
+            if threshold.condition.is_met(player_count) {
+                let met_since = *state.met_since.get_or_insert(time);
+                let held = time.duration_since(met_since).unwrap_or_default();
+                if state.triggered_at.is_none() && held >= threshold.duration {
+                    state.triggered_at = Some(time);
+                    alerts.push(alert(AlertState::Triggered, met_since));
+                }
+                continue;
+            }
+            state.met_since = None;
+            if let Some(triggered_at) = state.triggered_at {
+                if threshold
+                    .condition
+                    .is_cleared(player_count, threshold.hysteresis)
+                {
+                    state.triggered_at = None;
+                    alerts.push(alert(AlertState::Resolved, triggered_at));
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(feature = "net")]
+impl Monitor {
+    /// Subscribe to the alerts raised by the given PlayerCountAlerts for the updates
+    /// received from now on. Dropping the Receiver unsubscribes.
+    pub fn alert_receiver(&self, mut alerts: PlayerCountAlerts) -> Receiver<Alert> {
+        let (tx, rx) = mpsc::channel();
+        self.add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => alerts
+                .update(update)
+                .into_iter()
+                .all(|alert| tx.send(alert).is_ok()),
+            _ => true,
+        }));
+        rx
+    }
+}
+
+/// Posts alerts to a webhook as JSON, e.g. to page someone when the server empties.
+///
+/// The payload holds the alert's fields, times as unix milliseconds, along with a
+/// `content` message so it can be pointed directly at a Discord webhook.
+#[cfg(feature = "discord")]
+#[derive(Debug, Clone)]
+pub struct AlertWebhook {
+    url: String,
+}
+#[cfg(feature = "discord")]
+impl AlertWebhook {
+    /// Create an AlertWebhook posting to the given URL.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+
+    /// Post an Alert to the webhook.
+    pub fn post(&self, alert: &Alert) -> crate::Result<()> {
+        let millis = |time: SystemTime| {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64
+        };
+        let payload = serde_json::json!({
+            "content": alert.to_string(),
+            "allowed_mentions": { "parse": [] },
+            "threshold_id": alert.threshold_id,
+            "condition": alert.condition.to_string(),
+            "state": alert.state.to_string(),
+            "player_count": alert.player_count,
+            "since": millis(alert.since),
+            "time": millis(alert.time),
+        });
+        ureq::post(&self.url).send_json(payload)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Sanity checks on player positions, e.g. for spotting speedhackers from the monitor
+//! stream alone.
+//!
+//! An AnomalyDetector flags players seen outside of the world and players moving faster
+//! between two updates than they could in game. Legitimate warps, such as transport
+//! and `/goto`, also move players far in an instant, so Teleport anomalies are leads to
+//! look into rather than proof of cheating.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    geofence::Region,
+    map::{Position, MAP_SIZE},
+    Event, MonitorUpdate,
+};
+
+/// Something off about where a player was seen.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Anomaly {
+    /// A player was seen outside of the world bounds. Reported once each time the player
+    /// leaves them, rather than for every update they're out.
+    OutOfBounds { player: String, position: Position },
+    /// A player moved faster than the maximum speed between two consecutive updates.
+    Teleport {
+        player: String,
+        from: Position,
+        to: Position,
+        /// The time between the two updates.
+        elapsed: Duration,
+        /// The speed the player would have had to move at, in game units per second.
+        speed: f64,
+    },
+}
+impl Anomaly {
+    /// Get the name of the player the anomaly is about.
+    pub fn player(&self) -> &str {
+        match self {
+            Self::OutOfBounds { player, .. } | Self::Teleport { player, .. } => player,
+        }
+    }
+}
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { player, position } => {
+                write!(f, "{} is out of bounds at {}", player, position)
+            }
+            Self::Teleport {
+                player,
+                from,
+                to,
+                elapsed,
+                speed,
+            } => write!(
+                f,
+                "{} moved from {} to {} in {:?} ({:.0} units/s)",
+                player, from, to, elapsed, speed
+            ),
+        }
+    }
+}
+
+/// Checks the position of every player of each update against the world bounds and
+/// the position they had in the update before.
+///
+/// Players missing from an update are forgotten, so their next position isn't compared
+/// to where they went offline.
+#[derive(Debug, Clone)]
+pub struct AnomalyDetector {
+    bounds: Region,
+    max_speed: Option<f64>,
+    last_seen: HashMap<String, (Position, SystemTime)>,
+    out_of_bounds: HashSet<String>,
+}
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        Self {
+            bounds: Region::Rectangle {
+                min_x: 0,
+                min_y: 0,
+                max_x: MAP_SIZE - 1,
+                max_y: MAP_SIZE - 1,
+            },
+            max_speed: None,
+            last_seen: HashMap::new(),
+            out_of_bounds: HashSet::new(),
+        }
+    }
+}
+impl AnomalyDetector {
+    /// Create an AnomalyDetector checking players are on the world map, without a
+    /// maximum speed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the Region players are expected to stay in. Defaults to the whole world map.
+    pub fn with_bounds(mut self, bounds: Region) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Report players moving faster than the given speed, in game units per second,
+    /// between two updates. Off by default.
+    pub fn with_max_speed(mut self, max_speed: f64) -> Self {
+        self.max_speed = Some(max_speed);
+        self
+    }
+
+    /// Check a MonitorUpdate sent at its timestamp, or now if it has none.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<Anomaly> {
+        let time = update.timestamp().unwrap_or_else(SystemTime::now);
+        self.update_at(update, time)
+    }
+
+    /// Check a MonitorUpdate sent at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        let mut last_seen = HashMap::with_capacity(self.last_seen.len());
+        for event in update.events() {
+            let Event::Player(player) = event else {
+                continue;
+            };
+            if last_seen.contains_key(&player.name) {
+                continue;
+            }
+            let position = Position::new(player.x_coord, player.y_coord);
+            if self.bounds.contains(position.x, position.y) {
+                self.out_of_bounds.remove(&player.name);
+            } else if self.out_of_bounds.insert(player.name.clone()) {
+                anomalies.push(Anomaly::OutOfBounds {
+                    player: player.name.clone(),
+                    position,
+                });
+            }
+            if let (Some(max_speed), Some((from, from_time))) =
+                (self.max_speed, self.last_seen.get(&player.name))
+            {
+                let elapsed = time.duration_since(*from_time).unwrap_or_default();
+                // updates sent at the same time can't tell how fast anyone moved
+                if !elapsed.is_zero() {
+                    let speed = from.distance_to(&position) / elapsed.as_secs_f64();
+                    if speed > max_speed {
+                        anomalies.push(Anomaly::Teleport {
+                            player: player.name.clone(),
+                            from: *from,
+                            to: position,
+                            elapsed,
+                            speed,
+                        });
+                    }
+                }
+            }
+            last_seen.insert(player.name.clone(), (position, time));
+        }
+        self.out_of_bounds
+            .retain(|player| last_seen.contains_key(player));
+        self.last_seen = last_seen;
+        anomalies
+    }
+
+    /// Forget every player, e.g. after losing the connection.
+    pub fn clear(&mut self) {
+        self.last_seen.clear();
+        self.out_of_bounds.clear();
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    env,
+    io::{self, Write as _},
+    process::ExitCode,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use ffmonitor::{
+    relay::Relay,
+    replay::{Recorder, ReplayMonitor},
+    Monitor, MonitorNotification, MonitorUpdate,
+};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8003";
+
+const USAGE: &str = "\
+Usage: ffmonitor-cli [-a <address>] <command>
+
+Commands:
+    tail            Print live events
+    count           Print the player count of every update
+    record <file>   Append live updates to a recording file
+    replay <file>   Print the events of a recording file with their original timing
+    json            Print live events as JSON lines
+    relay <bind>    Share the monitor connection with other clients connecting to <bind>
+
+Options:
+    -a, --address <address>   Monitor address [default: 127.0.0.1:8003]";
+
+enum Command {
+    Tail,
+    Count,
+    Record(String),
+    Replay(String),
+    Json,
+    Relay(String),
+}
+
+fn parse_args() -> Result<(String, Command), String> {
+    let mut args = env::args().skip(1);
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut command = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" | "--address" => address = args.next().ok_or("Missing address")?,
+            "-h" | "--help" => return Err(String::new()),
+            "tail" => command = Some(Command::Tail),
+            "count" => command = Some(Command::Count),
+            "record" => command = Some(Command::Record(args.next().ok_or("Missing file")?)),
+            "replay" => command = Some(Command::Replay(args.next().ok_or("Missing file")?)),
+            "json" => command = Some(Command::Json),
+            "relay" => command = Some(Command::Relay(args.next().ok_or("Missing bind address")?)),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    let command = command.ok_or("Missing command")?;
+    Ok((address, command))
+}
+
+fn print_update(update: &MonitorUpdate) {
+    println!("Player count: {}", update.get_player_count());
+    for event in update.events() {
+        for line in event.to_human_string().lines() {
+            println!("\t{}", line);
+        }
+    }
+}
+
+/// Run `handler` on every update received by a Monitor connected to `address`.
+/// Connection changes are reported on stderr.
+fn run_live(
+    address: &str,
+    mut handler: impl FnMut(&MonitorUpdate) -> io::Result<()>,
+) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let _monitor = Monitor::new_with_callback(
+        address,
+        Box::new(move |notification| {
+            let _ = tx.send(notification);
+        }),
+    )
+    .map_err(|err| format!("Bad address {}: {}", address, err))?;
+
+    for notification in rx {
+        match notification {
+            MonitorNotification::Connected => eprintln!("Connected to monitor at {}", address),
+            MonitorNotification::Disconnected => eprintln!("Monitor disconnected"),
+            MonitorNotification::Reconnecting {
+                attempt,
+                last_error,
+                next_retry_in,
+            } => eprintln!(
+                "{}, retrying in {}s (attempt {})",
+                last_error.as_deref().unwrap_or("Disconnected"),
+                next_retry_in.as_secs(),
+                attempt
+            ),
+            MonitorNotification::Updated(update) => handler(&update).map_err(|e| e.to_string())?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn replay(path: &str) -> Result<(), String> {
+    let mut replay = ReplayMonitor::new(path).map_err(|err| format!("Bad recording: {}", err))?;
+    loop {
+        let finished = replay.is_finished();
+        while let Some(update) = replay.poll() {
+            print_update(&update);
+        }
+        if finished {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn relay(address: &str, bind: &str) -> Result<(), String> {
+    let relay = Relay::new(address, bind).map_err(|err| format!("Can't relay: {}", err))?;
+    eprintln!("Relaying monitor at {} on {}", address, relay.local_addr());
+    let mut clients = 0;
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        if relay.client_count() != clients {
+            clients = relay.client_count();
+            eprintln!("{} clients connected", clients);
+        }
+    }
+}
+
+fn run(address: &str, command: Command) -> Result<(), String> {
+    match command {
+        Command::Tail => run_live(address, |update| {
+            print_update(update);
+            Ok(())
+        }),
+        Command::Count => run_live(address, |update| {
+            println!("{}", update.get_player_count());
+            Ok(())
+        }),
+        Command::Record(path) => {
+            let mut recorder =
+                Recorder::create(&path).map_err(|err| format!("Can't open {}: {}", path, err))?;
+            let mut recorded = 0;
+            run_live(address, |update| {
+                recorder
+                    .record(update)
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                recorded += 1;
+                eprint!("\rRecorded {} updates", recorded);
+                Ok(())
+            })
+        }
+        Command::Replay(path) => replay(&path),
+        Command::Relay(bind) => relay(address, &bind),
+        Command::Json => run_live(address, |update| {
+            let mut stdout = io::stdout().lock();
+            for event in update.clone().get_sequenced_events() {
+                serde_json::to_writer(&mut stdout, &event)?;
+                writeln!(stdout)?;
+            }
+            stdout.flush()
+        }),
+    }
+}
+
+fn main() -> ExitCode {
+    let (address, command) = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&address, command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Tracking which announcements are on screen and spotting ones that collide.
+//!
+//! A broadcast stays on screen for its `duration_secs`. Two broadcasts of the same
+//! announcement type shown at the same time compete for the same spot on screen,
+//! e.g. two dialog boxes, so one of them may go unread.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{BroadcastEvent, Event, MonitorUpdate};
+
+/// Broadcasts lasting longer than this, about a hundred years, are treated as lasting
+/// this long.
+const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// A broadcast along with the time it is displayed for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActiveBroadcast {
+    pub broadcast: BroadcastEvent,
+    pub started_at: SystemTime,
+    pub ends_at: SystemTime,
+}
+impl ActiveBroadcast {
+    fn new(broadcast: BroadcastEvent, started_at: SystemTime) -> Self {
+        // durations come straight from the wire, so they may not fit in a SystemTime
+        let duration = Duration::from_secs(broadcast.duration_secs as u64).min(MAX_DURATION);
+        let ends_at = started_at.checked_add(duration).unwrap_or(started_at);
+        Self {
+            broadcast,
+            started_at,
+            ends_at,
+        }
+    }
+
+    /// Check whether the broadcast is displayed at the given time.
+    pub fn is_displayed_at(&self, time: SystemTime) -> bool {
+        self.started_at <= time && time < self.ends_at
+    }
+
+    /// Check whether the two broadcasts are of the same type and displayed at the same time.
+    pub fn collides_with(&self, other: &ActiveBroadcast) -> bool {
+        self.broadcast.announcement_type == other.broadcast.announcement_type
+            && self.started_at < other.ends_at
+            && other.started_at < self.ends_at
+    }
+}
+
+/// A broadcast that started while another one of the same type was still displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastCollision {
+    /// The broadcast that was already displayed.
+    pub displayed: ActiveBroadcast,
+    /// The broadcast that started on top of it.
+    pub incoming: ActiveBroadcast,
+}
+
+/// Keeps track of the broadcasts currently on screen.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastTracker {
+    active: Vec<ActiveBroadcast>,
+}
+impl BroadcastTracker {
+    /// Create a BroadcastTracker with no broadcasts displayed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<BroadcastCollision> {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    /// Returns the collisions caused by the broadcasts of the update.
+    pub fn update_at(
+        &mut self,
+        update: &MonitorUpdate,
+        time: SystemTime,
+    ) -> Vec<BroadcastCollision> {
+        let mut collisions = Vec::new();
+        for event in update.events() {
+            if let Event::Broadcast(broadcast) = event {
+                collisions.extend(self.add_broadcast_at(broadcast, time));
+            }
+        }
+        collisions
+    }
+
+    /// Add a single broadcast sent at the given time.
+    /// Returns a collision with each broadcast of the same type still displayed.
+    pub fn add_broadcast_at(
+        &mut self,
+        broadcast: &BroadcastEvent,
+        time: SystemTime,
+    ) -> Vec<BroadcastCollision> {
+        self.expire(time);
+        let incoming = ActiveBroadcast::new(broadcast.clone(), time);
+        let collisions = self
+            .active
+            .iter()
+            .filter(|displayed| displayed.collides_with(&incoming))
+            .map(|displayed| BroadcastCollision {
+                displayed: displayed.clone(),
+                incoming: incoming.clone(),
+            })
+            .collect();
+        self.active.push(incoming);
+        collisions
+    }
+
+    /// Check a planned broadcast against the broadcasts displayed so far, without adding it.
+    /// Returns the broadcasts it would collide with if it were sent at the given time.
+    pub fn check_planned(
+        &self,
+        broadcast: &BroadcastEvent,
+        at: SystemTime,
+    ) -> Vec<&ActiveBroadcast> {
+        let planned = ActiveBroadcast::new(broadcast.clone(), at);
+        self.active
+            .iter()
+            .filter(|displayed| displayed.collides_with(&planned))
+            .collect()
+    }
+
+    /// Get the broadcasts displayed now, oldest first.
+    pub fn displayed(&self) -> Vec<&ActiveBroadcast> {
+        self.displayed_at(SystemTime::now())
+    }
+
+    /// Get the broadcasts displayed at the given time, oldest first.
+    pub fn displayed_at(&self, time: SystemTime) -> Vec<&ActiveBroadcast> {
+        self.active
+            .iter()
+            .filter(|active| active.is_displayed_at(time))
+            .collect()
+    }
+
+    /// Forget the broadcasts that are no longer displayed at the given time.
+    pub fn expire(&mut self, time: SystemTime) {
+        self.active.retain(|active| active.ends_at > time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire;
+
+    #[test]
+    fn huge_durations_dont_overflow() {
+        let Ok(Event::Broadcast(broadcast)) =
+            wire::parse_line("bcast 0 0 18446744073709551615 a: b")
+        else {
+            panic!("broadcast didn't parse");
+        };
+        let now = SystemTime::now();
+        let mut tracker = BroadcastTracker::new();
+        tracker.add_broadcast_at(&broadcast, now);
+        let displayed = tracker.displayed_at(now + Duration::from_secs(365 * 24 * 60 * 60));
+        assert_eq!(displayed.len(), 1);
+    }
+}
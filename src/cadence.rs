@@ -0,0 +1,170 @@
+//! Measuring how regularly frames arrive, to spot server-side stalls.
+//!
+//! OpenFusion servers send a frame every tick of the monitor interval. Frames arriving
+//! late, or ticks skipped altogether, usually mean the server is struggling.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+use crate::MonitorUpdate;
+
+/// Ticks that went by without a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MissedTicks {
+    /// The number of frames that should have arrived in between.
+    pub missed: u64,
+    /// The time between the previous frame and this one.
+    pub interval: Duration,
+    /// The time the frame after the gap arrived at.
+    pub time: SystemTime,
+}
+
+/// A summary of the intervals measured by a Cadence.
+/// Jitter is how far an interval is from the expected tick interval, either way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CadenceStats {
+    pub frames: u64,
+    pub missed_ticks: u64,
+    pub mean_interval: Duration,
+    pub max_interval: Duration,
+    pub p50_jitter: Duration,
+    pub p95_jitter: Duration,
+    pub p99_jitter: Duration,
+}
+
+/// Measures the intervals between frames and detects missed ticks.
+///
+/// Statistics are computed over the most recent intervals only, so they follow the
+/// current state of the server. The frame and missed tick counts cover everything seen.
+#[derive(Debug, Clone)]
+pub struct Cadence {
+    tick_interval: Duration,
+    window: usize,
+    intervals: VecDeque<Duration>,
+    last_frame: Option<SystemTime>,
+    frames: u64,
+    missed_ticks: u64,
+}
+impl Cadence {
+    /// Create a Cadence for a server sending a frame every `tick_interval`.
+    /// Statistics are computed over the last 100 intervals by default.
+    pub fn new(tick_interval: Duration) -> Self {
+        Self {
+            tick_interval,
+            window: 100,
+            intervals: VecDeque::new(),
+            last_frame: None,
+            frames: 0,
+            missed_ticks: 0,
+        }
+    }
+
+    /// Set the number of recent intervals statistics are computed over.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        while self.intervals.len() > self.window {
+            self.intervals.pop_front();
+        }
+        self
+    }
+
+    /// Process a MonitorUpdate, at the time it was received.
+    /// Returns the ticks missed since the previous update, if any.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Option<MissedTicks> {
+        let time = update.received_at().unwrap_or_else(SystemTime::now);
+        self.record_frame_at(time)
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, _update: &MonitorUpdate, time: SystemTime) -> Option<MissedTicks> {
+        self.record_frame_at(time)
+    }
+
+    /// Record a frame arriving at the given time.
+    /// Returns the ticks missed since the previous frame, if any.
+    pub fn record_frame_at(&mut self, time: SystemTime) -> Option<MissedTicks> {
+        self.frames += 1;
+        let last_frame = self.last_frame.replace(time)?;
+        let interval = time.duration_since(last_frame).unwrap_or_default();
+        if self.intervals.len() == self.window {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+
+        if self.tick_interval.is_zero() {
+            return None;
+        }
+        // a frame within half a tick of when it was due doesn't count as missing one
+        let ticks = (interval.as_secs_f64() / self.tick_interval.as_secs_f64()).round() as u64;
+        let missed = ticks.saturating_sub(1);
+        if missed == 0 {
+            return None;
+        }
+        self.missed_ticks += missed;
+        Some(MissedTicks {
+            missed,
+            interval,
+            time,
+        })
+    }
+
+    /// Get the number of frames recorded.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Get the total number of ticks missed.
+    pub fn missed_ticks(&self) -> u64 {
+        self.missed_ticks
+    }
+
+    /// Get the mean of the recent intervals, if any were measured.
+    pub fn mean_interval(&self) -> Option<Duration> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let total: Duration = self.intervals.iter().sum();
+        Some(total / self.intervals.len() as u32)
+    }
+
+    /// Get the given percentile, between 0 and 100, of the jitter of the recent intervals.
+    pub fn jitter_percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut jitter: Vec<Duration> = self
+            .intervals
+            .iter()
+            .map(|interval| interval.abs_diff(self.tick_interval))
+            .collect();
+        if jitter.is_empty() {
+            return None;
+        }
+        jitter.sort_unstable();
+        // nearest rank
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * jitter.len() as f64).ceil() as usize;
+        Some(jitter[rank.saturating_sub(1)])
+    }
+
+    /// Summarize the recent intervals, if any were measured.
+    pub fn stats(&self) -> Option<CadenceStats> {
+        Some(CadenceStats {
+            frames: self.frames,
+            missed_ticks: self.missed_ticks,
+            mean_interval: self.mean_interval()?,
+            max_interval: self.intervals.iter().max().copied()?,
+            p50_jitter: self.jitter_percentile(50.0)?,
+            p95_jitter: self.jitter_percentile(95.0)?,
+            p99_jitter: self.jitter_percentile(99.0)?,
+        })
+    }
+
+    /// Forget every frame recorded, e.g. after reconnecting.
+    pub fn clear(&mut self) {
+        self.intervals.clear();
+        self.last_frame = None;
+        self.frames = 0;
+        self.missed_ticks = 0;
+    }
+}
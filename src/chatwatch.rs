@@ -0,0 +1,102 @@
+//! Rule-based scanning of player-written text for moderation.
+
+use regex::Regex;
+
+use crate::{Event, MonitorUpdate, Result};
+
+/// A rule matching the text of an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub rule_id: String,
+    pub event: Event,
+    /// The text that matched: a chat or broadcast message, an email subject or an email body line.
+    pub text: String,
+    /// Capture groups of the match, starting with the whole match.
+    /// For wordlist rules, this is just the matched word.
+    pub captures: Vec<Option<String>>,
+}
+
+/// Scans chat messages, broadcasts, and emails against a set of rules.
+#[derive(Debug, Clone, Default)]
+pub struct ChatWatch {
+    rules: Vec<(String, Regex)>,
+}
+impl ChatWatch {
+    /// Create a ChatWatch with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule matching the given regular expression.
+    /// A rule with the same id is replaced.
+    pub fn add_regex(&mut self, rule_id: &str, pattern: &str) -> Result<()> {
+        let regex = Regex::new(pattern)?;
+        self.add_rule(rule_id, regex);
+        Ok(())
+    }
+
+    /// Add a rule matching any of the given words, as whole words and ignoring case.
+    /// A rule with the same id is replaced.
+    pub fn add_wordlist(&mut self, rule_id: &str, words: &[&str]) -> Result<()> {
+        if words.is_empty() {
+            return Err("Empty wordlist".into());
+        }
+        let alternatives: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+        let regex = Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|")))?;
+        self.add_rule(rule_id, regex);
+        Ok(())
+    }
+
+    fn add_rule(&mut self, rule_id: &str, regex: Regex) {
+        self.remove_rule(rule_id);
+        self.rules.push((rule_id.to_string(), regex));
+    }
+
+    /// Remove the rule with the given id.
+    pub fn remove_rule(&mut self, rule_id: &str) {
+        self.rules.retain(|(id, _)| id != rule_id);
+    }
+
+    /// Check an Event against every rule.
+    /// Each rule matches at most once per piece of text.
+    pub fn check_event(&self, event: &Event) -> Vec<RuleMatch> {
+        let texts: Vec<&str> = match event {
+            Event::Chat(chat) => vec![&chat.message],
+            Event::Broadcast(bcast) => vec![&bcast.message],
+            Event::Email(email) => email
+                .subject_text()
+                .into_iter()
+                .chain(email.body.iter().map(String::as_str))
+                .collect(),
+            _ => return Vec::new(),
+        };
+
+        let mut matches = Vec::new();
+        for (rule_id, regex) in &self.rules {
+            for text in &texts {
+                let Some(captures) = regex.captures(text) else {
+                    continue;
+                };
+                matches.push(RuleMatch {
+                    rule_id: rule_id.clone(),
+                    event: event.clone(),
+                    text: text.to_string(),
+                    captures: captures
+                        .iter()
+                        .map(|group| group.map(|m| m.as_str().to_string()))
+                        .collect(),
+                });
+            }
+        }
+        matches
+    }
+
+    /// Check every event of a MonitorUpdate against every rule.
+    pub fn check_update(&self, update: &MonitorUpdate) -> Vec<RuleMatch> {
+        update
+            .events()
+            .iter()
+            .flat_map(|event| self.check_event(event))
+            .collect()
+    }
+}
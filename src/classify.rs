@@ -0,0 +1,95 @@
+//! Tagging chat messages, e.g. with their language or a toxicity score, so moderation
+//! models plug in at a single place.
+//!
+//! A ChatClassifier runs once per chat, and its tags travel with the event: to the
+//! subscribers of `Monitor::subscribe_classified()`, and to the sinks of an
+//! `EventPipeline` set up with `EventPipeline::with_classifier()`, through
+//! `EventSink::handle_tagged()`.
+
+#[cfg(feature = "net")]
+use std::sync::mpsc::Receiver;
+
+#[cfg(feature = "net")]
+use crate::{enrich::EnrichedEvent, Monitor};
+use crate::{ChatEvent, Event};
+
+/// What a ChatClassifier found out about a chat message.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChatTags {
+    /// The language of the message, e.g. as an ISO 639-1 code like `en`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub language: Option<String>,
+    /// How toxic the message is, from 0 for harmless to 1.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub toxicity: Option<f32>,
+    /// Any other labels, e.g. `spam` or `trade`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub labels: Vec<String>,
+}
+impl ChatTags {
+    /// Create ChatTags without any tags.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the language of the message.
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Set the toxicity score of the message.
+    pub fn with_toxicity(mut self, toxicity: f32) -> Self {
+        self.toxicity = Some(toxicity);
+        self
+    }
+
+    /// Add a label to the message.
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.labels.push(label.to_string());
+        self
+    }
+
+    /// Check if the message has the given label.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|other| other == label)
+    }
+
+    /// Check if the toxicity score is at least the given threshold.
+    /// Messages without a score never are.
+    pub fn is_toxic(&self, threshold: f32) -> bool {
+        self.toxicity.is_some_and(|toxicity| toxicity >= threshold)
+    }
+}
+
+/// Tags chat messages, e.g. with a language detector or a toxicity model.
+pub trait ChatClassifier: Send {
+    /// Tag a single chat.
+    fn classify(&mut self, chat: &ChatEvent) -> ChatTags;
+
+    /// Tag an Event if it's a chat. Other events get no tags.
+    fn classify_event(&mut self, event: &Event) -> Option<ChatTags> {
+        match event {
+            Event::Chat(chat) => Some(self.classify(chat)),
+            _ => None,
+        }
+    }
+}
+impl<F: FnMut(&ChatEvent) -> ChatTags + Send> ChatClassifier for F {
+    fn classify(&mut self, chat: &ChatEvent) -> ChatTags {
+        self(chat)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Monitor {
+    /// Subscribe to the events received from now on, with the tags the given classifier
+    /// gave each chat. Other events have no tags. Dropping the Receiver unsubscribes.
+    pub fn subscribe_classified<C: ChatClassifier + 'static>(
+        &self,
+        mut classifier: C,
+    ) -> Receiver<EnrichedEvent<Option<ChatTags>>> {
+        self.subscribe_enriched(move |event: &Event| classifier.classify_event(event))
+    }
+}
@@ -0,0 +1,134 @@
+//! Recognizing commands players type into chat, such as `/redeem CODE`.
+
+#[cfg(feature = "net")]
+use std::sync::mpsc::{self, Receiver};
+
+use crate::{name::PlayerName, ChatEvent, Event, MonitorUpdate};
+#[cfg(feature = "net")]
+use crate::{Monitor, MonitorNotification};
+
+/// A chat message recognized as a command.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandEvent {
+    /// The chat the command was typed in.
+    pub chat: ChatEvent,
+    /// The prefix the command was typed with, e.g. `/`.
+    pub prefix: String,
+    /// The name of the command, lowercased, e.g. `redeem`.
+    pub name: String,
+    /// The arguments after the name, split on whitespace.
+    pub args: Vec<String>,
+    /// Everything after the name, with surrounding whitespace trimmed,
+    /// for commands that take free text.
+    pub raw_args: String,
+}
+impl CommandEvent {
+    /// Get the name of the player who typed the command.
+    pub fn sender(&self) -> PlayerName {
+        self.chat.sender()
+    }
+}
+
+/// Picks out chats that start with one of a set of command prefixes.
+#[derive(Debug, Clone)]
+pub struct CommandParser {
+    prefixes: Vec<String>,
+    commands: Option<Vec<String>>,
+}
+impl Default for CommandParser {
+    fn default() -> Self {
+        Self {
+            prefixes: vec!["/".to_string()],
+            commands: None,
+        }
+    }
+}
+impl CommandParser {
+    /// Create a CommandParser recognizing any command starting with `/`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the prefixes commands start with, e.g. `["!", "/"]`.
+    /// Longer prefixes are tried first, so `!!` and `!` can be told apart.
+    pub fn with_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.prefixes = prefixes
+            .iter()
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| prefix.to_string())
+            .collect();
+        self.prefixes
+            .sort_by_key(|prefix| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Only recognize the given command names, ignoring case.
+    /// By default, any name after a prefix is recognized.
+    pub fn with_commands(mut self, commands: &[&str]) -> Self {
+        self.commands = Some(commands.iter().map(|name| name.to_lowercase()).collect());
+        self
+    }
+
+    /// Parse a chat as a command, if it is one.
+    pub fn parse(&self, chat: &ChatEvent) -> Option<CommandEvent> {
+        let message = chat.message.trim_start();
+        let (prefix, rest) = self
+            .prefixes
+            .iter()
+            .find_map(|prefix| Some((prefix, message.strip_prefix(prefix.as_str())?)))?;
+        // the name has to follow the prefix directly, so "/ hi" isn't a command
+        let (name, raw_args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if name.is_empty() {
+            return None;
+        }
+        let name = name.to_lowercase();
+        if let Some(commands) = &self.commands {
+            if !commands.contains(&name) {
+                return None;
+            }
+        }
+        Some(CommandEvent {
+            chat: chat.clone(),
+            prefix: prefix.clone(),
+            name,
+            args: raw_args.split_whitespace().map(str::to_string).collect(),
+            raw_args: raw_args.trim().to_string(),
+        })
+    }
+
+    /// Parse an Event as a command, if it is a chat holding one.
+    pub fn check_event(&self, event: &Event) -> Option<CommandEvent> {
+        match event {
+            Event::Chat(chat) => self.parse(chat),
+            _ => None,
+        }
+    }
+
+    /// Get the commands typed in the chats of a MonitorUpdate, in order.
+    pub fn check_update(&self, update: &MonitorUpdate) -> Vec<CommandEvent> {
+        update
+            .events()
+            .iter()
+            .filter_map(|event| self.check_event(event))
+            .collect()
+    }
+}
+
+#[cfg(feature = "net")]
+impl Monitor {
+    /// Subscribe to the commands recognized by the given CommandParser in chats
+    /// received from now on. Dropping the Receiver unsubscribes.
+    pub fn command_receiver(&self, parser: CommandParser) -> Receiver<CommandEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => parser
+                .check_update(update)
+                .into_iter()
+                .all(|command| tx.send(command).is_ok()),
+            _ => true,
+        }));
+        rx
+    }
+}
@@ -0,0 +1,135 @@
+//! Threading of directed chats and emails into per-pair conversations.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::SystemTime,
+};
+
+use crate::{Event, MonitorUpdate};
+
+/// A directed chat or email that is part of a Conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConversationMessage {
+    /// When the update holding the message was received.
+    pub time: SystemTime,
+    pub from: String,
+    pub to: String,
+    /// The chat or email event itself.
+    pub event: Event,
+}
+
+/// Every message exchanged between two players, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conversation {
+    /// The two players, in alphabetical order.
+    pub participants: (String, String),
+    pub messages: VecDeque<ConversationMessage>,
+}
+impl Conversation {
+    /// Get the time of the last message of the conversation.
+    pub fn last_activity(&self) -> Option<SystemTime> {
+        self.messages.back().map(|message| message.time)
+    }
+}
+
+/// Groups directed chats (buddy chats, trade chats, ...) and emails into Conversations
+/// between pairs of players, regardless of who wrote to whom.
+/// Chats without a recipient are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Conversations {
+    pub(crate) conversations: HashMap<(String, String), Conversation>,
+    pub(crate) history_limit: Option<usize>,
+}
+impl Conversations {
+    /// Create a Conversations with no conversations and no history limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the given number of most recent messages in each conversation.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = Some(limit);
+        self
+    }
+
+    /// Process a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) {
+        for event in update.events() {
+            self.add_event_at(event, time);
+        }
+    }
+
+    /// Add a single Event received at the given time.
+    /// Returns `false` if the event isn't a directed chat or an email.
+    pub fn add_event_at(&mut self, event: &Event, time: SystemTime) -> bool {
+        let (from, to) = match event {
+            Event::Chat(chat) => match &chat.to {
+                Some(to) => (&chat.from, to),
+                None => return false,
+            },
+            Event::Email(email) => (&email.from, &email.to),
+            _ => return false,
+        };
+        let participants = Self::key(from, to);
+        let conversation = self
+            .conversations
+            .entry(participants.clone())
+            .or_insert_with(|| Conversation {
+                participants,
+                messages: VecDeque::new(),
+            });
+        conversation.messages.push_back(ConversationMessage {
+            time,
+            from: from.clone(),
+            to: to.clone(),
+            event: event.clone(),
+        });
+        if let Some(limit) = self.history_limit {
+            while conversation.messages.len() > limit {
+                conversation.messages.pop_front();
+            }
+        }
+        true
+    }
+
+    fn key(a: &str, b: &str) -> (String, String) {
+        if a <= b {
+            (a.to_string(), b.to_string())
+        } else {
+            (b.to_string(), a.to_string())
+        }
+    }
+
+    /// Get the conversation between two players, in either order.
+    pub fn get(&self, a: &str, b: &str) -> Option<&Conversation> {
+        self.conversations.get(&Self::key(a, b))
+    }
+
+    /// Get every conversation, most recently active first.
+    pub fn conversations(&self) -> Vec<&Conversation> {
+        let mut conversations: Vec<&Conversation> = self.conversations.values().collect();
+        conversations.sort_by_key(|conversation| std::cmp::Reverse(conversation.last_activity()));
+        conversations
+    }
+
+    /// Get every conversation the given player took part in, most recently active first.
+    pub fn conversations_of(&self, name: &str) -> Vec<&Conversation> {
+        let mut conversations = self.conversations();
+        conversations.retain(|conversation| {
+            conversation.participants.0 == name || conversation.participants.1 == name
+        });
+        conversations
+    }
+
+    /// Forget every conversation.
+    pub fn clear(&mut self) {
+        self.conversations.clear();
+    }
+}
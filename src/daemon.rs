@@ -0,0 +1,104 @@
+//! Running a Monitor as a small daemon until it's told to stop.
+//!
+//! `run_until()` hands every update to an UpdateHandler until a flag is set. It then
+//! closes the connection, hands over the updates still in flight and finishes the
+//! handler, e.g. flushing the sinks of an `EventPipeline`. With the `signals` feature,
+//! `run_until_shutdown()` does the same until the process receives Ctrl-C or SIGTERM.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{logging::*, sink::EventPipeline, Monitor, MonitorUpdate, Result};
+
+/// How often the stop flag is checked while waiting for updates.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a daemon does with the updates it receives.
+pub trait UpdateHandler {
+    /// Handle a single MonitorUpdate.
+    fn handle_update(&mut self, update: Arc<MonitorUpdate>);
+
+    /// Finish up once the Monitor has shut down and every update was handled.
+    fn finish(self) -> Result<()>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+}
+impl<F: FnMut(Arc<MonitorUpdate>)> UpdateHandler for F {
+    fn handle_update(&mut self, update: Arc<MonitorUpdate>) {
+        self(update)
+    }
+}
+impl UpdateHandler for EventPipeline {
+    fn handle_update(&mut self, update: Arc<MonitorUpdate>) {
+        self.send_update(&update);
+    }
+
+    /// Wait for the sinks to handle every event and flush them.
+    fn finish(self) -> Result<()> {
+        self.shutdown();
+        Ok(())
+    }
+}
+
+/// Hand every update from the Monitor to the handler until `stop` is set, then shut
+/// the Monitor down.
+///
+/// The frame being received when `stop` is set is finished first, and every update
+/// received until the connection is closed is still handled before the handler is
+/// finished. Updates already taken with `Monitor::poll()` are not seen again.
+pub fn run_until(
+    monitor: Monitor,
+    mut handler: impl UpdateHandler,
+    stop: &AtomicBool,
+) -> Result<()> {
+    while !stop.load(Ordering::Acquire) {
+        match monitor.rx.recv_timeout(STOP_CHECK_INTERVAL) {
+            Ok(update) => handler.handle_update(update),
+            Err(RecvTimeoutError::Timeout) => {}
+            // the listen thread gave up after a panic
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    info!("Shutting down monitor");
+    let drained = monitor.close().map(|updates| {
+        for update in updates {
+            handler.handle_update(update);
+        }
+    });
+    let finished = handler.finish();
+    drained.and(finished)
+}
+
+/// Like `run_until()`, until the process receives Ctrl-C or SIGTERM.
+///
+/// The signal handler is installed on the first call, and fails if another one was
+/// installed with the `ctrlc` crate. A second signal during the shutdown exits right away.
+#[cfg(feature = "signals")]
+pub fn run_until_shutdown(monitor: Monitor, handler: impl UpdateHandler) -> Result<()> {
+    use std::sync::Once;
+
+    static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+    static INSTALL: Once = Once::new();
+
+    let mut installed = Ok(());
+    INSTALL.call_once(|| {
+        installed = ctrlc::set_handler(|| {
+            if SHUTDOWN.swap(true, Ordering::AcqRel) {
+                warn!("Received a second shutdown signal, exiting");
+                std::process::exit(1);
+            }
+            info!("Received shutdown signal");
+        });
+    });
+    installed?;
+    run_until(monitor, handler, &SHUTDOWN)
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Receiver, Arc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+use crate::{logging::*, sink::EventSink, Event, EventKind, MonitorUpdate, Result};
+
+/// Discord rejects messages longer than this.
+const MAX_MESSAGE_LEN: usize = 2000;
+
+/// How long to wait before retrying a rate-limited post without a `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Never wait longer than this for a rate limit to lift, whatever Discord asks for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Forwards selected monitor events to a Discord webhook.
+///
+/// Each relayed event is rendered through a per-kind template. Placeholders are written
+/// as `{field}` and are replaced by the matching field of the event:
+/// - Player events: `{name}`, `{x}`, `{y}`
+/// - Chat events: `{kind}`, `{from}`, `{to}`, `{message}`
+/// - Broadcast events: `{scope}`, `{type}`, `{duration}`, `{from}`, `{message}`
+/// - Email events: `{from}`, `{to}`, `{subject}`, `{body}`
+/// - Name request events: `{player_uid}`, `{requested_name}`
+/// - Custom events: `{token}`, `{line}`
+///
+/// Placeholders are filled in a single pass, so values holding braces are posted as
+/// they are. Unknown placeholders are left in place.
+///
+/// Posts rejected with 429 Too Many Requests are retried once, after the delay given in
+/// the `Retry-After` header.
+pub struct DiscordRelay {
+    webhook_url: String,
+    username: Option<String>,
+    kinds: Vec<EventKind>,
+    templates: HashMap<EventKind, String>,
+    min_interval: Duration,
+    last_sent: Option<Instant>,
+}
+impl DiscordRelay {
+    /// Create a DiscordRelay posting to the given webhook URL.
+    /// By default, broadcasts, emails, and name requests are relayed,
+    /// at most one message per second.
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            webhook_url: webhook_url.to_string(),
+            username: None,
+            kinds: vec![
+                EventKind::Broadcast,
+                EventKind::Email,
+                EventKind::NameRequest,
+            ],
+            templates: HashMap::new(),
+            min_interval: Duration::from_secs(1),
+            last_sent: None,
+        }
+    }
+
+    /// Override the display name of the webhook for relayed messages.
+    pub fn with_username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    /// Set which kinds of events are relayed.
+    pub fn with_kinds(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    /// Set the template used to render events of the given kind.
+    pub fn with_template(mut self, kind: EventKind, template: &str) -> Self {
+        self.templates.insert(kind, template.to_string());
+        self
+    }
+
+    /// Set the minimum time between two messages sent to the webhook.
+    /// Events arriving faster than this are delayed, not dropped.
+    pub fn with_rate_limit(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Render an Event into the message that would be posted for it.
+    /// Returns `None` if events of this kind are not relayed.
+    pub fn render(&self, event: &Event) -> Option<String> {
+        let kind = event.kind();
+        if !self.kinds.contains(&kind) {
+            return None;
+        }
+        let template = self
+            .templates
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or_else(|| default_template(kind));
+
+        let fields: Vec<(&str, String)> = match event {
+            Event::Player(player) => vec![
+                ("name", player.name.clone()),
+                ("x", player.x_coord.to_string()),
+                ("y", player.y_coord.to_string()),
+            ],
+            Event::Chat(chat) => vec![
+                ("kind", chat.kind.to_string()),
+                ("from", chat.from.clone()),
+                ("to", chat.to.clone().unwrap_or_default()),
+                ("message", chat.message.clone()),
+            ],
+            Event::Broadcast(bcast) => vec![
+                ("scope", format!("{:?}", bcast.scope)),
+                ("type", bcast.announcement_type.to_string()),
+                ("duration", bcast.duration_secs.to_string()),
+                ("from", bcast.from.clone()),
+                ("message", bcast.message.clone()),
+            ],
+            Event::Email(email) => vec![
+                ("from", email.from.clone()),
+                ("to", email.to.clone()),
+                (
+                    "subject",
+                    email.subject_text().unwrap_or_default().to_string(),
+                ),
+                ("body", email.body.join("\n")),
+            ],
+            Event::NameRequest(namereq) => vec![
+                ("player_uid", namereq.player_uid.to_string()),
+                ("requested_name", namereq.requested_name.clone()),
+            ],
+            Event::Custom(custom) => vec![
+                ("token", custom.token.clone()),
+                ("line", custom.line.clone()),
+            ],
+            _ => vec![("line", event.to_string())],
+        };
+
+        let mut message = fill_template(template, &fields);
+        if message.chars().count() > MAX_MESSAGE_LEN {
+            message = message.chars().take(MAX_MESSAGE_LEN - 1).collect();
+            message.push('…');
+        }
+        Some(message)
+    }
+
+    /// Post the given Event to the webhook if its kind is relayed.
+    /// Blocks as needed to respect the rate limit.
+    pub fn relay(&mut self, event: &Event) -> Result<()> {
+        let Some(message) = self.render(event) else {
+            return Ok(());
+        };
+
+        if let Some(last_sent) = self.last_sent {
+            let elapsed = last_sent.elapsed();
+            if elapsed < self.min_interval {
+                thread::sleep(self.min_interval - elapsed);
+            }
+        }
+        self.last_sent = Some(Instant::now());
+
+        let mut payload = json!({
+            "content": message,
+            // never ping anyone from player-controlled text
+            "allowed_mentions": { "parse": [] },
+        });
+        if let Some(username) = &self.username {
+            payload["username"] = json!(username);
+        }
+        match ureq::post(&self.webhook_url).send_json(payload.clone()) {
+            Err(ureq::Error::Status(429, response)) => {
+                let retry_after = response
+                    .header("Retry-After")
+                    .and_then(|secs| secs.trim().parse().ok())
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .unwrap_or(DEFAULT_RETRY_AFTER)
+                    .min(MAX_RETRY_AFTER);
+                warn!("Rate limited by Discord, retrying in {:?}", retry_after);
+                thread::sleep(retry_after);
+                self.last_sent = Some(Instant::now());
+                ureq::post(&self.webhook_url).send_json(payload)?;
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Relay every event in the given MonitorUpdate.
+    /// Failed posts are logged and do not stop the remaining events.
+    pub fn relay_update(&mut self, update: &MonitorUpdate) {
+        for event in update.events() {
+            if let Err(err) = self.relay(event) {
+                warn!("Couldn't relay event to Discord ({}): {}", err, event);
+            }
+        }
+    }
+
+    /// Relay updates from the given Receiver (e.g. from `Monitor::subscribe()`)
+    /// on a background thread until the sending side goes away.
+    pub fn spawn(mut self, updates: Receiver<Arc<MonitorUpdate>>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for update in updates {
+                self.relay_update(&update);
+            }
+        })
+    }
+}
+
+/// Replace the `{field}` placeholders of a template with their values, without
+/// looking for placeholders in the values themselves.
+fn fill_template(template: &str, fields: &[(&str, String)]) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let (_, value) = fields.iter().find(|(field, _)| *field == name)?;
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                message.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+fn default_template(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Player => "**{name}** is at ({x}, {y})",
+        EventKind::Chat => "[{kind}] **{from}**: {message}",
+        EventKind::Broadcast => "**Announcement from {from}:** {message}",
+        EventKind::Email => "**Email from {from} to {to}:** {subject}\n{body}",
+        EventKind::NameRequest => "Player {player_uid} requested the name **{requested_name}**",
+        EventKind::Custom => "{line}",
+        _ => "{line}",
+    }
+}
+impl EventSink for DiscordRelay {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        self.relay(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire;
+
+    #[test]
+    fn placeholders_in_values_are_kept() {
+        let update = wire::parse_frame(
+            "begin\nemail [Email] Bob (to GM): <{body} {nope}>\n\tthe body\nendemail\nend\n",
+        );
+        let relay = DiscordRelay::new("http://localhost");
+        assert_eq!(
+            relay.render(&update.events()[0]).as_deref(),
+            Some("**Email from Bob to GM:** {body} {nope}\nthe body")
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_in_place() {
+        let fields = [("name", "Bob".to_string())];
+        assert_eq!(fill_template("{name} {x} {", &fields), "Bob {x} {");
+    }
+}
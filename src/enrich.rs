@@ -0,0 +1,68 @@
+//! Attaching metadata to events once, before they reach consumers.
+
+#[cfg(feature = "net")]
+use std::sync::mpsc::{self, Receiver};
+
+use crate::{Event, MonitorUpdate};
+#[cfg(feature = "net")]
+use crate::{Monitor, MonitorNotification};
+
+/// An Event along with the metadata an Enricher attached to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnrichedEvent<T> {
+    pub event: Event,
+    pub metadata: T,
+}
+
+/// Computes metadata for events, e.g. by looking up the account of a player
+/// or tagging the region a player is in.
+pub trait Enricher: Send {
+    type Output;
+
+    /// Compute the metadata of a single Event.
+    fn enrich(&mut self, event: &Event) -> Self::Output;
+
+    /// Enrich every event of a MonitorUpdate, in order.
+    fn enrich_update(&mut self, update: &MonitorUpdate) -> Vec<EnrichedEvent<Self::Output>> {
+        update
+            .events()
+            .iter()
+            .map(|event| EnrichedEvent {
+                metadata: self.enrich(event),
+                event: event.clone(),
+            })
+            .collect()
+    }
+}
+impl<T, F: FnMut(&Event) -> T + Send> Enricher for F {
+    type Output = T;
+
+    fn enrich(&mut self, event: &Event) -> T {
+        self(event)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Monitor {
+    /// Subscribe to the events received from now on, each enriched with metadata by the
+    /// given Enricher. The Enricher runs once per event as updates are handled, so
+    /// consumers fed from the Receiver don't each repeat the lookups.
+    /// Dropping the Receiver unsubscribes.
+    pub fn subscribe_enriched<E>(&self, mut enricher: E) -> Receiver<EnrichedEvent<E::Output>>
+    where
+        E: Enricher + 'static,
+        E::Output: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => enricher
+                .enrich_update(update)
+                .into_iter()
+                .all(|event| tx.send(event).is_ok()),
+            _ => true,
+        }));
+        rx
+    }
+}
@@ -0,0 +1,116 @@
+//! Tagging events with where and when they were received, so events from several
+//! Monitors, shards and recordings can be handled side by side.
+
+#[cfg(feature = "net")]
+use std::sync::{
+    mpsc::{self, Receiver},
+    Arc,
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::SystemTime,
+};
+
+#[cfg(feature = "net")]
+use crate::{sharded::ShardedUpdate, Monitor, MonitorNotification};
+use crate::{Event, MonitorUpdate};
+
+/// Where an Event came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum MonitorSource {
+    /// A monitor port, by address.
+    Address(String),
+    /// A shard of a `sharded::ShardedMonitor`, by label.
+    Shard(String),
+    /// A recording played back or a capture backfilled, by path.
+    Recording(String),
+}
+impl MonitorSource {
+    /// Get the address, label or path naming the source.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Address(name) | Self::Shard(name) | Self::Recording(name) => name,
+        }
+    }
+}
+impl Display for MonitorSource {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "{}", address),
+            Self::Shard(label) => write!(f, "shard {}", label),
+            Self::Recording(path) => write!(f, "recording {}", path),
+        }
+    }
+}
+
+/// An Event along with where and when it was received.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    pub source: MonitorSource,
+    /// When the update holding the Event was received, if it was received by a Monitor.
+    pub received_at: Option<SystemTime>,
+    /// The sequence number of the Event among the events from its source, as in
+    /// `SequencedEvent::sequence`. 0 for events of updates not received by a Monitor.
+    pub seq: u64,
+    pub event: Event,
+}
+impl Envelope {
+    /// Wrap every event of a MonitorUpdate from the given source, in order.
+    pub fn from_update(source: &MonitorSource, update: MonitorUpdate) -> Vec<Self> {
+        let received_at = update.received_at();
+        let unsequenced = update.sequence() == 0;
+        update
+            .get_sequenced_events()
+            .into_iter()
+            .map(|sequenced| Self {
+                source: source.clone(),
+                received_at,
+                seq: match unsequenced {
+                    true => 0,
+                    false => sequenced.sequence,
+                },
+                event: sequenced.event,
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "net")]
+impl ShardedUpdate {
+    /// Wrap every event of the update, with the shard as their source.
+    pub fn into_envelopes(self) -> Vec<Envelope> {
+        let update = Arc::unwrap_or_clone(self.update);
+        Envelope::from_update(&MonitorSource::Shard(self.shard), update)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Monitor {
+    /// Subscribe to the events received from now on, in Envelopes naming the address
+    /// they were received from, which changes when failing over to a fallback address.
+    /// Dropping the Receiver unsubscribes.
+    pub fn subscribe_envelopes(&self) -> Receiver<Envelope> {
+        let (tx, rx) = mpsc::channel();
+        let address = self
+            .active_endpoint()
+            .map_or_else(|| self.inner.address.clone(), |endpoint| endpoint.address);
+        let mut source = MonitorSource::Address(address);
+        self.add_tap(Box::new(move |notification| match notification {
+            MonitorNotification::EndpointChanged(endpoint) => {
+                source = MonitorSource::Address(endpoint.address.clone());
+                true
+            }
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => {
+                Envelope::from_update(&source, MonitorUpdate::clone(update))
+                    .into_iter()
+                    .all(|envelope| tx.send(envelope).is_ok())
+            }
+            _ => true,
+        }));
+        rx
+    }
+}
@@ -0,0 +1,314 @@
+//! Aggregating updates into tables for analytics, and exporting them as CSV or,
+//! with the `parquet` feature, Parquet.
+//!
+//! Two tables are produced: per-minute activity (frames, player counts and message
+//! volumes) and per-player sessions. Times are unix seconds, in UTC.
+//!
+//! Recordings made with `replay::Recorder` can be aggregated in one go with
+//! `aggregate_recording()`. For live data, feed updates to an `ActivityAggregator`,
+//! or export the sessions of an existing `tracker::SessionTracker`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    replay::read_recording,
+    tracker::{Session, SessionTracker},
+    MonitorUpdate, Result,
+};
+
+/// What happened on the server during a minute.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinuteActivity {
+    /// The start of the minute.
+    pub minute: SystemTime,
+    /// The number of frames received during the minute.
+    pub frames: u64,
+    pub max_players: usize,
+    /// The player count averaged over the frames of the minute.
+    pub mean_players: f64,
+    pub chats: u64,
+    pub broadcasts: u64,
+    pub emails: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MinuteTotals {
+    frames: u64,
+    players: u64,
+    max_players: usize,
+    chats: u64,
+    broadcasts: u64,
+    emails: u64,
+}
+
+/// The tables aggregated from a series of updates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Activity {
+    /// Activity for every minute with at least one frame, oldest first.
+    pub minutes: Vec<MinuteActivity>,
+    /// Every session seen, in the order they ended.
+    pub sessions: Vec<Session>,
+}
+impl Activity {
+    /// Write the per-minute activity as CSV, with a header row.
+    pub fn write_minutes_csv(&self, writer: impl Write) -> Result<()> {
+        write_csv(writer, &minute_columns(&self.minutes))
+    }
+
+    /// Write the sessions as CSV, with a header row.
+    pub fn write_sessions_csv(&self, writer: impl Write) -> Result<()> {
+        write_sessions_csv(&self.sessions, writer)
+    }
+
+    /// Write the per-minute activity as a Parquet file.
+    #[cfg(feature = "parquet")]
+    pub fn write_minutes_parquet(&self, writer: impl Write + Send) -> Result<()> {
+        write_parquet(writer, "minute_activity", minute_columns(&self.minutes))
+    }
+
+    /// Write the sessions as a Parquet file.
+    #[cfg(feature = "parquet")]
+    pub fn write_sessions_parquet(&self, writer: impl Write + Send) -> Result<()> {
+        write_sessions_parquet(&self.sessions, writer)
+    }
+}
+
+/// Aggregates updates into per-minute activity and per-player sessions.
+#[derive(Debug, Clone, Default)]
+pub struct ActivityAggregator {
+    minutes: BTreeMap<u64, MinuteTotals>,
+    sessions: SessionTracker,
+}
+impl ActivityAggregator {
+    /// Create an ActivityAggregator with no data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a MonitorUpdate at its timestamp, or now if it has none.
+    pub fn update(&mut self, update: &MonitorUpdate) {
+        let time = update.timestamp().unwrap_or_else(SystemTime::now);
+        self.update_at(update, time);
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let totals = self.minutes.entry(secs / 60).or_default();
+        let counts = update.counts_by_kind();
+        totals.frames += 1;
+        totals.players += counts.players as u64;
+        totals.max_players = totals.max_players.max(counts.players);
+        totals.chats += counts.chats as u64;
+        totals.broadcasts += counts.broadcasts as u64;
+        totals.emails += counts.emails as u64;
+        self.sessions.update_at(update, time);
+    }
+
+    /// Get the activity of every minute seen so far, oldest first.
+    pub fn minutes(&self) -> Vec<MinuteActivity> {
+        self.minutes
+            .iter()
+            .map(|(&minute, totals)| MinuteActivity {
+                minute: UNIX_EPOCH + Duration::from_secs(minute * 60),
+                frames: totals.frames,
+                max_players: totals.max_players,
+                mean_players: totals.players as f64 / totals.frames as f64,
+                chats: totals.chats,
+                broadcasts: totals.broadcasts,
+                emails: totals.emails,
+            })
+            .collect()
+    }
+
+    /// Get the sessions tracked so far.
+    pub fn sessions(&self) -> &SessionTracker {
+        &self.sessions
+    }
+
+    /// End the sessions still going, as of the last update they were seen in,
+    /// and get the aggregated tables.
+    pub fn finish(mut self) -> Activity {
+        self.sessions.end_all();
+        Activity {
+            minutes: self.minutes(),
+            sessions: self.sessions.completed_sessions().to_vec(),
+        }
+    }
+}
+
+/// Aggregate every frame of the recording at the given path.
+/// Frames without a recorded time are counted at their server time, if any, and
+/// skipped otherwise.
+pub fn aggregate_recording(path: &str) -> Result<Activity> {
+    let mut aggregator = ActivityAggregator::new();
+    for recorded in read_recording(BufReader::new(File::open(path)?))? {
+        if let Some(time) = recorded.time.or(recorded.update.server_time()) {
+            aggregator.update_at(&recorded.update, time);
+        }
+    }
+    Ok(aggregator.finish())
+}
+
+/// Write sessions, e.g. `SessionTracker::completed_sessions()`, as CSV with a header row.
+pub fn write_sessions_csv(sessions: &[Session], writer: impl Write) -> Result<()> {
+    write_csv(writer, &session_columns(sessions))
+}
+
+/// Write sessions, e.g. `SessionTracker::completed_sessions()`, as a Parquet file.
+#[cfg(feature = "parquet")]
+pub fn write_sessions_parquet(sessions: &[Session], writer: impl Write + Send) -> Result<()> {
+    write_parquet(writer, "sessions", session_columns(sessions))
+}
+
+pub(crate) enum Column {
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+    Text(Vec<String>),
+}
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Self::Int(values) => values.len(),
+            Self::Float(values) => values.len(),
+            Self::Text(values) => values.len(),
+        }
+    }
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn minute_columns(minutes: &[MinuteActivity]) -> Vec<(&'static str, Column)> {
+    let int = |field: fn(&MinuteActivity) -> i64| Column::Int(minutes.iter().map(field).collect());
+    vec![
+        ("minute", int(|row| unix_secs(row.minute))),
+        ("frames", int(|row| row.frames as i64)),
+        ("max_players", int(|row| row.max_players as i64)),
+        (
+            "mean_players",
+            Column::Float(minutes.iter().map(|row| row.mean_players).collect()),
+        ),
+        ("chats", int(|row| row.chats as i64)),
+        ("broadcasts", int(|row| row.broadcasts as i64)),
+        ("emails", int(|row| row.emails as i64)),
+    ]
+}
+
+fn session_columns(sessions: &[Session]) -> Vec<(&'static str, Column)> {
+    let int = |field: fn(&Session) -> i64| Column::Int(sessions.iter().map(field).collect());
+    vec![
+        (
+            "name",
+            Column::Text(
+                sessions
+                    .iter()
+                    .map(|session| session.name.clone())
+                    .collect(),
+            ),
+        ),
+        ("started_at", int(|session| unix_secs(session.started_at))),
+        ("ended_at", int(|session| unix_secs(session.ended_at))),
+        (
+            "duration_secs",
+            int(|session| session.duration().as_secs() as i64),
+        ),
+    ]
+}
+
+/// Quote a CSV field if it holds a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+pub(crate) fn write_csv(mut writer: impl Write, columns: &[(&str, Column)]) -> Result<()> {
+    let header: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+    writeln!(writer, "{}", header.join(","))?;
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    for row in 0..rows {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|(_, column)| match column {
+                Column::Int(values) => values[row].to_string(),
+                Column::Float(values) => format!("{:.2}", values[row]),
+                Column::Text(values) => csv_field(&values[row]),
+            })
+            .collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    writer: impl Write + Send,
+    table: &str,
+    columns: Vec<(&'static str, Column)>,
+) -> Result<()> {
+    use std::sync::Arc;
+
+    use parquet::{
+        data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|(name, column)| match column {
+            Column::Int(_) => format!("REQUIRED INT64 {};", name),
+            Column::Float(_) => format!("REQUIRED DOUBLE {};", name),
+            Column::Text(_) => format!("REQUIRED BYTE_ARRAY {} (UTF8);", name),
+        })
+        .collect();
+    let schema = parse_message_type(&format!("message {} {{ {} }}", table, fields.join(" ")))?;
+    let properties = WriterProperties::builder().build();
+    let mut file = SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(properties))?;
+    let mut row_group = file.next_row_group()?;
+    for (_, column) in columns {
+        let Some(mut writer) = row_group.next_column()? else {
+            break;
+        };
+        match column {
+            Column::Int(values) => {
+                writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Float(values) => {
+                writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Text(values) => {
+                let values: Vec<ByteArray> = values
+                    .iter()
+                    .map(|value| ByteArray::from(value.as_str()))
+                    .collect();
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        writer.close()?;
+    }
+    row_group.close()?;
+    file.close()?;
+    Ok(())
+}
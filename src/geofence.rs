@@ -0,0 +1,132 @@
+//! Alerts for players entering or leaving named regions of the game world.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{map::Position, Event, MonitorUpdate};
+
+/// An area of the game world, in the same units as player coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    /// Axis-aligned rectangle, inclusive of its edges.
+    Rectangle {
+        min_x: i32,
+        min_y: i32,
+        max_x: i32,
+        max_y: i32,
+    },
+    /// Circle, inclusive of its edge.
+    Circle {
+        center_x: i32,
+        center_y: i32,
+        radius: u32,
+    },
+}
+impl Region {
+    /// Check if the given point lies within the Region.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        match *self {
+            Self::Rectangle {
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+            } => (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y),
+            Self::Circle {
+                center_x,
+                center_y,
+                radius,
+            } => {
+                Position::new(x, y).distance_to(&Position::new(center_x, center_y)) <= radius as f64
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeofenceEvent {
+    Entered { player: String, region: String },
+    Left { player: String, region: String },
+}
+
+/// Keeps track of which players are inside which named Regions.
+///
+/// Players going offline while inside a region are reported as leaving it.
+#[derive(Debug, Clone, Default)]
+pub struct Geofence {
+    regions: Vec<(String, Region)>,
+    inside: HashMap<String, HashSet<String>>,
+}
+impl Geofence {
+    /// Create a Geofence with no regions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a named Region. A Region with the same name is replaced.
+    pub fn add_region(&mut self, name: &str, region: Region) {
+        self.remove_region(name);
+        self.regions.push((name.to_string(), region));
+    }
+
+    /// Remove the Region with the given name.
+    /// Players inside it are forgotten without generating `Left` events.
+    pub fn remove_region(&mut self, name: &str) {
+        self.regions.retain(|(region_name, _)| region_name != name);
+        for regions in self.inside.values_mut() {
+            regions.remove(name);
+        }
+    }
+
+    /// Get the names of the Regions the given player was inside as of the last update.
+    pub fn regions_of(&self, player: &str) -> impl Iterator<Item = &str> {
+        self.inside
+            .get(player)
+            .into_iter()
+            .flat_map(|regions| regions.iter().map(String::as_str))
+    }
+
+    /// Process a MonitorUpdate, returning the region entries and exits it caused.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<GeofenceEvent> {
+        let mut events = Vec::new();
+        let mut inside = HashMap::new();
+        for event in update.events() {
+            let Event::Player(player) = event else {
+                continue;
+            };
+            if inside.contains_key(&player.name) {
+                continue;
+            }
+            let regions: HashSet<String> = self
+                .regions
+                .iter()
+                .filter(|(_, region)| region.contains(player.x_coord, player.y_coord))
+                .map(|(name, _)| name.clone())
+                .collect();
+            let previous = self.inside.remove(&player.name).unwrap_or_default();
+            for region in regions.difference(&previous) {
+                events.push(GeofenceEvent::Entered {
+                    player: player.name.clone(),
+                    region: region.clone(),
+                });
+            }
+            for region in previous.difference(&regions) {
+                events.push(GeofenceEvent::Left {
+                    player: player.name.clone(),
+                    region: region.clone(),
+                });
+            }
+            inside.insert(player.name.clone(), regions);
+        }
+
+        // players that went offline
+        for (player, regions) in std::mem::replace(&mut self.inside, inside) {
+            for region in regions {
+                events.push(GeofenceEvent::Left {
+                    player: player.clone(),
+                    region,
+                });
+            }
+        }
+        events
+    }
+}
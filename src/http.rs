@@ -0,0 +1,302 @@
+//! Serving the status of a Monitor over HTTP, as JSON with times in milliseconds since
+//! the Unix epoch:
+//! - `/health`: the `MonitorHealth` of the connection
+//! - `/players`: the players currently online, with their positions
+//! - `/stats`: update and event counts since the server was started
+//!
+//! With the `http` feature, `Monitor::serve_status()` runs a tiny server of its own. With
+//! the `axum` feature, `router()` serves the same endpoints from an existing axum app,
+//! along with `/events`, streaming every event as Server-Sent Events.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "axum")]
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "http")]
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[cfg(feature = "axum")]
+use axum::{
+    extract::State,
+    response::sse::{self, KeepAlive, Sse},
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+#[cfg(feature = "http")]
+use crate::{logging::*, Monitor, Result};
+use crate::{tracker::PlayerTracker, MonitorNotification, NotificationTap, Shared};
+#[cfg(feature = "axum")]
+use crate::{MonitorHandle, MonitorUpdate};
+
+/// Give up on clients that take longer than this to send their request.
+#[cfg(feature = "http")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct StatusState {
+    roster: PlayerTracker,
+    updates: u64,
+    events_by_kind: HashMap<String, u64>,
+    peak_players: usize,
+}
+
+/// Keep the counts and roster served at `/players` and `/stats` up to date.
+fn status_tap(state: Arc<Mutex<StatusState>>) -> NotificationTap {
+    Box::new(move |notification| {
+        let mut state = state.lock().unwrap();
+        match notification {
+            MonitorNotification::Updated(update) => {
+                state.roster.update(update);
+                state.updates += 1;
+                for event in update.events() {
+                    *state
+                        .events_by_kind
+                        .entry(format!("{:?}", event.kind()))
+                        .or_default() += 1;
+                }
+                state.peak_players = state.peak_players.max(state.roster.player_count());
+            }
+            MonitorNotification::Disconnected => {
+                state.roster.clear();
+            }
+            _ => {}
+        }
+        true
+    })
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn health_json(shared: &Shared) -> Value {
+    let health = shared.health();
+    json!({
+        "connected": health.connected,
+        "last_connect_attempt": health.last_connect_attempt.map(unix_millis),
+        "last_frame": health.last_frame.map(unix_millis),
+        "consecutive_failures": health.consecutive_failures,
+        "last_error": health.last_error,
+    })
+}
+
+fn players_json(state: &StatusState) -> Value {
+    let mut players: Vec<_> = state.roster.players().collect();
+    players.sort_by(|a, b| a.name.cmp(&b.name));
+    let players: Vec<Value> = players
+        .into_iter()
+        .map(|player| {
+            json!({
+                "name": player.name,
+                "x": player.x_coord,
+                "y": player.y_coord,
+                "first_seen": unix_millis(player.first_seen),
+                "last_seen": unix_millis(player.last_seen),
+            })
+        })
+        .collect();
+    json!(players)
+}
+
+fn stats_json(state: &StatusState, started: SystemTime) -> Value {
+    json!({
+        "uptime_secs": started.elapsed().unwrap_or_default().as_secs(),
+        "updates": state.updates,
+        "events_by_kind": state.events_by_kind,
+        "player_count": state.roster.player_count(),
+        "peak_player_count": state.peak_players,
+    })
+}
+
+#[cfg(feature = "http")]
+fn respond(stream: &mut TcpStream, status: &str, body: &Value) -> Result<()> {
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "http")]
+fn handle(
+    mut stream: TcpStream,
+    shared: &Shared,
+    state: &Mutex<StatusState>,
+    started: SystemTime,
+) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers don't matter, but have to be read before responding
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut tokens = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (tokens.next(), tokens.next()) else {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            &json!({ "error": "Bad request" }),
+        );
+    };
+    if method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            &json!({ "error": "Method not allowed" }),
+        );
+    }
+    let body = match path.split('?').next().unwrap_or_default() {
+        "/health" => health_json(shared),
+        "/players" => players_json(&state.lock().unwrap()),
+        "/stats" => stats_json(&state.lock().unwrap(), started),
+        _ => {
+            return respond(
+                &mut stream,
+                "404 Not Found",
+                &json!({ "error": "Not found" }),
+            )
+        }
+    };
+    respond(&mut stream, "200 OK", &body)
+}
+
+#[cfg(feature = "http")]
+impl Monitor {
+    /// Serve the status of the Monitor over HTTP on the given address, as JSON
+    /// with times in milliseconds since the Unix epoch:
+    /// - `/health`: the `MonitorHealth` of the connection
+    /// - `/players`: the players currently online, with their positions
+    /// - `/stats`: update and event counts since the server was started
+    ///
+    /// The server runs in a background thread for as long as the program does.
+    pub fn serve_status(&self, addr: &str) -> Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+        info!("Serving monitor status on {}", listener.local_addr()?);
+        let started = SystemTime::now();
+        let state = Arc::new(Mutex::new(StatusState::default()));
+
+        self.add_tap(status_tap(state.clone()));
+
+        let shared = self.inner.shared.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(Into::into)
+                    .and_then(|stream| handle(stream, &shared, &state, started));
+                if let Err(err) = result {
+                    warn!("Bad status request: {}", err);
+                }
+            }
+        });
+        Ok(handle)
+    }
+}
+
+#[cfg(feature = "axum")]
+#[derive(Clone)]
+struct RouterState {
+    monitor: MonitorHandle,
+    status: Arc<Mutex<StatusState>>,
+    started: SystemTime,
+}
+
+/// Create an axum Router serving the status of the Monitor, with counts from now on,
+/// to be nested into an app, e.g. `app.nest("/monitor", http::router(monitor.handle()))`.
+/// Along with the JSON endpoints, `/events` streams the events of every update received
+/// as Server-Sent Events, named after the kind of event (see `EventKind::as_str()`)
+/// and holding the event as JSON, as serialized with the `serde` feature.
+#[cfg(feature = "axum")]
+pub fn router(monitor: MonitorHandle) -> Router {
+    let status = Arc::new(Mutex::new(StatusState::default()));
+    monitor.add_tap(status_tap(status.clone()));
+    let state = RouterState {
+        monitor,
+        status,
+        started: SystemTime::now(),
+    };
+    Router::new()
+        .route("/health", get(get_health))
+        .route("/players", get(get_players))
+        .route("/stats", get(get_stats))
+        .route("/events", get(get_events))
+        .with_state(state)
+}
+
+#[cfg(feature = "axum")]
+async fn get_health(State(state): State<RouterState>) -> Json<Value> {
+    Json(health_json(&state.monitor.shared))
+}
+
+#[cfg(feature = "axum")]
+async fn get_players(State(state): State<RouterState>) -> Json<Value> {
+    Json(players_json(&state.status.lock().unwrap()))
+}
+
+#[cfg(feature = "axum")]
+async fn get_stats(State(state): State<RouterState>) -> Json<Value> {
+    Json(stats_json(&state.status.lock().unwrap(), state.started))
+}
+
+#[cfg(feature = "axum")]
+async fn get_events(State(state): State<RouterState>) -> Sse<sse::KeepAliveStream<EventStream>> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    state
+        .monitor
+        .add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once the client has gone away
+            MonitorNotification::Updated(update) => {
+                sse_events(update).all(|event| tx.send(event).is_ok())
+            }
+            _ => true,
+        }));
+    Sse::new(EventStream { rx }).keep_alive(KeepAlive::default())
+}
+
+#[cfg(feature = "axum")]
+fn sse_events(update: &MonitorUpdate) -> impl Iterator<Item = sse::Event> + '_ {
+    update.events().iter().filter_map(|event| {
+        sse::Event::default()
+            .event(event.kind().as_str())
+            .json_data(event)
+            .ok()
+    })
+}
+
+/// The events streamed to a client of `/events`, as they're received.
+#[cfg(feature = "axum")]
+struct EventStream {
+    rx: tokio::sync::mpsc::UnboundedReceiver<sse::Event>,
+}
+#[cfg(feature = "axum")]
+impl futures_core::Stream for EventStream {
+    type Item = std::result::Result<sse::Event, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx).map(|event| event.map(Ok))
+    }
+}
@@ -0,0 +1,316 @@
+//! Ranking players by how many chats, broadcasts and emails they sent over a sliding
+//! window of time, e.g. for "top chatters of the week" pages.
+//!
+//! Counts are kept per player in buckets of time, an hour long by default, so memory
+//! doesn't grow with the number of messages and the window moves a bucket at a time.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    export::{unix_secs, write_csv, Column},
+    Event, MonitorUpdate, Result,
+};
+
+/// What players are ranked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LeaderboardMetric {
+    Chats,
+    Broadcasts,
+    Emails,
+}
+impl LeaderboardMetric {
+    /// Every metric, in the order they're exported.
+    pub const ALL: [LeaderboardMetric; 3] = [Self::Chats, Self::Broadcasts, Self::Emails];
+
+    /// Get the lowercase name of the metric, e.g. `chats`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chats => "chats",
+            Self::Broadcasts => "broadcasts",
+            Self::Emails => "emails",
+        }
+    }
+}
+impl Display for LeaderboardMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A player's place on a leaderboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeaderboardEntry {
+    /// The place of the player, from 1. Players with the same count share a rank,
+    /// and the ranks after them are skipped, e.g. 1, 2, 2, 4.
+    pub rank: usize,
+    pub player: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Counts {
+    chats: u64,
+    broadcasts: u64,
+    emails: u64,
+}
+impl Counts {
+    fn get(&self, metric: LeaderboardMetric) -> u64 {
+        match metric {
+            LeaderboardMetric::Chats => self.chats,
+            LeaderboardMetric::Broadcasts => self.broadcasts,
+            LeaderboardMetric::Emails => self.emails,
+        }
+    }
+}
+
+/// Counts the messages each player sent over a sliding window of time and ranks them.
+/// The window ends at the latest update seen, so recordings can be ranked as well as
+/// live updates.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Leaderboards {
+    window: Duration,
+    bucket_size: Duration,
+    buckets: BTreeMap<u64, HashMap<String, Counts>>,
+    as_of: Option<SystemTime>,
+}
+impl Default for Leaderboards {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(7 * 24 * 60 * 60),
+            bucket_size: Duration::from_secs(60 * 60),
+            buckets: BTreeMap::new(),
+            as_of: None,
+        }
+    }
+}
+impl Leaderboards {
+    /// Create Leaderboards over the last week, in buckets of an hour.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length of the sliding window.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set how long each bucket of counts lasts, and so how precisely the start of the
+    /// window is followed. Rounded to whole seconds, at least one.
+    pub fn with_bucket_size(mut self, bucket_size: Duration) -> Self {
+        self.bucket_size = Duration::from_secs(bucket_size.as_secs().max(1));
+        self
+    }
+
+    /// Process a MonitorUpdate at its timestamp, or now if it has none.
+    pub fn update(&mut self, update: &MonitorUpdate) {
+        let time = update.timestamp().unwrap_or_else(SystemTime::now);
+        self.update_at(update, time);
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) {
+        for event in update.events() {
+            self.add_event_at(event, time);
+        }
+        self.advance(time);
+    }
+
+    /// Count a single Event sent at the given time.
+    /// Only chat, broadcast and email events are counted.
+    pub fn add_event_at(&mut self, event: &Event, time: SystemTime) {
+        let (player, metric) = match event {
+            Event::Chat(chat) => (&chat.from, LeaderboardMetric::Chats),
+            Event::Broadcast(broadcast) => (&broadcast.from, LeaderboardMetric::Broadcasts),
+            Event::Email(email) => (&email.from, LeaderboardMetric::Emails),
+            _ => return,
+        };
+        let bucket = self.bucket_of(time);
+        if bucket < self.first_bucket() {
+            return;
+        }
+        let counts = self
+            .buckets
+            .entry(bucket)
+            .or_default()
+            .entry(player.clone())
+            .or_default();
+        match metric {
+            LeaderboardMetric::Chats => counts.chats += 1,
+            LeaderboardMetric::Broadcasts => counts.broadcasts += 1,
+            LeaderboardMetric::Emails => counts.emails += 1,
+        }
+        self.advance(time);
+    }
+
+    /// Move the end of the window to the given time, if it's later, and forget the
+    /// counts falling out of the window.
+    pub fn advance(&mut self, time: SystemTime) {
+        if self.as_of.is_some_and(|as_of| as_of >= time) {
+            return;
+        }
+        self.as_of = Some(time);
+        let first = self.first_bucket();
+        self.buckets = self.buckets.split_off(&first);
+    }
+
+    /// Get the time the window ends at, as of the latest update seen.
+    pub fn as_of(&self) -> Option<SystemTime> {
+        self.as_of
+    }
+
+    /// Get the start of the oldest bucket still counted.
+    pub fn window_start(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.first_bucket() * self.bucket_size.as_secs())
+    }
+
+    /// Get the players with the highest counts, at most `limit` of them, highest first.
+    /// Players with the same count are sorted by name.
+    pub fn top(&self, metric: LeaderboardMetric, limit: usize) -> Vec<LeaderboardEntry> {
+        let mut ranking = self.ranking(metric);
+        ranking.truncate(limit);
+        ranking
+    }
+
+    /// Get the place of a player on a leaderboard, if they sent anything.
+    pub fn rank_of(&self, player: &str, metric: LeaderboardMetric) -> Option<LeaderboardEntry> {
+        self.ranking(metric)
+            .into_iter()
+            .find(|entry| entry.player == player)
+    }
+
+    /// Get how many messages a player sent within the window.
+    pub fn count(&self, player: &str, metric: LeaderboardMetric) -> u64 {
+        self.buckets
+            .values()
+            .filter_map(|players| players.get(player))
+            .map(|counts| counts.get(metric))
+            .sum()
+    }
+
+    /// Get the top players of every leaderboard, at most `limit` per leaderboard,
+    /// for exporting.
+    pub fn snapshot(&self, limit: usize) -> LeaderboardSnapshot {
+        LeaderboardSnapshot {
+            window_start: self.window_start(),
+            window_end: self.as_of.unwrap_or(UNIX_EPOCH),
+            chats: self.top(LeaderboardMetric::Chats, limit),
+            broadcasts: self.top(LeaderboardMetric::Broadcasts, limit),
+            emails: self.top(LeaderboardMetric::Emails, limit),
+        }
+    }
+
+    /// Forget every count.
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.as_of = None;
+    }
+
+    fn ranking(&self, metric: LeaderboardMetric) -> Vec<LeaderboardEntry> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        for players in self.buckets.values() {
+            for (player, counts) in players {
+                *totals.entry(player).or_default() += counts.get(metric);
+            }
+        }
+        let mut totals: Vec<(&str, u64)> = totals.into_iter().filter(|(_, n)| *n > 0).collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let mut ranking: Vec<LeaderboardEntry> = Vec::with_capacity(totals.len());
+        for (idx, (player, count)) in totals.into_iter().enumerate() {
+            let rank = match ranking.last() {
+                Some(last) if last.count == count => last.rank,
+                _ => idx + 1,
+            };
+            ranking.push(LeaderboardEntry {
+                rank,
+                player: player.to_string(),
+                count,
+            });
+        }
+        ranking
+    }
+
+    fn bucket_of(&self, time: SystemTime) -> u64 {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        secs / self.bucket_size.as_secs()
+    }
+
+    fn first_bucket(&self) -> u64 {
+        let Some(as_of) = self.as_of else {
+            return 0;
+        };
+        let buckets = self.window.as_secs().div_ceil(self.bucket_size.as_secs());
+        (self.bucket_of(as_of) + 1).saturating_sub(buckets.max(1))
+    }
+}
+
+/// The top players of every leaderboard at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeaderboardSnapshot {
+    /// The start of the oldest bucket counted.
+    pub window_start: SystemTime,
+    /// The time of the latest update counted.
+    pub window_end: SystemTime,
+    pub chats: Vec<LeaderboardEntry>,
+    pub broadcasts: Vec<LeaderboardEntry>,
+    pub emails: Vec<LeaderboardEntry>,
+}
+impl LeaderboardSnapshot {
+    /// Get the entries of one leaderboard.
+    pub fn entries(&self, metric: LeaderboardMetric) -> &[LeaderboardEntry] {
+        match metric {
+            LeaderboardMetric::Chats => &self.chats,
+            LeaderboardMetric::Broadcasts => &self.broadcasts,
+            LeaderboardMetric::Emails => &self.emails,
+        }
+    }
+
+    /// Write every leaderboard as CSV, with a header row and a row per entry.
+    /// Window times are unix seconds, in UTC.
+    pub fn write_csv(&self, writer: impl Write) -> Result<()> {
+        let rows: Vec<(LeaderboardMetric, &LeaderboardEntry)> = LeaderboardMetric::ALL
+            .iter()
+            .flat_map(|&metric| {
+                self.entries(metric)
+                    .iter()
+                    .map(move |entry| (metric, entry))
+            })
+            .collect();
+        let int = |value: i64| Column::Int(vec![value; rows.len()]);
+        let columns = vec![
+            ("window_start", int(unix_secs(self.window_start))),
+            ("window_end", int(unix_secs(self.window_end))),
+            (
+                "metric",
+                Column::Text(rows.iter().map(|(metric, _)| metric.to_string()).collect()),
+            ),
+            (
+                "rank",
+                Column::Int(rows.iter().map(|(_, entry)| entry.rank as i64).collect()),
+            ),
+            (
+                "player",
+                Column::Text(rows.iter().map(|(_, entry)| entry.player.clone()).collect()),
+            ),
+            (
+                "count",
+                Column::Int(rows.iter().map(|(_, entry)| entry.count as i64).collect()),
+            ),
+        ];
+        write_csv(writer, &columns)
+    }
+}
@@ -1,528 +1,2106 @@
+#[cfg(feature = "net")]
 use std::{
-    fmt::{self, Display, Formatter},
-    io::{BufRead as _, BufReader},
-    net::{SocketAddr, TcpStream},
-    str::FromStr,
+    any::Any,
+    borrow::Cow,
+    fmt::{self, Formatter},
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    io::{BufRead as _, BufReader, Read as _, Write as _},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    panic::{self, AssertUnwindSafe},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::{self, Receiver},
-        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Condvar, Mutex,
     },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::Instant,
 };
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "net")]
+use crate::logging::*;
+
+pub mod alerts;
+pub mod anomaly;
+pub mod broadcasts;
+pub mod cadence;
+#[cfg(feature = "regex")]
+pub mod chatwatch;
+pub mod classify;
+pub mod commands;
+pub mod conversations;
+#[cfg(feature = "net")]
+pub mod daemon;
+#[cfg(feature = "discord")]
+pub mod discord;
+pub mod enrich;
+pub mod envelope;
+pub mod export;
+pub mod geofence;
+#[cfg(any(feature = "http", feature = "axum"))]
+pub mod http;
+pub mod leaderboard;
+mod logging;
+#[cfg(feature = "regex")]
+pub mod mailroute;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod namereq;
+pub mod offline;
+#[cfg(feature = "net")]
+pub mod pool;
+#[cfg(feature = "privacy")]
+pub mod privacy;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "net")]
+pub mod relay;
+pub mod replay;
+pub mod sampling;
+#[cfg(feature = "net")]
+pub mod sharded;
+pub mod simulator;
+pub mod sink;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+pub mod spam;
+#[cfg(feature = "sse")]
+pub mod sse;
+#[cfg(feature = "futures")]
+mod stream;
+pub mod tracker;
+pub mod uid;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+// the events and the wire format live in ffmonitor-proto, which doesn't need the network
+pub use ffmonitor_proto::*;
+
+/// How much of a line over the length limit is kept for its ProtocolViolation.
+#[cfg(feature = "net")]
+const TRUNCATED_LINE_LEN: usize = 256;
+
+/// How long to wait before connecting again after a failed attempt.
+#[cfg(feature = "net")]
+const RETRY_DELAY: Duration = Duration::from_secs(1);
 
-use log::*;
-use regex::Regex;
+/// How many times longer than the failback interval the wait between probes of a
+/// primary address that keeps refusing connections can grow.
+#[cfg(feature = "net")]
+const MAX_FAILBACK_BACKOFF: u32 = 16;
+
+/// Frames up to this many lines never give their line buffers back.
+#[cfg(feature = "net")]
+const MIN_FRAME_BUFFER_LINES: usize = 64;
+
+/// How long shutting down waits for the frame being received to end.
+#[cfg(feature = "net")]
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
 
 type Error = Box<dyn std::error::Error>;
 type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(feature = "net")]
 pub type MonitorNotificationCallback = Box<dyn Fn(MonitorNotification) + Send + Sync>;
 
+/// Internal observer of every notification. Returns `false` once it should be removed.
+#[cfg(feature = "net")]
+type NotificationTap = Box<dyn FnMut(&MonitorNotification) -> bool + Send>;
+
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum MonitorNotification {
     Connected,
-    Updated(MonitorUpdate),
+    /// A frame was received. The update is shared with the Monitor and its other
+    /// observers instead of being copied for each of them.
+    Updated(Arc<MonitorUpdate>),
+    /// An update identical to the previous one was received and dropped.
+    /// Only sent when deduplication is enabled in the `MonitorConfig`.
+    DuplicateSuppressed,
+    /// A player is in the update just delivered but wasn't in the one before it.
+    /// Holds the name of the player. Only sent when roster events are enabled in the
+    /// `MonitorConfig`, right after the `Updated` notification of the update.
+    PlayerJoined(String),
+    /// A player was in the previous update but isn't in the one just delivered.
+    /// Holds the name of the player. Only sent when roster events are enabled in the
+    /// `MonitorConfig`, right after the `Updated` notification of the update.
+    PlayerLeft(String),
+    /// A player was seen somewhere they shouldn't be, such as out of the world or too
+    /// far from where they were. Only sent when anomaly detection is enabled in the
+    /// `MonitorConfig`, right after the `Updated` notification of the update.
+    Anomaly(anomaly::Anomaly),
+    /// The server sent something that doesn't follow the monitor protocol.
+    /// Only sent when strict mode is enabled in the `MonitorConfig`, except for
+    /// lines and frames over the configured limits, which are always reported.
+    ProtocolViolation(ProtocolViolation),
+    /// A line that isn't valid UTF-8 was received and decoded lossily.
+    /// Holds the decoded line. Only sent when lossy decoding is enabled in the `MonitorConfig`.
+    InvalidUtf8(String),
+    /// The connection was lost in the middle of a frame. Holds the events parsed from
+    /// the lines received so far, which may be missing any number of events.
+    /// Only sent when partial frames are enabled in the `MonitorConfig`; otherwise the
+    /// lines are discarded. Partial updates aren't returned by `poll()`.
+    PartialUpdate(Arc<MonitorUpdate>),
+    /// The server announced the version of the protocol it speaks.
+    /// Also available from `Monitor::protocol_version()`.
+    ProtocolVersion(wire::ProtocolVersion),
+    /// The Monitor connected to a different address than on its previous connection,
+    /// including on its first connection, right after the `Connected` notification.
+    /// Also available from `Monitor::active_endpoint()`.
+    EndpointChanged(MonitorEndpoint),
+    /// A frame didn't match the line count or checksum of its `end` line, and was dropped.
+    /// Only sent when frame verification is enabled in the `MonitorConfig`.
+    IntegrityFailure(wire::IntegrityError),
     Disconnected,
+    /// The Monitor is about to try connecting again, after losing the connection
+    /// or failing to connect.
+    Reconnecting {
+        /// Number of the upcoming attempt since the last successful connection, starting from 1.
+        attempt: u32,
+        /// The error that ended the previous attempt, if any.
+        last_error: Option<String>,
+        /// How long the Monitor waits before the attempt.
+        next_retry_in: Duration,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct PlayerEvent {
-    pub x_coord: i32,
-    pub y_coord: i32,
-    pub name: String,
+/// Check that a Command fits on the single line it is sent as.
+#[cfg(feature = "net")]
+fn validate_command(command: &Command) -> Result<()> {
+    if let Command::Broadcast { message, .. } = command {
+        if message.contains(['\n', '\r']) {
+            return Err("Broadcast message contains a line break".into());
+        }
+    }
+    Ok(())
 }
-impl PlayerEvent {
-    fn parse(line: &str) -> Result<Self> {
-        // player <x> <y> <name...>
-        const PATTERN: &str = r"^player (-?\d+) (-?\d+) (.+)$";
-        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN).unwrap());
 
-        let captures = REGEX.captures(line).ok_or("Malformed")?;
-        let x_coord = captures[1].parse().map_err(|_| "Invalid x coordinate")?;
-        let y_coord = captures[2].parse().map_err(|_| "Invalid y coordinate")?;
-        let name = captures[3].to_string();
-        Ok(Self {
-            x_coord,
-            y_coord,
-            name,
-        })
+/// Check that an address is either a socket address or a `host:port` pair,
+/// without resolving it.
+#[cfg(feature = "net")]
+fn validate_address(address: &str) -> Result<()> {
+    if address.parse::<SocketAddr>().is_ok() {
+        return Ok(());
     }
+    let (host, port) = address.rsplit_once(':').ok_or("Address has no port")?;
+    if host.is_empty() || host.contains(':') {
+        // IPv6 addresses need brackets to be told apart from the port
+        return Err(format!("Invalid host {}", host).into());
+    }
+    port.parse::<u16>()?;
+    Ok(())
 }
-impl Display for PlayerEvent {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "player {} {} {}", self.x_coord, self.y_coord, self.name)
+
+/// Connect to an address, through the proxy of the config if it has one.
+#[cfg(feature = "net")]
+fn connect(address: &str, config: &MonitorConfig) -> Result<TcpStream> {
+    #[cfg(feature = "proxy")]
+    if let Some(proxy) = &config.proxy {
+        let stream = connect_direct(proxy.address(), config)?;
+        proxy.handshake(&stream, address, config.connect_timeout)?;
+        return Ok(stream);
     }
+    connect_direct(address, config)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-#[non_exhaustive]
-pub enum ChatKind {
-    FreeChat,
-    MenuChat,
-    BuddyChat,
-    BuddyMenuChat,
-    GroupChat,
-    GroupMenuChat,
-    TradeChat,
-    Unknown(String),
-}
-impl From<&str> for ChatKind {
-    fn from(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "freechat" => Self::FreeChat,
-            "menuchat" => Self::MenuChat,
-            "buddychat" => Self::BuddyChat,
-            "buddymenuchat" => Self::BuddyMenuChat,
-            "groupchat" => Self::GroupChat,
-            "groupmenuchat" => Self::GroupMenuChat,
-            "tradechat" => Self::TradeChat,
-            _ => Self::Unknown(s.to_string()),
-        }
-    }
-}
-impl FromStr for ChatKind {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<Self> {
-        Ok(s.into())
-    }
-}
-impl Display for ChatKind {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Self::Unknown(s) => write!(f, "{}*", s),
-            other => write!(f, "{:?}", other),
+/// Resolve an address and connect to the first of its socket addresses that accepts,
+/// in the order of preference.
+#[cfg(feature = "net")]
+fn connect_direct(address: &str, config: &MonitorConfig) -> Result<TcpStream> {
+    let mut addrs: Vec<SocketAddr> = address
+        .to_socket_addrs()?
+        .filter(|addr| config.ip_preference.allows(addr))
+        .collect();
+    // stable, so the resolver's order is kept within each family
+    addrs.sort_by_key(|addr| config.ip_preference.rank(addr));
+
+    let mut last_err = None;
+    for addr in addrs {
+        debug!("Trying {}", addr);
+        let result = match config.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        match result {
+            Ok(stream) => {
+                configure_stream(&stream, config)?;
+                return Ok(stream);
+            }
+            Err(err) => {
+                debug!("Couldn't connect to {}: {}", addr, err);
+                last_err = Some(err);
+            }
+        }
+    }
+    match last_err {
+        Some(err) => Err(err.into()),
+        None => Err(format!("{} has no usable addresses", address).into()),
+    }
+}
+
+/// Apply the TCP options of the config to a new connection.
+#[cfg(feature = "net")]
+fn configure_stream(stream: &TcpStream, config: &MonitorConfig) -> Result<()> {
+    stream.set_nodelay(config.nodelay)?;
+    if let Some(time) = config.tcp_keepalive {
+        #[allow(unused_mut)]
+        let mut keepalive = socket2::TcpKeepalive::new().with_time(time);
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            windows
+        ))]
+        if let Some(interval) = config.tcp_keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
         }
+        socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ChatEvent {
-    pub kind: ChatKind,
-    pub from: String,
-    pub to: Option<String>,
-    pub message: String,
+/// While connected to a fallback address, check every `interval` whether the primary
+/// address accepts connections again, and if so, have the fallback connection end.
+/// Each refusal doubles the wait before the next check, up to `MAX_FAILBACK_BACKOFF`
+/// intervals. Checks use the connect timeout of the config, bounded by the interval.
+/// The probe stops once the returned Sender is dropped.
+#[cfg(feature = "net")]
+fn spawn_failback_probe(
+    primary: &MonitorEndpoint,
+    interval: Duration,
+    config: &MonitorConfig,
+    shared: &Arc<Shared>,
+) -> Sender<()> {
+    let (tx, rx) = mpsc::channel::<()>();
+    let address = primary.address.clone();
+    let mut config = config.clone();
+    config.connect_timeout = Some(config.connect_timeout.map_or(interval, |t| t.min(interval)));
+    let shared = shared.clone();
+    thread::spawn(move || {
+        let mut refusals = 0;
+        while let Err(RecvTimeoutError::Timeout) =
+            rx.recv_timeout(failback_delay(interval, refusals))
+        {
+            match connect(&address, &config) {
+                Ok(_) => {
+                    debug!("Primary monitor address {} accepts connections", address);
+                    shared.fail_back.store(true, Ordering::Release);
+                    return;
+                }
+                Err(err) => {
+                    debug!("Primary monitor address {} still down: {}", address, err);
+                    refusals += 1;
+                }
+            }
+        }
+    });
+    tx
 }
-impl ChatEvent {
-    fn parse(line: &str) -> Result<Self> {
-        // chat [<kind>] <from>: <message...>
-        // chat [<kind>] <from> (to <to>): <message...>
-        const PATTERN: &str = r"^chat \[(.+?)\] (.+?)(?: \(to (.+)\))?: (.*)$";
-        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN).unwrap());
 
-        let captures = REGEX.captures(line).ok_or("Malformed")?;
-        let kind = captures[1].parse()?;
-        let from = captures[2].to_string();
-        let to = captures.get(3).map(|m| m.as_str().to_string());
-        let message = captures[4].to_string();
-        Ok(Self {
-            kind,
-            from,
-            to,
-            message,
-        })
-    }
+/// Get how long to wait before checking a primary address again after it refused the
+/// given number of checks in a row.
+#[cfg(feature = "net")]
+fn failback_delay(interval: Duration, refusals: u32) -> Duration {
+    let backoff = 2u32.saturating_pow(refusals).min(MAX_FAILBACK_BACKOFF);
+    interval.saturating_mul(backoff)
 }
-impl Display for ChatEvent {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(
-            f,
-            "chat [{}] {}{}: {}",
-            self.kind,
-            self.from,
-            self.to
-                .as_ref()
-                .map(|to| format!(" (to {})", to))
-                .unwrap_or_default(),
-            self.message
-        )
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum BroadcastScope {
-    Local,
-    Channel,
-    Shard,
-    Global,
-}
-impl TryFrom<usize> for BroadcastScope {
-    type Error = Error;
-
-    fn try_from(value: usize) -> Result<Self> {
-        match value {
-            0 => Ok(Self::Local),
-            1 => Ok(Self::Channel),
-            2 => Ok(Self::Shard),
-            3 => Ok(Self::Global),
-            other => Err(format!("Unknown broadcast scope {}", other).into()),
-        }
-    }
-}
-impl From<BroadcastScope> for usize {
-    fn from(scope: BroadcastScope) -> usize {
-        match scope {
-            BroadcastScope::Local => 0,
-            BroadcastScope::Channel => 1,
-            BroadcastScope::Shard => 2,
-            BroadcastScope::Global => 3,
-        }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct BroadcastEvent {
-    pub scope: BroadcastScope,
-    pub announcement_type: usize,
-    pub duration_secs: usize,
-    pub from: String,
-    pub message: String,
-}
-impl BroadcastEvent {
-    fn parse(line: &str) -> Result<Self> {
-        // bcast <scope> <announcement_type> <duration> <from>: <message...>
-        const PATTERN: &str = r"^bcast (\d+) (\d+) (\d+) (.+?): (.*)$";
-        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN).unwrap());
-
-        let captures = REGEX.captures(line).ok_or("Malformed")?;
-        let scope = captures[1].parse::<usize>()?.try_into()?;
-        let announcement_type = captures[2].parse()?;
-        let duration_secs = captures[3].parse()?;
-        let from = captures[4].to_string();
-        let message = captures[5].to_string();
-        Ok(Self {
-            scope,
-            announcement_type,
-            duration_secs,
-            from,
-            message,
-        })
+
+/// Send the auth line and check the server's response.
+#[cfg(feature = "net")]
+fn authenticate(
+    stream: &TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    auth: &AuthHandshake,
+    config: &MonitorConfig,
+) -> Result<()> {
+    debug!("Authenticating with monitor");
+    // don't wait forever for a server that doesn't know about the handshake
+    stream.set_read_timeout(config.read_timeout.or(config.connect_timeout))?;
+    let mut writer = stream;
+    writer.write_all(format!("{}\n", auth.line).as_bytes())?;
+
+    let mut response = String::new();
+    if reader.read_line(&mut response)? == 0 {
+        return Err("Connection closed during authentication".into());
     }
+    let response = response.trim_end_matches(['\r', '\n']);
+    if response != auth.expected_response {
+        return Err(format!("Authentication rejected: {}", response).into());
+    }
+    stream.set_read_timeout(config.read_timeout)?;
+    Ok(())
 }
-impl Display for BroadcastEvent {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let scope_ord: usize = self.scope.clone().into();
-        write!(
-            f,
-            "bcast {} {} {} {}: {}",
-            scope_ord, self.announcement_type, self.duration_secs, self.from, self.message
-        )
-    }
-}
-
-const NO_SUBJECT_IDENTIFIER: &str = "No subject.";
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct EmailEvent {
-    pub from: String,
-    pub to: String,
-    pub subject: Option<String>,
-    pub body: Vec<String>,
-}
-impl EmailEvent {
-    fn parse(header: &str, body: Vec<String>) -> Result<Self> {
-        // email [Email] <from> (to <to>): <<subject>>
-        const PATTERN: &str = r"^email \[Email\] (.+?) \(to (.+?)\): <(.+)>$";
-        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN).unwrap());
-
-        let captures = REGEX.captures(header).ok_or("Malformed")?;
-        let from = captures[1].to_string();
-        let to = captures[2].to_string();
-        let subject = match captures[3].to_string().as_str() {
-            NO_SUBJECT_IDENTIFIER => None,
-            other => Some(other.to_string()),
+
+#[cfg(feature = "net")]
+fn listen(
+    endpoint: &MonitorEndpoint,
+    config: &MonitorConfig,
+    shared: &Shared,
+    callback: &dyn Fn(MonitorNotification),
+) -> Result<()> {
+    let address = endpoint.address.as_str();
+    let _span = connection_span(address);
+    info!("Connecting to monitor at {}", address);
+    shared.health.lock().unwrap().last_connect_attempt = Some(SystemTime::now());
+    let stream = connect(address, config)?;
+    stream.set_read_timeout(config.read_timeout)?;
+    let mut reader = BufReader::with_capacity(config.read_buffer_size, stream.try_clone()?);
+    if let Some(auth) = &config.auth {
+        authenticate(&stream, &mut reader, auth, config)?;
+    }
+    let writer = &shared.writer;
+    *writer.lock().unwrap() = Some(stream);
+    // shut down while connecting, before the stream could be closed
+    if shared.stopping.load(Ordering::Acquire) {
+        writer.lock().unwrap().take();
+        return Ok(());
+    }
+    // the server may have been upgraded since the last connection
+    shared.protocol_version.lock().unwrap().take();
+    shared.health.lock().unwrap().consecutive_failures = 0;
+    callback(MonitorNotification::Connected);
+    let previous = shared.endpoint.lock().unwrap().replace(endpoint.clone());
+    if previous.as_ref() != Some(endpoint) {
+        if !endpoint.is_primary() {
+            warn!("Failed over to fallback monitor address {}", address);
+        }
+        callback(MonitorNotification::EndpointChanged(endpoint.clone()));
+    }
+    let mut lines = FrameBuffer::default();
+    // reused for every line, so it grows to the longest line once instead of per line
+    let mut buf = Vec::new();
+    let mut in_frame = false;
+    // set after going over a limit, until the next begin line
+    let mut resyncing = false;
+    let report = |kind, line: &str| {
+        let violation = ProtocolViolation {
+            kind,
+            line: line.to_string(),
         };
-        Ok(Self {
-            from,
-            to,
-            subject,
-            body,
-        })
+        warn!("{}", violation);
+        callback(MonitorNotification::ProtocolViolation(violation));
+    };
+    let violation = |kind, line: &str| {
+        if config.strict {
+            report(kind, line);
+        }
+    };
+    // returns whether the connection should be restarted
+    let deliver = |mut update: MonitorUpdate| {
+        let now = SystemTime::now();
+        shared.health.lock().unwrap().last_frame = Some(now);
+        if !config.heartbeats && update.is_heartbeat() {
+            return shared.restart.swap(false, Ordering::AcqRel);
+        }
+        update.set_received_at(Some(now));
+        callback(MonitorNotification::Updated(Arc::new(update)));
+        shared.restart.swap(false, Ordering::AcqRel)
+    };
+    // called when the connection is lost, with the lines of the unfinished frame
+    let discard_frame = |lines: &[String]| {
+        if lines.is_empty() {
+            return;
+        }
+        warn!(
+            "Connection lost with {} lines of a frame received",
+            lines.len()
+        );
+        if config.partial_frames {
+            let version = shared.protocol_version.lock().unwrap().clone();
+            let mut stats = wire::ParseStats::default();
+            let mut update = wire::parse_lines_with_stats(
+                lines,
+                &config.parsers,
+                version.as_ref(),
+                &mut Vec::new(),
+                &mut stats,
+            );
+            shared.parse_stats.lock().unwrap().merge(stats);
+            update.set_received_at(Some(SystemTime::now()));
+            callback(MonitorNotification::PartialUpdate(Arc::new(update)));
+        }
+    };
+    let restart = || {
+        warn!("Restarting monitor connection after callback panic");
+        writer.lock().unwrap().take();
+        callback(MonitorNotification::Disconnected);
+    };
+    loop {
+        // the frame being received is finished before shutting down
+        shared.in_frame.store(in_frame, Ordering::SeqCst);
+        if !in_frame && shared.stopping.load(Ordering::SeqCst) {
+            writer.lock().unwrap().take();
+            callback(MonitorNotification::Disconnected);
+            return Ok(());
+        }
+        if !in_frame && shared.fail_back.swap(false, Ordering::AcqRel) {
+            info!("Primary monitor address is back, leaving {}", address);
+            writer.lock().unwrap().take();
+            callback(MonitorNotification::Disconnected);
+            return Ok(());
+        }
+        buf.clear();
+        let result = match config.max_line_length {
+            // one more byte than the limit, for the line ending
+            Some(max) => (&mut reader)
+                .take(max as u64 + 1)
+                .read_until(b'\n', &mut buf),
+            None => reader.read_until(b'\n', &mut buf),
+        };
+        match result {
+            Ok(n) if n > 0 => {}
+            Ok(_) => {
+                if !shared.stopping.load(Ordering::Acquire) {
+                    shared.record_error("Connection closed by server".to_string());
+                }
+                if in_frame {
+                    discard_frame(lines.lines());
+                }
+                writer.lock().unwrap().take();
+                callback(MonitorNotification::Disconnected);
+                return Ok(());
+            }
+            Err(err) => {
+                // includes read timeouts, which is how half-open connections are caught
+                warn!("Lost connection to monitor: {}", err);
+                shared.record_error(format!("Lost connection: {}", err));
+                if in_frame {
+                    discard_frame(lines.lines());
+                }
+                writer.lock().unwrap().take();
+                callback(MonitorNotification::Disconnected);
+                return Ok(());
+            }
+        }
+        if let Some(max) = config.max_line_length {
+            if buf.len() > max && buf.last() != Some(&b'\n') {
+                // skip the rest of the line without keeping it around
+                if let Err(err) = reader.skip_until(b'\n') {
+                    warn!("Lost connection to monitor: {}", err);
+                    shared.record_error(format!("Lost connection: {}", err));
+                    writer.lock().unwrap().take();
+                    callback(MonitorNotification::Disconnected);
+                    return Ok(());
+                }
+                buf.truncate(TRUNCATED_LINE_LEN);
+                report(ViolationKind::LineTooLong, &String::from_utf8_lossy(&buf));
+                in_frame = false;
+                lines.clear();
+                resyncing = true;
+                continue;
+            }
+        }
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+            // servers on Windows may end lines with CRLF
+            if config.lenient_line_endings && buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        }
+
+        let line = match std::str::from_utf8(&buf) {
+            Ok(line) => Cow::Borrowed(line),
+            Err(_) if config.lossy_utf8 => {
+                let line = String::from_utf8_lossy(&buf);
+                warn!("Invalid UTF-8 from monitor, decoded lossily: {}", line);
+                violation(ViolationKind::InvalidUtf8, &line);
+                callback(MonitorNotification::InvalidUtf8(line.to_string()));
+                line
+            }
+            Err(_) if config.strict => {
+                let line = String::from_utf8_lossy(&buf);
+                violation(ViolationKind::InvalidUtf8, &line);
+                continue;
+            }
+            Err(err) => {
+                warn!("Lost connection to monitor: {}", err);
+                shared.record_error(format!("Lost connection: {}", err));
+                if in_frame {
+                    discard_frame(lines.lines());
+                }
+                writer.lock().unwrap().take();
+                callback(MonitorNotification::Disconnected);
+                return Ok(());
+            }
+        };
+
+        if !in_frame {
+            if let Some(version) = wire::ProtocolVersion::parse(&line) {
+                info!("Monitor speaks protocol {}", version);
+                *shared.protocol_version.lock().unwrap() = Some(version.clone());
+                callback(MonitorNotification::ProtocolVersion(version));
+                continue;
+            }
+        }
+
+        // blank lines between frames keep some connections alive
+        if !in_frame && line.trim().is_empty() {
+            continue;
+        }
+
+        #[cfg(feature = "json")]
+        if config.framing.is_json_frame(&line, in_frame) {
+            let _span = frame_span(1);
+            match wire::parse_json_frame(&line) {
+                Ok(update) => {
+                    if deliver(update) {
+                        restart();
+                        return Ok(());
+                    }
+                }
+                Err(err) => {
+                    warn!("Bad JSON frame ({}): {}", err, line);
+                    violation(ViolationKind::InvalidJsonFrame, &line);
+                }
+            }
+            continue;
+        }
+
+        if wire::is_keyword(&line, "begin", config.lenient_keywords) {
+            if in_frame {
+                violation(ViolationKind::NestedBegin, &line);
+            }
+            in_frame = true;
+            resyncing = false;
+            lines.clear();
+            continue;
+        }
+        if resyncing {
+            continue;
+        }
+
+        let Some(trailer) = wire::FrameTrailer::parse(&line, config.lenient_keywords) else {
+            if !in_frame && config.strict {
+                violation(ViolationKind::LineOutsideFrame, &line);
+                continue;
+            }
+            if config.max_frame_lines.is_some_and(|max| lines.len() >= max) {
+                report(
+                    ViolationKind::FrameTooLong,
+                    &format!("{} lines, last: {}", lines.len() + 1, line),
+                );
+                in_frame = false;
+                lines.clear();
+                resyncing = true;
+                continue;
+            }
+            lines.push(&line);
+            continue;
+        };
+
+        if !in_frame && config.strict {
+            violation(ViolationKind::EndWithoutBegin, &line);
+            continue;
+        }
+        in_frame = false;
+        if let Err(err) = trailer.verify(lines.lines()) {
+            warn!("{}", err);
+            if config.verify_frames {
+                callback(MonitorNotification::IntegrityFailure(err));
+                continue;
+            }
+        }
+
+        let _span = frame_span(lines.len());
+        let mut violations = Vec::new();
+        let mut stats = wire::ParseStats::default();
+        let version = shared.protocol_version.lock().unwrap().clone();
+        let update = wire::parse_lines_with_stats(
+            lines.lines(),
+            &config.parsers,
+            version.as_ref(),
+            &mut violations,
+            &mut stats,
+        );
+        shared.parse_stats.lock().unwrap().merge(stats);
+        shared.health.lock().unwrap().buffer_capacity = lines.capacity() + buf.capacity();
+        for (kind, line) in violations {
+            violation(kind, &line);
+        }
+        if deliver(update) {
+            restart();
+            return Ok(());
+        }
     }
 }
-impl Display for EmailEvent {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(
-            f,
-            "email [Email] {} (to {}): <{}>",
-            self.from,
-            self.to,
-            self.subject.as_deref().unwrap_or(NO_SUBJECT_IDENTIFIER)
-        )?;
-        for line in &self.body {
-            write!(f, "\n\t{}", line)?;
-        }
-        write!(f, "\nendemail")
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct NameRequestEvent {
-    pub player_uid: u64,
-    pub requested_name: String,
-}
-impl NameRequestEvent {
-    fn parse(line: &str) -> Result<Self> {
-        // namereq <player_uid> <requested_name>
-        const PATTERN: &str = r"^namereq (\d+) (.+)$";
-        static REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN).unwrap());
-
-        let captures = REGEX.captures(line).ok_or("Malformed")?;
-        let player_uid = captures[1].parse()?;
-        let requested_name = captures[2].to_string();
-        Ok(Self {
-            player_uid,
-            requested_name,
-        })
+
+/// How frames are delimited and encoded on the wire.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Framing {
+    /// The classic text protocol: `begin`, one line per event, `end`.
+    Text,
+    /// One JSON object per line, each holding a whole frame. See `wire::parse_json_frame()`.
+    Json,
+    /// Detect the framing line by line: lines starting with `{` outside of
+    /// a text frame are JSON frames, anything else is the text protocol.
+    Auto,
+}
+#[cfg(all(feature = "json", feature = "net"))]
+impl Framing {
+    fn is_json_frame(&self, line: &str, in_text_frame: bool) -> bool {
+        match self {
+            Self::Text => false,
+            Self::Json => true,
+            Self::Auto => !in_text_frame && line.starts_with('{'),
+        }
     }
 }
-impl Display for NameRequestEvent {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "namereq {} {}", self.player_uid, self.requested_name)
+
+/// Which IP versions to connect over when an address resolves to several.
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpPreference {
+    /// Try every address in the order the resolver returned them.
+    Any,
+    /// Try IPv4 addresses first, then IPv6 addresses.
+    PreferIpv4,
+    /// Try IPv6 addresses first, then IPv4 addresses.
+    PreferIpv6,
+    /// Only try IPv4 addresses.
+    Ipv4Only,
+    /// Only try IPv6 addresses.
+    Ipv6Only,
+}
+#[cfg(feature = "net")]
+impl IpPreference {
+    fn allows(&self, addr: &SocketAddr) -> bool {
+        match self {
+            Self::Ipv4Only => addr.is_ipv4(),
+            Self::Ipv6Only => addr.is_ipv6(),
+            _ => true,
+        }
+    }
+
+    fn rank(&self, addr: &SocketAddr) -> u8 {
+        match self {
+            Self::PreferIpv4 => addr.is_ipv6() as u8,
+            Self::PreferIpv6 => addr.is_ipv4() as u8,
+            _ => 0,
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-#[non_exhaustive]
-pub enum Event {
-    Player(PlayerEvent),
-    Chat(ChatEvent),
-    Broadcast(BroadcastEvent),
-    Email(EmailEvent),
-    NameRequest(NameRequestEvent),
-}
-impl Display for Event {
+/// The line sent to authenticate with the monitor and the response expected back.
+#[cfg(feature = "net")]
+#[derive(Clone)]
+struct AuthHandshake {
+    line: String,
+    expected_response: String,
+}
+#[cfg(feature = "net")]
+impl fmt::Debug for AuthHandshake {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Self::Player(event) => write!(f, "{}", event),
-            Self::Chat(event) => write!(f, "{}", event),
-            Self::Broadcast(event) => write!(f, "{}", event),
-            Self::Email(event) => write!(f, "{}", event),
-            Self::NameRequest(event) => write!(f, "{}", event),
+        // the line holds the secret
+        f.debug_struct("AuthHandshake")
+            .field("line", &"<redacted>")
+            .field("expected_response", &self.expected_response)
+            .finish()
+    }
+}
+
+#[cfg(feature = "net")]
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    tcp_keepalive_interval: Option<Duration>,
+    nodelay: bool,
+    read_buffer_size: usize,
+    dedup: bool,
+    roster_events: bool,
+    anomalies: Option<anomaly::AnomalyDetector>,
+    sampler: Option<sampling::Sampler>,
+    restart_on_panic: bool,
+    strict: bool,
+    verify_frames: bool,
+    parsers: ParserRegistry,
+    lossy_utf8: bool,
+    lenient_line_endings: bool,
+    lenient_keywords: bool,
+    coalesce_interval: Option<Duration>,
+    #[cfg(feature = "json")]
+    framing: Framing,
+    ip_preference: IpPreference,
+    auth: Option<AuthHandshake>,
+    partial_frames: bool,
+    heartbeats: bool,
+    handler_pool: Option<pool::HandlerPool>,
+    max_line_length: Option<usize>,
+    max_frame_lines: Option<usize>,
+    fallback_addresses: Vec<String>,
+    failback_interval: Option<Duration>,
+    shutdown_timeout: Duration,
+    #[cfg(feature = "proxy")]
+    proxy: Option<proxy::Proxy>,
+}
+#[cfg(feature = "net")]
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(10)),
+            read_timeout: None,
+            tcp_keepalive: None,
+            tcp_keepalive_interval: None,
+            nodelay: false,
+            read_buffer_size: 8 * 1024,
+            dedup: false,
+            roster_events: false,
+            anomalies: None,
+            sampler: None,
+            restart_on_panic: false,
+            strict: false,
+            verify_frames: false,
+            parsers: ParserRegistry::default(),
+            lossy_utf8: false,
+            lenient_line_endings: true,
+            lenient_keywords: true,
+            coalesce_interval: None,
+            #[cfg(feature = "json")]
+            framing: Framing::Text,
+            ip_preference: IpPreference::Any,
+            auth: None,
+            partial_frames: false,
+            heartbeats: true,
+            handler_pool: None,
+            max_line_length: None,
+            max_frame_lines: None,
+            fallback_addresses: Vec::new(),
+            failback_interval: Some(Duration::from_secs(30)),
+            shutdown_timeout: Duration::from_secs(10),
+            #[cfg(feature = "proxy")]
+            proxy: None,
         }
     }
 }
+#[cfg(feature = "net")]
+impl MonitorConfig {
+    /// Create a MonitorConfig with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how long to wait for the connection to the monitor to be established.
+    /// `None` waits indefinitely. Defaults to 10 seconds.
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set how long a read from the monitor may block before the connection is
+    /// considered dead. Should be comfortably longer than the server's update interval.
+    /// `None` waits indefinitely. Defaults to `None`.
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Send TCP keepalive probes once the connection has been idle for the given time,
+    /// so connections silently dropped by a NAT or firewall are noticed, and idle ones
+    /// aren't dropped in the first place. `None` leaves keepalive off. Defaults to `None`.
+    pub fn with_tcp_keepalive(mut self, idle: Option<Duration>) -> Self {
+        self.tcp_keepalive = idle;
+        self
+    }
+
+    /// Set the time between unanswered keepalive probes. `None` uses the OS default,
+    /// which is often over a minute. Only has an effect with `with_tcp_keepalive()`,
+    /// and is ignored on platforms that don't support it. Defaults to `None`.
+    pub fn with_tcp_keepalive_interval(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive_interval = interval;
+        self
+    }
+
+    /// Disable Nagle's algorithm, so commands are sent right away instead of being
+    /// batched with later writes. Defaults to `false`.
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Set the size of the buffer the connection is read through. Larger buffers
+    /// mean fewer reads on servers sending large frames. Defaults to 8 KiB.
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size.max(1);
+        self
+    }
+
+    /// Drop updates that are identical to the one received just before them,
+    /// e.g. when the server resends its most recent frame after a reconnect.
+    /// Updates are compared by their events, server time and metadata, or only by
+    /// `MonitorUpdate::server_sequence()` when the server numbers its frames.
+    /// A `DuplicateSuppressed` notification is sent in place of each dropped update.
+    /// Defaults to `false`.
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Send `PlayerJoined` and `PlayerLeft` notifications for the players that appear in
+    /// or disappear from each update, compared to the update delivered before it.
+    /// Every player of the first update counts as joining. The roster is kept across
+    /// reconnects, so a reconnect doesn't report everyone leaving and joining again.
+    /// Defaults to `false`.
+    pub fn with_roster_events(mut self, roster_events: bool) -> Self {
+        self.roster_events = roster_events;
+        self
+    }
 
-fn get_first_token(line: &str) -> Option<&str> {
-    line.split_whitespace().next()
+    /// Check the position of every player of each update with the given AnomalyDetector,
+    /// sending an `Anomaly` notification for every player out of bounds or moving too
+    /// fast. Positions are kept across reconnects, like the roster. Off by default.
+    pub fn with_anomaly_detection(mut self, detector: anomaly::AnomalyDetector) -> Self {
+        self.anomalies = Some(detector);
+        self
+    }
+
+    /// Keep only the events of each update chosen by the given Sampler, before updates
+    /// are buffered or reach the callback and subscribers. Deduplication still sees every
+    /// event, but roster events, anomaly detection and anything else tracking players
+    /// from the updates delivered only see the players kept. Off by default.
+    pub fn with_sampling(mut self, sampler: sampling::Sampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Choose what happens after the user callback or the listen thread panics.
+    /// Panics are always caught and reported by `Monitor::has_panicked()`.
+    /// If `true`, the connection is re-established and the callback keeps being invoked.
+    /// If `false`, a panicking callback is no longer invoked and a panicking listen thread exits.
+    /// Defaults to `false`.
+    pub fn with_restart_on_panic(mut self, restart_on_panic: bool) -> Self {
+        self.restart_on_panic = restart_on_panic;
+        self
+    }
+
+    /// Validate the structure of incoming frames and send a `ProtocolViolation`
+    /// notification for each problem found, instead of silently working around it.
+    /// Useful for testing a server's monitor output.
+    /// Defaults to `false`.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Drop frames that don't match the line count or checksum of their `end` line,
+    /// and send an `IntegrityFailure` notification for each of them. Frames with a plain
+    /// `end` line can't be verified and are delivered as usual. See the `wire` module for
+    /// the fields. Otherwise, mismatches are only logged. Defaults to `false`.
+    pub fn with_verify_frames(mut self, verify_frames: bool) -> Self {
+        self.verify_frames = verify_frames;
+        self
+    }
+
+    /// Decode lines that aren't valid UTF-8 lossily, replacing invalid sequences
+    /// with U+FFFD, and send an `InvalidUtf8` notification for each of them.
+    /// Otherwise, such lines are dropped in strict mode and end the connection in permissive mode.
+    /// Defaults to `false`.
+    pub fn with_lossy_utf8(mut self, lossy_utf8: bool) -> Self {
+        self.lossy_utf8 = lossy_utf8;
+        self
+    }
+
+    /// Accept CRLF line endings in addition to LF.
+    /// If `false`, a trailing carriage return is kept as part of the line,
+    /// so frame delimiters and events on CRLF-terminated lines are not recognized.
+    /// Defaults to `true`.
+    pub fn with_lenient_line_endings(mut self, lenient: bool) -> Self {
+        self.lenient_line_endings = lenient;
+        self
+    }
+
+    /// Accept the `begin` and `end` frame keywords in any case and with surrounding
+    /// whitespace, as sent by some modified servers.
+    /// If `false`, only the exact lowercase keywords start and end frames.
+    /// Defaults to `true`.
+    pub fn with_lenient_keywords(mut self, lenient: bool) -> Self {
+        self.lenient_keywords = lenient;
+        self
+    }
+
+    /// Deliver at most one update per `interval`, merging the updates received in between.
+    /// Merged updates contain every event of the updates they replace, except that
+    /// each player only appears once, at their latest position.
+    /// `None` delivers every update as it is received. Defaults to `None`.
+    pub fn with_coalesce_interval(mut self, interval: Option<Duration>) -> Self {
+        self.coalesce_interval = interval;
+        self
+    }
+
+    /// Drop lines longer than the given number of bytes, not counting the line ending,
+    /// without buffering them, so a misbehaving server can't exhaust memory.
+    /// The frame the line is in is dropped, and lines are skipped until the next `begin`.
+    /// JSON frames are single lines, so this also limits their size.
+    /// `None` allows lines of any length. Defaults to `None`.
+    pub fn with_max_line_length(mut self, max_line_length: Option<usize>) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Drop frames with more than the given number of lines, counting each line of
+    /// multi-line events. Lines are skipped until the next `begin`.
+    /// `None` allows frames of any size. Defaults to `None`.
+    pub fn with_max_frame_lines(mut self, max_frame_lines: Option<usize>) -> Self {
+        self.max_frame_lines = max_frame_lines;
+        self
+    }
+
+    /// Deliver the events of a frame cut short by a lost connection in a
+    /// `PartialUpdate` notification, instead of discarding them.
+    /// Defaults to `false`.
+    pub fn with_partial_frames(mut self, partial_frames: bool) -> Self {
+        self.partial_frames = partial_frames;
+        self
+    }
+
+    /// Deliver heartbeat frames (see `MonitorUpdate::is_heartbeat()`) like any other.
+    /// Without, they only count towards `MonitorHealth::last_frame`, so pollers and
+    /// callbacks aren't woken for them. Empty frames, sent once nobody is online, are
+    /// always delivered. Defaults to `true`.
+    pub fn with_heartbeats(mut self, heartbeats: bool) -> Self {
+        self.heartbeats = heartbeats;
+        self
+    }
+
+    /// Handle notifications (deduplication, coalescing, the callback and subscribers)
+    /// on a thread of the given pool instead of the thread reading from the connection,
+    /// so slow handlers don't delay reads. Notifications are still handled in order.
+    /// With a pool, a restart after a callback panic happens on the next frame received.
+    /// Defaults to handling them on the reading thread.
+    pub fn with_handler_pool(mut self, pool: pool::HandlerPool) -> Self {
+        self.handler_pool = Some(pool);
+        self
+    }
+
+    /// Set which resolved addresses of a hostname are tried, and in which order.
+    /// Defaults to `IpPreference::Any`.
+    pub fn with_ip_preference(mut self, preference: IpPreference) -> Self {
+        self.ip_preference = preference;
+        self
+    }
+
+    /// Authenticate with the given token after connecting, for monitor ports behind
+    /// a token handshake. The line `auth <token>` is sent and the server has to
+    /// answer `ok` before the connection is reported as `Connected`.
+    /// A rejected handshake is treated like a failed connection attempt.
+    pub fn with_auth_token(self, token: &str) -> Self {
+        self.with_auth_handshake(&format!("auth {}", token), "ok")
+    }
+
+    /// Authenticate by sending the given line after connecting and expecting
+    /// the given response, for servers with a handshake other than `with_auth_token()`'s.
+    pub fn with_auth_handshake(mut self, line: &str, expected_response: &str) -> Self {
+        self.auth = Some(AuthHandshake {
+            line: line.to_string(),
+            expected_response: expected_response.to_string(),
+        });
+        self
+    }
+
+    /// Fall back to the given addresses, in order, when the Monitor's own address can't
+    /// be connected to, e.g. for mirrored relays. Each lost connection starts over from
+    /// the Monitor's own address, and every address is tried before waiting to retry.
+    /// Defaults to no fallbacks.
+    pub fn with_fallback_addresses(mut self, addresses: &[&str]) -> Self {
+        self.fallback_addresses = addresses
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+        self
+    }
+
+    /// Set how often the Monitor's own address is checked while connected to a fallback.
+    /// Once it accepts connections again, the fallback connection is closed at the end
+    /// of the frame being received and the Monitor connects back to its own address.
+    /// While it keeps refusing, each check doubles the wait before the next one, up to 16
+    /// times the interval. `None` stays on the fallback until its connection is lost.
+    /// Defaults to 30 seconds.
+    pub fn with_failback_interval(mut self, interval: Option<Duration>) -> Self {
+        self.failback_interval = interval;
+        self
+    }
+
+    /// Set how long `Monitor::shutdown()` waits for the Monitor thread to stop, e.g. while
+    /// it's connecting or in a slow callback, before leaving it to stop in the background.
+    /// Defaults to 10 seconds.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Connect through the given proxy, and have it resolve hostnames. The connect
+    /// timeout covers connecting to the proxy and, separately, its handshake.
+    /// Defaults to connecting directly.
+    #[cfg(feature = "proxy")]
+    pub fn with_proxy(mut self, proxy: proxy::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Set the framing the server sends frames with. Defaults to `Framing::Text`.
+    #[cfg(feature = "json")]
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Parse lines whose first token is `token` with the given parser,
+    /// producing an `Event::Custom` holding the parsed value.
+    /// Registered parsers take precedence over the built-in ones,
+    /// so forks that changed the format of a built-in event can parse it too.
+    pub fn register_parser<T, F>(mut self, token: &str, parser: F) -> Self
+    where
+        T: Any + Send + Sync,
+        F: Fn(&str) -> Result<T> + Send + Sync + 'static,
+    {
+        self.parsers.register(token, parser);
+        self
+    }
 }
 
-fn listen(addr: SocketAddr, callback: Arc<MonitorNotificationCallback>) -> Result<()> {
-    info!("Connecting to monitor at {}", addr);
-    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(10))?;
-    callback(MonitorNotification::Connected);
-    let mut reader = BufReader::new(stream);
-    let mut lines = Vec::new();
-    loop {
-        let mut line = String::new();
-        if !reader.read_line(&mut line).is_ok_and(|n| n > 0) {
-            callback(MonitorNotification::Disconnected);
-            return Ok(());
+/// A snapshot of the state of a Monitor's connection, as returned by `Monitor::health()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorHealth {
+    pub connected: bool,
+    /// When the Monitor last tried to connect.
+    pub last_connect_attempt: Option<SystemTime>,
+    /// When the Monitor last received a complete frame.
+    pub last_frame: Option<SystemTime>,
+    /// Number of failed connection attempts since the last successful one.
+    pub consecutive_failures: u32,
+    /// The last error the connection ran into, whether or not it has recovered since.
+    pub last_error: Option<String>,
+    /// Bytes allocated for reading frames, which are reused from one frame to the next.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub buffer_capacity: usize,
+}
+
+/// An address a Monitor connects to, as reported by `MonitorNotification::EndpointChanged`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorEndpoint {
+    pub address: String,
+    /// 0 for the Monitor's own address, and 1 onwards for the fallback addresses in order.
+    pub priority: usize,
+}
+impl MonitorEndpoint {
+    /// Check if this is the Monitor's own address rather than a fallback.
+    pub fn is_primary(&self) -> bool {
+        self.priority == 0
+    }
+}
+
+/// State shared between a Monitor and its listen thread.
+#[cfg(feature = "net")]
+#[derive(Default)]
+struct Shared {
+    connected: AtomicBool,
+    panicked: AtomicBool,
+    restart: AtomicBool,
+    stopping: AtomicBool,
+    in_frame: AtomicBool,
+    fail_back: AtomicBool,
+    endpoint: Mutex<Option<MonitorEndpoint>>,
+    last_update: Mutex<Option<Arc<MonitorUpdate>>>,
+    taps: Mutex<Vec<NotificationTap>>,
+    writer: Mutex<Option<TcpStream>>,
+    health: Mutex<MonitorHealth>,
+    protocol_version: Mutex<Option<wire::ProtocolVersion>>,
+    parse_stats: Mutex<wire::ParseStats>,
+    /// Wakes the listen thread up from waiting to reconnect once `stopping` is set.
+    wake: Condvar,
+    waiting: Mutex<()>,
+}
+#[cfg(feature = "net")]
+impl Shared {
+    /// Wait for the given time, or until the Monitor is stopped.
+    fn wait(&self, timeout: Duration) {
+        let waiting = self.waiting.lock().unwrap();
+        let _ = self
+            .wake
+            .wait_timeout_while(waiting, timeout, |_| !self.stopping.load(Ordering::SeqCst));
+    }
+
+    /// Stop the Monitor, cutting any wait short.
+    fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // taking the lock makes sure waiters either see the flag or get the notification
+        let _waiting = self.waiting.lock().unwrap();
+        self.wake.notify_all();
+    }
+
+    fn health(&self) -> MonitorHealth {
+        MonitorHealth {
+            connected: self.connected.load(Ordering::Acquire),
+            ..self.health.lock().unwrap().clone()
         }
-        line.pop(); // remove newline
+    }
 
-        if line == "begin" {
-            lines.clear();
-            continue;
+    fn record_error(&self, error: String) {
+        self.health.lock().unwrap().last_error = Some(error);
+    }
+}
+
+/// The lines of the frame being received. Clearing it keeps the Strings of the lines,
+/// so each frame copies its lines into the allocations of the previous frames'.
+#[cfg(feature = "net")]
+#[derive(Default)]
+struct FrameBuffer {
+    lines: Vec<String>,
+    len: usize,
+}
+#[cfg(feature = "net")]
+impl FrameBuffer {
+    fn push(&mut self, line: &str) {
+        match self.lines.get_mut(self.len) {
+            Some(slot) => {
+                slot.clear();
+                slot.push_str(line);
+            }
+            None => self.lines.push(line.to_string()),
         }
+        self.len += 1;
+    }
 
-        if line != "end" {
-            lines.push(line);
-            continue;
+    fn clear(&mut self) {
+        // let go of what's left over from a frame much bigger than the last one
+        if self.lines.len() > 2 * self.len.max(MIN_FRAME_BUFFER_LINES) {
+            self.lines.truncate(self.len);
+            self.lines.shrink_to_fit();
         }
+        self.len = 0;
+    }
+
+    fn lines(&self) -> &[String] {
+        &self.lines[..self.len]
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
 
-        let update = MonitorUpdate::from_lines(&mut lines);
-        callback(MonitorNotification::Updated(update));
+    /// Get the number of bytes allocated for lines.
+    fn capacity(&self) -> usize {
+        self.lines.capacity() * std::mem::size_of::<String>()
+            + self.lines.iter().map(String::capacity).sum::<usize>()
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct MonitorUpdate {
-    events: Vec<Event>,
+#[cfg(feature = "net")]
+#[derive(Default)]
+struct Coalescer {
+    pending: Option<MonitorUpdate>,
+    last_delivery: Option<Instant>,
+}
+
+/// Turns the notifications produced by `listen()` into what the user sees.
+#[cfg(feature = "net")]
+struct Pipeline {
+    shared: Arc<Shared>,
+    tx: Sender<Arc<MonitorUpdate>>,
+    user_callback: Option<MonitorNotificationCallback>,
+    dedup: bool,
+    roster_events: bool,
+    restart_on_panic: bool,
+    coalesce_interval: Option<Duration>,
+    coalescer: Mutex<Coalescer>,
+    last_hash: Mutex<Option<u64>>,
+    /// The last update delivered, to diff the next one against for roster events.
+    last_roster: Mutex<Option<Arc<MonitorUpdate>>>,
+    anomalies: Option<Mutex<anomaly::AnomalyDetector>>,
+    sampler: Option<Mutex<sampling::Sampler>>,
+    callback_disabled: AtomicBool,
+    next_sequence: AtomicU64,
+    next_event_sequence: AtomicU64,
 }
-impl MonitorUpdate {
-    /// Decompose the MonitorUpdate into a Vec of Events
-    pub fn get_events(self) -> Vec<Event> {
-        self.events
+#[cfg(feature = "net")]
+impl Drop for Pipeline {
+    /// Once nothing is left to deliver, end the subscriptions of any MonitorHandle
+    /// still around.
+    fn drop(&mut self) {
+        self.shared.connected.store(false, Ordering::Release);
+        self.shared.taps.lock().unwrap().clear();
+    }
+}
+#[cfg(feature = "net")]
+impl Pipeline {
+    fn process(&self, notification: MonitorNotification) {
+        for notification in self.coalesce(notification) {
+            // before the roster keeps a reference, so updates are changed in place
+            let notification = self.prepare(notification);
+            let roster_changes = self.roster_changes(&notification);
+            let anomalies = self.anomalies(&notification);
+            self.deliver(notification);
+            for change in roster_changes.into_iter().chain(anomalies) {
+                self.deliver(change);
+            }
+        }
     }
 
-    /// Get the number of players online in the MonitorUpdate.
-    /// This value is equal to the number of PlayerEvents in the update.
-    pub fn get_player_count(&self) -> usize {
-        self.events
-            .iter()
-            .filter(|event| matches!(event, Event::Player(_)))
-            .count()
-    }
-
-    /// Add an Event to the MonitorUpdate.
-    pub fn add_event(&mut self, event: Event) {
-        self.events.push(event);
-    }
-
-    /// Parse a MonitorUpdate from a vector of lines.
-    /// The lines are consumed and removed from the input vector.
-    pub fn from_lines(lines: &mut Vec<String>) -> Self {
-        let mut events = Vec::new();
-        while !lines.is_empty() {
-            let first_line = lines.remove(0);
-            let event = match get_first_token(&first_line) {
-                Some("player") => match PlayerEvent::parse(&first_line) {
-                    Ok(event) => Event::Player(event),
-                    Err(err) => {
-                        warn!("Bad player event ({}): {}", err, first_line);
-                        continue;
-                    }
-                },
-                Some("chat") => match ChatEvent::parse(&first_line) {
-                    Ok(event) => Event::Chat(event),
-                    Err(err) => {
-                        warn!("Bad chat event ({}): {}", err, first_line);
-                        continue;
-                    }
-                },
-                Some("bcast") => match BroadcastEvent::parse(&first_line) {
-                    Ok(event) => Event::Broadcast(event),
-                    Err(err) => {
-                        warn!("Bad broadcast event ({}): {}", err, first_line);
-                        continue;
-                    }
-                },
-                Some("email") => {
-                    // next lines with tabs at the beginning are part of the email body
-                    let mut body = Vec::new();
-                    while !lines.is_empty() && lines[0].starts_with('\t') {
-                        body.push(lines.remove(0).trim_start().to_string());
-                    }
-                    if lines.is_empty() || !lines[0].starts_with("endemail") {
-                        warn!("Malformed email event (no endemail)");
-                        continue;
-                    }
-                    lines.remove(0); // remove endemail
-                    match EmailEvent::parse(&first_line, body) {
-                        Ok(event) => Event::Email(event),
-                        Err(err) => {
-                            warn!("Bad email event header ({}): {}", err, first_line);
-                            continue;
-                        }
-                    }
-                }
-                Some("namereq") => match NameRequestEvent::parse(&first_line) {
-                    Ok(event) => Event::NameRequest(event),
-                    Err(err) => {
-                        warn!("Bad name request event ({}): {}", err, first_line);
-                        continue;
+    fn roster_changes(&self, notification: &MonitorNotification) -> Vec<MonitorNotification> {
+        let MonitorNotification::Updated(update) = notification else {
+            return Vec::new();
+        };
+        if !self.roster_events {
+            return Vec::new();
+        }
+        let previous = self
+            .last_roster
+            .lock()
+            .unwrap()
+            .replace(update.clone())
+            .unwrap_or_default();
+        let diff = update.diff(&previous);
+        let joined = diff
+            .added
+            .into_iter()
+            .map(|player| MonitorNotification::PlayerJoined(player.name));
+        let left = diff
+            .removed
+            .into_iter()
+            .map(|player| MonitorNotification::PlayerLeft(player.name));
+        joined.chain(left).collect()
+    }
+
+    fn anomalies(&self, notification: &MonitorNotification) -> Vec<MonitorNotification> {
+        let (MonitorNotification::Updated(update), Some(anomalies)) =
+            (notification, &self.anomalies)
+        else {
+            return Vec::new();
+        };
+        anomalies
+            .lock()
+            .unwrap()
+            .update(update)
+            .into_iter()
+            .map(MonitorNotification::Anomaly)
+            .collect()
+    }
+
+    fn coalesce(&self, notification: MonitorNotification) -> Vec<MonitorNotification> {
+        let Some(interval) = self.coalesce_interval else {
+            return vec![notification];
+        };
+        let mut coalescer = self.coalescer.lock().unwrap();
+        match notification {
+            MonitorNotification::Updated(update) => {
+                let update = Arc::unwrap_or_clone(update);
+                let merged = match coalescer.pending.take() {
+                    Some(mut pending) => {
+                        pending.coalesce_with(update);
+                        pending
                     }
-                },
-                Some(_) => {
-                    warn!("Unknown event: {}", first_line);
-                    continue;
-                }
-                None => {
-                    warn!("Empty line in monitor update");
-                    continue;
+                    None => update,
+                };
+                let due = coalescer
+                    .last_delivery
+                    .is_none_or(|last| last.elapsed() >= interval);
+                if !due {
+                    coalescer.pending = Some(merged);
+                    return Vec::new();
                 }
-            };
-            events.push(event);
+                coalescer.last_delivery = Some(Instant::now());
+                vec![MonitorNotification::Updated(Arc::new(merged))]
+            }
+            MonitorNotification::Disconnected => {
+                // don't sit on events from a connection that's gone
+                let mut notifications: Vec<_> = coalescer
+                    .pending
+                    .take()
+                    .map(|pending| MonitorNotification::Updated(Arc::new(pending)))
+                    .into_iter()
+                    .collect();
+                notifications.push(MonitorNotification::Disconnected);
+                notifications
+            }
+            other => vec![other],
         }
-        Self { events }
     }
-}
-impl Display for MonitorUpdate {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        writeln!(f, "begin")?;
-        for event in &self.events {
-            writeln!(f, "{}", event)?;
+
+    fn deduplicate(&self, update: Arc<MonitorUpdate>) -> MonitorNotification {
+        // identical updates hash the same within the same process
+        let mut hasher = DefaultHasher::new();
+        match update.server_sequence() {
+            Some(sequence) => sequence.hash(&mut hasher),
+            None => {
+                update.events().hash(&mut hasher);
+                update.server_time().hash(&mut hasher);
+                let mut metadata: Vec<_> = update.metadata().iter().collect();
+                metadata.sort_unstable();
+                metadata.hash(&mut hasher);
+            }
+        }
+        let hash = hasher.finish();
+        if self.last_hash.lock().unwrap().replace(hash) == Some(hash) {
+            debug!("Suppressing duplicate monitor update");
+            MonitorNotification::DuplicateSuppressed
+        } else {
+            MonitorNotification::Updated(update)
+        }
+    }
+
+    /// Deduplicate, sample and number an update.
+    fn prepare(&self, notification: MonitorNotification) -> MonitorNotification {
+        let notification = match notification {
+            MonitorNotification::Updated(update) if self.dedup => self.deduplicate(update),
+            other => other,
+        };
+        let notification = match (notification, &self.sampler) {
+            (MonitorNotification::Updated(mut update), Some(sampler)) => {
+                sampler.lock().unwrap().apply(Arc::make_mut(&mut update));
+                MonitorNotification::Updated(update)
+            }
+            (other, _) => other,
+        };
+        match notification {
+            MonitorNotification::Updated(mut update) => {
+                // not shared with anyone yet, so this doesn't copy
+                let update_mut = Arc::make_mut(&mut update);
+                let event_count = update_mut.events().len() as u64;
+                update_mut.set_sequence(
+                    self.next_sequence.fetch_add(1, Ordering::Relaxed),
+                    self.next_event_sequence
+                        .fetch_add(event_count, Ordering::Relaxed),
+                );
+                MonitorNotification::Updated(update)
+            }
+            other => other,
+        }
+    }
+
+    fn deliver(&self, notification: MonitorNotification) {
+        let shared = &self.shared;
+        match &notification {
+            MonitorNotification::Connected => shared.connected.store(true, Ordering::Release),
+            MonitorNotification::Updated(update) => {
+                *shared.last_update.lock().unwrap() = Some(update.clone());
+                if self.user_callback.is_none() {
+                    // don't buffer if user is handling updates
+                    let _ = self.tx.send(update.clone());
+                }
+            }
+            MonitorNotification::DuplicateSuppressed
+            | MonitorNotification::ProtocolViolation(_)
+            | MonitorNotification::InvalidUtf8(_)
+            | MonitorNotification::PartialUpdate(_)
+            | MonitorNotification::ProtocolVersion(_)
+            | MonitorNotification::EndpointChanged(_)
+            | MonitorNotification::IntegrityFailure(_)
+            | MonitorNotification::PlayerJoined(_)
+            | MonitorNotification::PlayerLeft(_)
+            | MonitorNotification::Anomaly(_)
+            | MonitorNotification::Reconnecting { .. } => {}
+            MonitorNotification::Disconnected => shared.connected.store(false, Ordering::Release),
+        }
+        shared
+            .taps
+            .lock()
+            .unwrap()
+            .retain_mut(|tap| tap(&notification));
+
+        let Some(cb) = &self.user_callback else {
+            return;
+        };
+        if self.callback_disabled.load(Ordering::Acquire) {
+            return;
+        }
+        if panic::catch_unwind(AssertUnwindSafe(|| cb(notification))).is_err() {
+            error!("Monitor callback panicked");
+            shared.record_error("Monitor callback panicked".to_string());
+            shared.panicked.store(true, Ordering::Release);
+            if self.restart_on_panic {
+                shared.restart.store(true, Ordering::Release);
+            } else {
+                warn!("Monitor callback disabled");
+                self.callback_disabled.store(true, Ordering::Release);
+            }
         }
-        writeln!(f, "end")
     }
 }
 
+#[cfg(feature = "net")]
 pub struct Monitor {
-    handle: JoinHandle<()>,
-    rx: Receiver<MonitorUpdate>,
-    connected: Arc<AtomicBool>,
-    last_update: Arc<Mutex<Option<MonitorUpdate>>>,
+    thread: JoinHandle<()>,
+    rx: Receiver<Arc<MonitorUpdate>>,
+    shutdown_timeout: Duration,
+    inner: MonitorHandle,
 }
+#[cfg(feature = "net")]
 impl Monitor {
     /// Create a new Monitor instance that connects to the given address.
     /// Updates are buffered and can be pulled with `poll()`.
     pub fn new(address: &str) -> Result<Self> {
-        Self::new_internal(address, None)
+        Self::new_internal(address, MonitorConfig::default(), None)
     }
 
     /// Create a new Monitor instance that connects to the given address.
     /// Updates are passed to the given callback and not buffered.
     pub fn new_with_callback(address: &str, callback: MonitorNotificationCallback) -> Result<Self> {
-        Self::new_internal(address, Some(callback))
+        Self::new_internal(address, MonitorConfig::default(), Some(callback))
+    }
+
+    /// Create a new Monitor instance that connects to the given address with the given settings.
+    /// Updates are buffered and can be pulled with `poll()`.
+    pub fn new_with_config(address: &str, config: MonitorConfig) -> Result<Self> {
+        Self::new_internal(address, config, None)
+    }
+
+    /// Create a new Monitor instance that connects to the given address with the given settings.
+    /// Updates are passed to the given callback and not buffered.
+    pub fn new_with_config_and_callback(
+        address: &str,
+        config: MonitorConfig,
+        callback: MonitorNotificationCallback,
+    ) -> Result<Self> {
+        Self::new_internal(address, config, Some(callback))
     }
 
     fn new_internal(
         address: &str,
+        config: MonitorConfig,
         user_callback: Option<MonitorNotificationCallback>,
     ) -> Result<Self> {
         info!("ffmonitor v{}", env!("CARGO_PKG_VERSION"));
-        let address: SocketAddr = address.parse()?;
+        validate_address(address)?;
+        let mut endpoints = vec![address.to_string()];
+        for fallback in &config.fallback_addresses {
+            validate_address(fallback)?;
+            endpoints.push(fallback.clone());
+        }
+        let endpoints: Vec<MonitorEndpoint> = endpoints
+            .into_iter()
+            .enumerate()
+            .map(|(priority, address)| MonitorEndpoint { address, priority })
+            .collect();
         let (tx, rx) = mpsc::channel();
-        let connected = Arc::new(AtomicBool::new(false));
-        let last_update = Arc::new(Mutex::new(None));
-
-        let conn = connected.clone();
-        let lu = last_update.clone();
-        let callback: Arc<MonitorNotificationCallback> = Arc::new(Box::new(move |notification| {
-            match notification.clone() {
-                MonitorNotification::Connected => conn.store(true, Ordering::Release),
-                MonitorNotification::Updated(update) => {
-                    *lu.lock().unwrap() = Some(update.clone());
-                    if user_callback.is_none() {
-                        // don't buffer if user is handling updates
-                        let _ = tx.send(update);
-                    }
-                }
-                MonitorNotification::Disconnected => conn.store(false, Ordering::Release),
-            }
-            if let Some(cb) = &user_callback {
-                cb(notification);
-            }
-        }));
+        let shared = Arc::new(Shared::default());
+        let restart_on_panic = config.restart_on_panic;
+        let shutdown_timeout = config.shutdown_timeout;
 
-        let handle = thread::spawn({
+        let pipeline = Arc::new(Pipeline {
+            shared: shared.clone(),
+            tx,
+            user_callback,
+            dedup: config.dedup,
+            roster_events: config.roster_events,
+            restart_on_panic,
+            coalesce_interval: config.coalesce_interval,
+            coalescer: Mutex::default(),
+            last_hash: Mutex::default(),
+            last_roster: Mutex::default(),
+            anomalies: config.anomalies.clone().map(Mutex::new),
+            sampler: config.sampler.clone().map(Mutex::new),
+            callback_disabled: AtomicBool::new(false),
+            next_sequence: AtomicU64::new(1),
+            next_event_sequence: AtomicU64::new(1),
+        });
+        let worker = config.handler_pool.as_ref().map(pool::HandlerPool::assign);
+
+        let sh = shared.clone();
+        let thread = thread::spawn({
+            let mut priority = 0;
             move || loop {
-                if let Err(err) = listen(address, callback.clone()) {
-                    error!("Couldn't connect to monitor: {}", err);
-                    thread::sleep(Duration::from_secs(1));
+                if sh.stopping.load(Ordering::Acquire) {
+                    return;
+                }
+                let notify = |notification| match &worker {
+                    Some(worker) => {
+                        let pipeline = pipeline.clone();
+                        // handler threads live as long as their senders, so this can't fail
+                        let _ = worker.send(Box::new(move || pipeline.process(notification)));
+                    }
+                    None => pipeline.process(notification),
+                };
+                let reconnecting = |next_retry_in| {
+                    let health = sh.health.lock().unwrap().clone();
+                    notify(MonitorNotification::Reconnecting {
+                        attempt: health.consecutive_failures + 1,
+                        last_error: health.last_error,
+                        next_retry_in,
+                    });
+                };
+                let endpoint = &endpoints[priority];
+                sh.fail_back.store(false, Ordering::Release);
+                let probe = match config.failback_interval {
+                    Some(interval) if !endpoint.is_primary() => {
+                        Some(spawn_failback_probe(&endpoints[0], interval, &config, &sh))
+                    }
+                    _ => None,
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    listen(endpoint, &config, &sh, &notify)
+                }));
+                // stops the probe
+                drop(probe);
+                sh.in_frame.store(false, Ordering::SeqCst);
+                if sh.stopping.load(Ordering::Acquire) {
+                    return;
+                }
+                match result {
+                    Ok(Ok(())) => {
+                        priority = 0;
+                        reconnecting(Duration::ZERO);
+                    }
+                    Ok(Err(err)) => {
+                        error!("Couldn't connect to monitor: {}", err);
+                        sh.record_error(format!("Couldn't connect: {}", err));
+                        sh.health.lock().unwrap().consecutive_failures += 1;
+                        priority = (priority + 1) % endpoints.len();
+                        // fail over right away, and wait once every address was tried
+                        let delay = match priority {
+                            0 => RETRY_DELAY,
+                            _ => Duration::ZERO,
+                        };
+                        reconnecting(delay);
+                        sh.wait(delay);
+                    }
+                    Err(_) => {
+                        error!("Monitor thread panicked");
+                        sh.record_error("Monitor thread panicked".to_string());
+                        sh.panicked.store(true, Ordering::Release);
+                        sh.writer.lock().unwrap().take();
+                        notify(MonitorNotification::Disconnected);
+                        if !restart_on_panic {
+                            return;
+                        }
+                        reconnecting(RETRY_DELAY);
+                        sh.wait(RETRY_DELAY);
+                    }
                 }
             }
         });
 
         Ok(Self {
-            handle,
+            thread,
             rx,
-            connected,
-            last_update,
+            shutdown_timeout,
+            inner: MonitorHandle {
+                address: address.to_string(),
+                shared,
+            },
         })
     }
 
+    /// Get a handle to check on the Monitor and subscribe to its updates from other
+    /// threads, e.g. to show the connection status in a GUI while a worker polls.
+    /// Handles can be cloned freely; only the Monitor itself can shut down.
+    pub fn handle(&self) -> MonitorHandle {
+        self.inner.clone()
+    }
+
     /// Check if the Monitor is connected to the feed.
     pub fn is_connected(&self) -> bool {
-        self.connected.load(Ordering::Acquire)
+        self.inner.is_connected()
+    }
+
+    /// Check if the user callback or the listen thread has panicked at any point.
+    /// See `MonitorConfig::with_restart_on_panic()` for what happens afterwards.
+    pub fn has_panicked(&self) -> bool {
+        self.inner.has_panicked()
+    }
+
+    /// Get the protocol version announced by the server on the current connection, if any.
+    /// Servers that don't announce one are assumed to support every feature.
+    pub fn protocol_version(&self) -> Option<wire::ProtocolVersion> {
+        self.inner.protocol_version()
+    }
+
+    /// Get the address of the current connection, or of the last one while reconnecting.
+    /// `None` until the Monitor first connects.
+    pub fn active_endpoint(&self) -> Option<MonitorEndpoint> {
+        self.inner.active_endpoint()
+    }
+
+    /// Get a snapshot of the state of the connection, e.g. for an external health check.
+    pub fn health(&self) -> MonitorHealth {
+        self.inner.health()
+    }
+
+    /// Get the counts of event lines parsed and dropped since the Monitor was created, by
+    /// kind of event, with samples of the last lines dropped and the time spent splitting
+    /// up chats and emails. Displays as a report, e.g. to find out why no emails show up
+    /// without turning on trace logging.
+    pub fn diagnostics(&self) -> wire::ParseStats {
+        self.inner.diagnostics()
     }
 
     /// Return a MonitorUpdate if one is available. Does not block.
-    pub fn poll(&mut self) -> Option<MonitorUpdate> {
+    pub fn poll(&mut self) -> Option<Arc<MonitorUpdate>> {
         self.rx.try_recv().ok()
     }
 
+    /// Wait for the next MonitorUpdate, for up to the given time.
+    /// Returns `None` on timeout, or if the Monitor thread has stopped.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Arc<MonitorUpdate>> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// Wait for the next MonitorUpdate. Returns `None` if the Monitor thread has stopped.
+    /// Monitors created with a callback don't buffer updates, so this waits forever.
+    pub fn recv(&mut self) -> Option<Arc<MonitorUpdate>> {
+        self.rx.recv().ok()
+    }
+
+    /// Get the Receiver `poll()` and `recv()` read from, e.g. to wait on it along with
+    /// other channels, such as with crossbeam's `select!`. The Monitor keeps running in
+    /// the background for as long as the process does, since it can't be shut down anymore.
+    pub fn into_receiver(self) -> Receiver<Arc<MonitorUpdate>> {
+        self.rx
+    }
+
     /// Get a copy of the last MonitorUpdate received.
     pub fn get_last_update(&self) -> Option<MonitorUpdate> {
-        self.last_update.lock().unwrap().clone()
+        self.inner.get_last_update()
     }
 
-    /// Shut down the Monitor and wait for the thread to finish.
-    pub fn shutdown(self) -> Result<()> {
-        self.handle.join().map_err(|_| "Monitor thread panicked")?;
+    fn add_tap(&self, tap: NotificationTap) {
+        self.inner.add_tap(tap);
+    }
+
+    /// Subscribe to the MonitorUpdates received from now on.
+    /// Each subscriber gets every update, independently of `poll()`, the callback, and
+    /// other subscribers, sharing it with them rather than getting a copy.
+    /// Dropping the Receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<Arc<MonitorUpdate>> {
+        self.inner.subscribe()
+    }
+
+    /// Subscribe to the player events received from now on.
+    /// Every update lists every online player, so each player is received once per update.
+    pub fn player_receiver(&self) -> Receiver<PlayerEvent> {
+        self.inner.player_receiver()
+    }
+
+    /// Subscribe to the chat events received from now on.
+    pub fn chat_receiver(&self) -> Receiver<ChatEvent> {
+        self.inner.chat_receiver()
+    }
+
+    /// Subscribe to the broadcast events received from now on.
+    pub fn broadcast_receiver(&self) -> Receiver<BroadcastEvent> {
+        self.inner.broadcast_receiver()
+    }
+
+    /// Subscribe to the email events received from now on.
+    pub fn email_receiver(&self) -> Receiver<EmailEvent> {
+        self.inner.email_receiver()
+    }
+
+    /// Subscribe to the name request events received from now on.
+    pub fn name_request_receiver(&self) -> Receiver<NameRequestEvent> {
+        self.inner.name_request_receiver()
+    }
+
+    /// Send a Command to the server over the monitor connection.
+    /// Fails if the Monitor is not connected, the command can't be framed as a single line,
+    /// or the server announced a protocol version without commands.
+    pub fn send_command(&self, command: Command) -> Result<()> {
+        validate_command(&command)?;
+        if let Some(version) = self.protocol_version() {
+            if !version.supports(wire::ProtocolFeature::Commands) {
+                return Err(format!("Server speaks protocol {}, without commands", version).into());
+            }
+        }
+        let mut writer = self.inner.shared.writer.lock().unwrap();
+        let stream = writer.as_mut().ok_or("Not connected to monitor")?;
+        writeln!(stream, "{}", command)?;
+        stream.flush()?;
         Ok(())
     }
+
+    /// Shut down the Monitor and wait for the thread to finish, for up to the shutdown
+    /// timeout of its config (see `MonitorConfig::with_shutdown_timeout()`).
+    /// The connection is closed, and frames received in full before that are still
+    /// delivered. Updates not yet taken from `poll()` are discarded.
+    pub fn shutdown(self) -> Result<()> {
+        self.close().map(drop)
+    }
+
+    /// Stop the Monitor and return the Receiver of the updates not yet polled.
+    /// The Receiver disconnects once every update has been handled, including on
+    /// the threads of a `pool::HandlerPool`. A thread that doesn't stop within the
+    /// shutdown timeout is left to stop in the background, and the Receiver only has
+    /// the updates received until then.
+    fn close(self) -> Result<Receiver<Arc<MonitorUpdate>>> {
+        let shared = &self.inner.shared;
+        shared.stop();
+        let started = Instant::now();
+        let grace = started + SHUTDOWN_GRACE.min(self.shutdown_timeout);
+        while shared.in_frame.load(Ordering::SeqCst)
+            && !self.thread.is_finished()
+            && Instant::now() < grace
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if let Some(stream) = shared.writer.lock().unwrap().as_ref() {
+            // unblocks the listen thread, which reads what's left and stops
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+        let deadline = started + self.shutdown_timeout;
+        while !self.thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if !self.thread.is_finished() {
+            // e.g. connecting without a connect timeout; dropping the handle detaches it
+            warn!(
+                "Monitor thread didn't stop within {:?}, leaving it to stop in the background",
+                self.shutdown_timeout
+            );
+            // the detached thread would keep the Receiver open
+            let (tx, rx) = mpsc::channel();
+            for update in self.rx.try_iter() {
+                let _ = tx.send(update);
+            }
+            return Ok(rx);
+        }
+        self.thread.join().map_err(|_| "Monitor thread panicked")?;
+        Ok(self.rx)
+    }
+}
+
+/// A cloneable handle to a Monitor, to check on the connection and subscribe to updates
+/// from any thread. Handles outlive the Monitor: once it has shut down, they report it
+/// disconnected and their subscriptions end.
+#[cfg(feature = "net")]
+#[derive(Clone)]
+pub struct MonitorHandle {
+    /// The Monitor's own address, before any fallbacks.
+    address: String,
+    shared: Arc<Shared>,
+}
+#[cfg(feature = "net")]
+impl MonitorHandle {
+    /// Check if the Monitor is connected to the feed.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+
+    /// Check if the user callback or the listen thread has panicked at any point.
+    /// See `MonitorConfig::with_restart_on_panic()` for what happens afterwards.
+    pub fn has_panicked(&self) -> bool {
+        self.shared.panicked.load(Ordering::Acquire)
+    }
+
+    /// Get the protocol version announced by the server on the current connection, if any.
+    /// Servers that don't announce one are assumed to support every feature.
+    pub fn protocol_version(&self) -> Option<wire::ProtocolVersion> {
+        self.shared.protocol_version.lock().unwrap().clone()
+    }
+
+    /// Get the address of the current connection, or of the last one while reconnecting.
+    /// `None` until the Monitor first connects.
+    pub fn active_endpoint(&self) -> Option<MonitorEndpoint> {
+        self.shared.endpoint.lock().unwrap().clone()
+    }
+
+    /// Get a snapshot of the state of the connection, e.g. for an external health check.
+    pub fn health(&self) -> MonitorHealth {
+        self.shared.health()
+    }
+
+    /// Get the counts of event lines parsed and dropped, as in `Monitor::diagnostics()`.
+    pub fn diagnostics(&self) -> wire::ParseStats {
+        self.shared.parse_stats.lock().unwrap().clone()
+    }
+
+    /// Get a copy of the last MonitorUpdate received.
+    pub fn get_last_update(&self) -> Option<MonitorUpdate> {
+        self.shared.last_update.lock().unwrap().as_deref().cloned()
+    }
+
+    fn add_tap(&self, tap: NotificationTap) {
+        let mut taps = self.shared.taps.lock().unwrap();
+        // a stopped Monitor won't deliver anything, so disconnect the subscriber right away
+        if !self.shared.stopping.load(Ordering::Acquire) {
+            taps.push(tap);
+        }
+    }
+
+    /// Subscribe to the MonitorUpdates received from now on.
+    /// Each subscriber gets every update, independently of `poll()`, the callback, and
+    /// other subscribers, sharing it with them rather than getting a copy.
+    /// Dropping the Receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<Arc<MonitorUpdate>> {
+        let (tx, rx) = mpsc::channel();
+        self.add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => tx.send(update.clone()).is_ok(),
+            _ => true,
+        }));
+        rx
+    }
+
+    fn event_receiver<T: Send + 'static>(&self, select: fn(&Event) -> Option<T>) -> Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+        self.add_tap(Box::new(move |notification| match notification {
+            // drop the subscriber once its receiver has gone away
+            MonitorNotification::Updated(update) => update
+                .events()
+                .iter()
+                .filter_map(select)
+                .all(|event| tx.send(event).is_ok()),
+            _ => true,
+        }));
+        rx
+    }
+
+    /// Subscribe to the player events received from now on.
+    /// Every update lists every online player, so each player is received once per update.
+    pub fn player_receiver(&self) -> Receiver<PlayerEvent> {
+        self.event_receiver(|event| match event {
+            Event::Player(player) => Some(player.clone()),
+            _ => None,
+        })
+    }
+
+    /// Subscribe to the chat events received from now on.
+    pub fn chat_receiver(&self) -> Receiver<ChatEvent> {
+        self.event_receiver(|event| match event {
+            Event::Chat(chat) => Some(chat.clone()),
+            _ => None,
+        })
+    }
+
+    /// Subscribe to the broadcast events received from now on.
+    pub fn broadcast_receiver(&self) -> Receiver<BroadcastEvent> {
+        self.event_receiver(|event| match event {
+            Event::Broadcast(bcast) => Some(bcast.clone()),
+            _ => None,
+        })
+    }
+
+    /// Subscribe to the email events received from now on.
+    pub fn email_receiver(&self) -> Receiver<EmailEvent> {
+        self.event_receiver(|event| match event {
+            Event::Email(email) => Some(email.clone()),
+            _ => None,
+        })
+    }
+
+    /// Subscribe to the name request events received from now on.
+    pub fn name_request_receiver(&self) -> Receiver<NameRequestEvent> {
+        self.event_receiver(|event| match event {
+            Event::NameRequest(namereq) => Some(namereq.clone()),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "net"))]
+mod tests {
+    use std::{io::Write as _, net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    /// Serve the given bytes to a Monitor with the given settings, closing the connection
+    /// after them, and get the notifications sent up to the disconnection.
+    fn serve(data: &'static [u8], config: MonitorConfig) -> Vec<MonitorNotification> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(data).unwrap();
+        });
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let callback: MonitorNotificationCallback = Box::new(move |notification| {
+            let _ = tx.lock().unwrap().send(notification);
+        });
+        let _monitor = Monitor::new_with_config_and_callback(&address, config, callback).unwrap();
+        let mut notifications = Vec::new();
+        loop {
+            let notification = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+            if matches!(notification, MonitorNotification::Disconnected) {
+                break;
+            }
+            notifications.push(notification);
+        }
+        server.join().unwrap();
+        notifications
+    }
+
+    fn updates(notifications: &[MonitorNotification]) -> Vec<MonitorUpdate> {
+        notifications
+            .iter()
+            .filter_map(|notification| match notification {
+                MonitorNotification::Updated(update) => Some(MonitorUpdate::clone(update)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn player_names(notifications: &[MonitorNotification]) -> Vec<String> {
+        updates(notifications)
+            .into_iter()
+            .flat_map(MonitorUpdate::get_events)
+            .filter_map(|event| match event {
+                Event::Player(player) => Some(player.name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lossy_utf8_replaces_invalid_sequences() {
+        let notifications = serve(
+            b"begin\nchat [FreeChat] Bob: caf\xe9 cr\xc3\xa8me\nend\n",
+            MonitorConfig::new().with_lossy_utf8(true),
+        );
+        let invalid: Vec<_> = notifications
+            .iter()
+            .filter_map(|notification| match notification {
+                MonitorNotification::InvalidUtf8(line) => Some(line.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(invalid, ["chat [FreeChat] Bob: caf\u{FFFD} cr\u{e8}me"]);
+        let updates = updates(&notifications);
+        assert_eq!(updates.len(), 1);
+        match &updates[0].clone().get_events()[..] {
+            [Event::Chat(chat)] => assert_eq!(chat.message, "caf\u{FFFD} cr\u{e8}me"),
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_ends_the_connection_unless_lossy() {
+        let notifications = serve(
+            b"begin\nplayer 1 2 caf\xe9\nend\nbegin\nplayer 1 2 Bob\nend\n",
+            MonitorConfig::new(),
+        );
+        assert!(updates(&notifications).is_empty());
+        assert!(!notifications
+            .iter()
+            .any(|notification| matches!(notification, MonitorNotification::InvalidUtf8(_))));
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped() {
+        let notifications = serve(b"begin\r\nplayer 1 2 Bob\r\nend\r\n", MonitorConfig::new());
+        assert_eq!(player_names(&notifications), ["Bob"]);
+    }
+
+    #[test]
+    fn crlf_end_closes_lf_frames() {
+        let notifications = serve(
+            b"begin\nplayer 1 2 Bob\nend\r\nbegin\nplayer 1 2 Alice\nend\n",
+            MonitorConfig::new(),
+        );
+        assert_eq!(player_names(&notifications), ["Bob", "Alice"]);
+    }
+
+    #[test]
+    fn crlf_line_endings_can_be_refused() {
+        let data = b"begin\r\nplayer 1 2 Bob\r\nend\r\n";
+        // lenient keywords still see past the carriage return, but names keep it
+        let notifications = serve(data, MonitorConfig::new().with_lenient_line_endings(false));
+        assert_eq!(player_names(&notifications), ["Bob\r"]);
+
+        let notifications = serve(
+            data,
+            MonitorConfig::new()
+                .with_lenient_line_endings(false)
+                .with_lenient_keywords(false),
+        );
+        assert!(updates(&notifications).is_empty());
+    }
+
+    #[test]
+    fn dedup_compares_times_metadata_and_server_sequences() {
+        let notifications = serve(
+            b"begin\nplayer 1 2 Bob\nend\n\
+              begin\nplayer 1 2 Bob\nend\n\
+              begin\ntime 1000\nplayer 1 2 Bob\nend\n\
+              begin\ntime 1000\nmeta uptime 5\nplayer 1 2 Bob\nend\n\
+              begin\nmeta seq 7\nplayer 1 2 Bob\nend\n\
+              begin\nmeta seq 7\nplayer 3 4 Bob\nend\n\
+              begin\nmeta seq 8\nplayer 3 4 Bob\nend\n",
+            MonitorConfig::new().with_dedup(true),
+        );
+        let suppressed = notifications
+            .iter()
+            .filter(|notification| matches!(notification, MonitorNotification::DuplicateSuppressed))
+            .count();
+        assert_eq!(suppressed, 2);
+        let sequences: Vec<_> = updates(&notifications)
+            .iter()
+            .map(|update| update.server_sequence())
+            .collect();
+        assert_eq!(sequences, [None, None, None, Some(7), Some(8)]);
+    }
+
+    #[test]
+    fn failback_probes_back_off() {
+        let interval = Duration::from_secs(30);
+        let delays: Vec<u64> = (0..7)
+            .map(|refusals| failback_delay(interval, refusals).as_secs())
+            .collect();
+        assert_eq!(delays, [30, 60, 120, 240, 480, 480, 480]);
+        assert_eq!(
+            failback_delay(interval, u32::MAX),
+            interval * MAX_FAILBACK_BACKOFF
+        );
+    }
+
+    #[test]
+    fn shutdown_gives_up_on_a_stuck_thread() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"begin\nend\n").unwrap();
+            stream
+        });
+        let (entered_tx, entered) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        let (entered_tx, release_rx) = (Mutex::new(entered_tx), Mutex::new(release_rx));
+        let callback: MonitorNotificationCallback = Box::new(move |notification| {
+            if let MonitorNotification::Updated(_) = notification {
+                let _ = entered_tx.lock().unwrap().send(());
+                let _ = release_rx.lock().unwrap().recv();
+            }
+        });
+        let config = MonitorConfig::new().with_shutdown_timeout(Duration::from_millis(100));
+        let monitor = Monitor::new_with_config_and_callback(&address, config, callback).unwrap();
+        entered.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        let started = Instant::now();
+        monitor.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(release);
+        drop(server.join().unwrap());
+    }
+
+    #[test]
+    fn updates_are_shared_rather_than_copied() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            ready_rx.recv().unwrap();
+            stream.write_all(b"begin\nplayer 1 2 Bob\nend\n").unwrap();
+        });
+        let config = MonitorConfig::new().with_roster_events(true);
+        let mut monitor = Monitor::new_with_config(&address, config).unwrap();
+        let subscription = monitor.subscribe();
+        ready_tx.send(()).unwrap();
+        let polled = monitor.recv_timeout(Duration::from_secs(10)).unwrap();
+        let subscribed = subscription.recv_timeout(Duration::from_secs(10)).unwrap();
+        server.join().unwrap();
+        assert!(Arc::ptr_eq(&polled, &subscribed));
+        assert_eq!(polled.sequence(), 1);
+        monitor.shutdown().unwrap();
+    }
 }
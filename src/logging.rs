@@ -0,0 +1,41 @@
+//! The logging backend: `log` by default, or `tracing` with the tracing feature.
+//!
+//! With tracing, every connection and every frame gets its own span, so events logged
+//! while handling a frame (including by the user callback) can be correlated with it.
+//! Without a tracing subscriber, events are still forwarded to `log`.
+
+// most of the logging happens in the networking code
+#![cfg_attr(not(feature = "net"), allow(unused_imports, dead_code))]
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use log::{debug, error, info, warn};
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, error, info, warn};
+
+/// Keeps a span entered until dropped.
+#[cfg(feature = "tracing")]
+pub(crate) type SpanGuard = tracing::span::EnteredSpan;
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct SpanGuard;
+
+/// Enter the span of a connection to a monitor.
+pub(crate) fn connection_span(address: &str) -> SpanGuard {
+    #[cfg(feature = "tracing")]
+    return tracing::info_span!("connection", address = %address).entered();
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = address;
+        SpanGuard
+    }
+}
+
+/// Enter the span of a frame received from a monitor.
+pub(crate) fn frame_span(lines: usize) -> SpanGuard {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!("frame", lines).entered();
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = lines;
+        SpanGuard
+    }
+}
@@ -0,0 +1,177 @@
+//! Routing in-game emails to outputs by who they're from, who they're to and their
+//! subject, e.g. to surface mail sent to staff in a ticket system.
+//!
+//! An EmailRouter holds rules naming the sinks of an `sink::EventPipeline` that matching
+//! emails go to. Sinks added with `EventPipeline::with_routed_sink()` only receive the
+//! emails the rules send them. The router is a shared handle: rules added or removed
+//! through any clone of it apply right away, even once the pipeline is running.
+
+use std::sync::{Arc, RwLock};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    sink::{EventPipeline, EventSink},
+    EmailEvent, Event, Result,
+};
+
+/// A rule sending emails matching all of its patterns to a set of sinks.
+/// Patterns are regular expressions matched anywhere in their field, ignoring case;
+/// anchor them with `^` and `$` to match whole names.
+#[derive(Debug, Clone)]
+pub struct EmailRule {
+    id: String,
+    from: Option<Regex>,
+    to: Option<Regex>,
+    subject: Option<Regex>,
+    sinks: Vec<String>,
+}
+impl EmailRule {
+    /// Create a rule sending every email to the sink with the given name.
+    pub fn new(id: &str, sink: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            from: None,
+            to: None,
+            subject: None,
+            sinks: vec![sink.to_string()],
+        }
+    }
+
+    /// Also send matching emails to the sink with the given name.
+    pub fn with_sink(mut self, sink: &str) -> Self {
+        self.sinks.push(sink.to_string());
+        self
+    }
+
+    /// Only match emails from senders matching the pattern.
+    pub fn with_from(mut self, pattern: &str) -> Result<Self> {
+        self.from = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Only match emails to recipients matching the pattern.
+    pub fn with_to(mut self, pattern: &str) -> Result<Self> {
+        self.to = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Only match emails with subjects matching the pattern.
+    /// Emails without a subject are matched as having an empty one.
+    pub fn with_subject(mut self, pattern: &str) -> Result<Self> {
+        self.subject = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Get the id of the rule.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get the names of the sinks matching emails go to.
+    pub fn sinks(&self) -> &[String] {
+        &self.sinks
+    }
+
+    /// Check if an email matches every pattern of the rule.
+    pub fn matches(&self, email: &EmailEvent) -> bool {
+        let matches = |pattern: &Option<Regex>, text: &str| {
+            pattern.as_ref().is_none_or(|regex| regex.is_match(text))
+        };
+        matches(&self.from, &email.from)
+            && matches(&self.to, &email.to)
+            && matches(&self.subject, email.subject_text().unwrap_or_default())
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+}
+
+/// Decides which sinks each email goes to, from a set of rules that can be changed
+/// at any time. Clones share the same rules.
+#[derive(Debug, Clone, Default)]
+pub struct EmailRouter {
+    rules: Arc<RwLock<Vec<EmailRule>>>,
+}
+impl EmailRouter {
+    /// Create an EmailRouter with no rules, sending emails nowhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule. A rule with the same id is replaced, keeping its place.
+    pub fn add_rule(&self, rule: EmailRule) {
+        let mut rules = self.rules.write().unwrap();
+        match rules.iter_mut().find(|other| other.id == rule.id) {
+            Some(other) => *other = rule,
+            None => rules.push(rule),
+        }
+    }
+
+    /// Remove the rule with the given id.
+    pub fn remove_rule(&self, id: &str) {
+        self.rules.write().unwrap().retain(|rule| rule.id != id);
+    }
+
+    /// Get a copy of the rules, in the order they were added.
+    pub fn rules(&self) -> Vec<EmailRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Get the ids of the rules an email matches, in the order they were added.
+    pub fn matching_rules(&self, email: &EmailEvent) -> Vec<String> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule.matches(email))
+            .map(|rule| rule.id.clone())
+            .collect()
+    }
+
+    /// Get the names of the sinks an email goes to, each once, in the order the
+    /// rules sending it there were added.
+    pub fn route(&self, email: &EmailEvent) -> Vec<String> {
+        let mut sinks: Vec<String> = Vec::new();
+        for rule in self.rules.read().unwrap().iter() {
+            if !rule.matches(email) {
+                continue;
+            }
+            for sink in &rule.sinks {
+                if !sinks.contains(sink) {
+                    sinks.push(sink.clone());
+                }
+            }
+        }
+        sinks
+    }
+
+    /// Check if an Event is an email going to the sink with the given name.
+    pub fn routes_to(&self, event: &Event, sink: &str) -> bool {
+        let Event::Email(email) = event else {
+            return false;
+        };
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .any(|rule| rule.sinks.iter().any(|other| other == sink) && rule.matches(email))
+    }
+}
+
+impl EventPipeline {
+    /// Add a sink receiving only the emails the router's rules send to it, by name.
+    /// The rules are checked on the sink's thread as it takes each email, so rule
+    /// changes also apply to emails still queued for it.
+    pub fn with_routed_sink(
+        self,
+        name: &str,
+        sink: impl EventSink + 'static,
+        router: &EmailRouter,
+    ) -> Self {
+        let router = router.clone();
+        let sink_name = name.to_string();
+        self.with_filtered_sink(name, sink, move |event| router.routes_to(event, &sink_name))
+    }
+}
@@ -0,0 +1,121 @@
+//! Publishing events to an MQTT broker.
+
+use std::{thread, time::Duration};
+
+use rumqttc::{Client, Outgoing};
+pub use rumqttc::{MqttOptions, QoS};
+
+use crate::{logging::*, sink::EventSink, Event, MonitorUpdate, Result};
+
+/// How long to wait before reconnecting to the broker after a connection error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Publishes each event as a JSON payload to an MQTT topic chosen by its kind.
+///
+/// The topic is rendered from a template. Placeholders are written as `{field}`:
+/// - `{shard}`: the shard label set with `with_shard()`
+/// - `{kind}`: `player`, `chat`, `broadcast`, `email`, `name_request` or `custom`
+///
+/// Publishing only queues the message; the connection to the broker is kept up, and
+/// re-established after errors, by a background thread.
+pub struct MqttSink {
+    client: Client,
+    topic: String,
+    shard: String,
+    qos: QoS,
+    retain: bool,
+}
+impl MqttSink {
+    /// Connect to the broker at the given host and port, identifying as `client_id`.
+    /// By default, events are published to `openfusion/{shard}/{kind}` at QoS 0.
+    pub fn connect(host: &str, port: u16, client_id: &str) -> Self {
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        Self::connect_with_options(options)
+    }
+
+    /// Connect to a broker with the given options, e.g. to log in with credentials.
+    pub fn connect_with_options(options: MqttOptions) -> Self {
+        let (client, mut connection) = Client::new(options, 64);
+        thread::spawn(move || {
+            // ends once the sink disconnects or is dropped
+            for notification in connection.iter() {
+                match notification {
+                    Ok(rumqttc::Event::Outgoing(Outgoing::Disconnect)) => return,
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("MQTT connection error: {}", err);
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+        Self {
+            client,
+            topic: "openfusion/{shard}/{kind}".to_string(),
+            shard: "main".to_string(),
+            qos: QoS::AtMostOnce,
+            retain: false,
+        }
+    }
+
+    /// Set the template of the topics events are published to.
+    pub fn with_topic(mut self, template: &str) -> Self {
+        self.topic = template.to_string();
+        self
+    }
+
+    /// Set the label substituted for `{shard}` in topics. Defaults to `main`.
+    pub fn with_shard(mut self, shard: &str) -> Self {
+        self.shard = shard.to_string();
+        self
+    }
+
+    /// Set the quality of service events are published with. Defaults to `QoS::AtMostOnce`.
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Ask the broker to retain the last event published to each topic.
+    /// Defaults to `false`.
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Render the topic the given Event is published to.
+    pub fn topic_for(&self, event: &Event) -> String {
+        self.topic
+            .replace("{shard}", &self.shard)
+            .replace("{kind}", event.kind().as_str())
+    }
+
+    /// Publish a single Event.
+    pub fn publish(&mut self, event: &Event) -> Result<()> {
+        let payload = serde_json::to_vec(event)?;
+        self.client
+            .publish(self.topic_for(event), self.qos, self.retain, payload)?;
+        Ok(())
+    }
+
+    /// Publish every event of a MonitorUpdate, logging failures.
+    pub fn publish_update(&mut self, update: &MonitorUpdate) {
+        for event in update.events() {
+            if let Err(err) = self.publish(event) {
+                warn!("Couldn't publish event to MQTT ({}): {}", err, event);
+            }
+        }
+    }
+}
+impl EventSink for MqttSink {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        self.publish(event)
+    }
+}
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        // sent after the queued messages, so they still go out if the broker is reachable
+        let _ = self.client.disconnect();
+    }
+}
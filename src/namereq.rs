@@ -0,0 +1,128 @@
+use std::sync::mpsc::Sender;
+
+use crate::{logging::*, Command, Event, MonitorUpdate, NameRequestEvent, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameDecision {
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NameRequestDecision {
+    pub player_uid: u64,
+    pub requested_name: String,
+    pub decision: NameDecision,
+}
+
+impl From<NameRequestDecision> for Command {
+    fn from(decision: NameRequestDecision) -> Self {
+        let player_uid = decision.player_uid;
+        match decision.decision {
+            NameDecision::Approved => Self::ApproveName { player_uid },
+            NameDecision::Rejected => Self::RejectName { player_uid },
+        }
+    }
+}
+
+/// Collects name requests for moderators to approve or reject.
+///
+/// Only the most recent request of each player is kept pending.
+/// Decisions are recorded and, if a decision sender is set, sent out over it.
+#[derive(Debug, Default)]
+pub struct NameRequestQueue {
+    pending: Vec<NameRequestEvent>,
+    decisions: Vec<NameRequestDecision>,
+    outbound: Option<Sender<NameRequestDecision>>,
+}
+impl NameRequestQueue {
+    /// Create an empty NameRequestQueue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send every decision made from now on over the given Sender.
+    /// Decisions can be forwarded to the server with `Monitor::send_command()`.
+    pub fn with_decision_sender(mut self, sender: Sender<NameRequestDecision>) -> Self {
+        self.outbound = Some(sender);
+        self
+    }
+
+    /// Add a name request to the queue.
+    /// If the player already has a pending request, it is replaced and `false` is returned.
+    pub fn push(&mut self, request: NameRequestEvent) -> bool {
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.player_uid == request.player_uid)
+        {
+            Some(pending) => {
+                *pending = request;
+                false
+            }
+            None => {
+                self.pending.push(request);
+                true
+            }
+        }
+    }
+
+    /// Add every name request in the given MonitorUpdate to the queue.
+    pub fn add_update(&mut self, update: &MonitorUpdate) {
+        for event in update.events() {
+            if let Event::NameRequest(request) = event {
+                self.push(request.clone());
+            }
+        }
+    }
+
+    /// Get the pending name requests, oldest first.
+    pub fn pending(&self) -> &[NameRequestEvent] {
+        &self.pending
+    }
+
+    /// Get the pending name request of the given player, if any.
+    pub fn get(&self, player_uid: u64) -> Option<&NameRequestEvent> {
+        self.pending
+            .iter()
+            .find(|pending| pending.player_uid == player_uid)
+    }
+
+    /// Get every decision made so far, oldest first.
+    pub fn decisions(&self) -> &[NameRequestDecision] {
+        &self.decisions
+    }
+
+    /// Approve the pending name request of the given player.
+    pub fn approve(&mut self, player_uid: u64) -> Result<NameRequestDecision> {
+        self.decide(player_uid, NameDecision::Approved)
+    }
+
+    /// Reject the pending name request of the given player.
+    pub fn reject(&mut self, player_uid: u64) -> Result<NameRequestDecision> {
+        self.decide(player_uid, NameDecision::Rejected)
+    }
+
+    fn decide(&mut self, player_uid: u64, decision: NameDecision) -> Result<NameRequestDecision> {
+        let idx = self
+            .pending
+            .iter()
+            .position(|pending| pending.player_uid == player_uid)
+            .ok_or_else(|| format!("No pending name request for player {}", player_uid))?;
+        let request = self.pending.remove(idx);
+        let decision = NameRequestDecision {
+            player_uid,
+            requested_name: request.requested_name,
+            decision,
+        };
+        self.decisions.push(decision.clone());
+
+        if let Some(outbound) = &self.outbound {
+            if outbound.send(decision.clone()).is_err() {
+                warn!("Name request decision receiver is gone");
+                self.outbound = None;
+            }
+        }
+        Ok(decision)
+    }
+}
@@ -0,0 +1,116 @@
+//! Reading monitor output captured to files, e.g. to backfill trackers and sinks
+//! with historical data.
+//!
+//! Lines outside of frames are skipped, so captures mixed with other output can be read too.
+//! Frame keywords are recognized regardless of case and surrounding whitespace.
+//! A `time <unix_millis>` line right before a frame, as written by `replay::Recorder`,
+//! is used as the server time of the frame if the frame has none of its own.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    logging::*,
+    wire::{is_keyword, FrameTrailer},
+    MonitorUpdate, ParserRegistry, Result,
+};
+
+/// Iterator over the frames of captured monitor output, as returned by `parse_log_file()`.
+pub struct LogFrames<R: BufRead> {
+    reader: R,
+    line: usize,
+}
+impl<R: BufRead> LogFrames<R> {
+    /// Read frames from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>> {
+        let mut buf = Vec::new();
+        if self.reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+        }
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+    }
+}
+impl<R: BufRead> Iterator for LogFrames<R> {
+    type Item = Result<MonitorUpdate>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut time = None;
+        let mut lines = Vec::new();
+        let mut in_frame = false;
+        loop {
+            let line = match self.next_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    if in_frame {
+                        warn!("Capture ends in the middle of a frame");
+                    }
+                    return None;
+                }
+                Err(err) => return Some(Err(err)),
+            };
+
+            if !in_frame {
+                if let Some(millis) = line.strip_prefix("time ") {
+                    time = millis
+                        .parse()
+                        .ok()
+                        .and_then(|millis| UNIX_EPOCH.checked_add(Duration::from_millis(millis)));
+                } else if is_keyword(&line, "begin", true) {
+                    in_frame = true;
+                } else {
+                    #[cfg(feature = "json")]
+                    if line.starts_with('{') {
+                        match crate::wire::parse_json_frame(&line) {
+                            Ok(update) => return Some(Ok(update)),
+                            Err(err) => warn!("Bad JSON frame on line {} ({})", self.line, err),
+                        }
+                    }
+                    time = None;
+                }
+                continue;
+            }
+
+            if is_keyword(&line, "begin", true) {
+                warn!("Nested begin on line {}", self.line);
+                lines.clear();
+            } else if let Some(trailer) = FrameTrailer::parse(&line, true) {
+                if let Err(err) = trailer.verify(&lines) {
+                    warn!("{} on line {}", err, self.line);
+                }
+                let mut update = crate::wire::parse_lines(
+                    &lines,
+                    &ParserRegistry::default(),
+                    None,
+                    &mut Vec::new(),
+                );
+                if update.server_time().is_none() {
+                    update.set_server_time(time);
+                }
+                return Some(Ok(update));
+            } else {
+                lines.push(line);
+            }
+        }
+    }
+}
+
+/// Read every frame of the monitor output captured in the file at the given path.
+/// Frames are read lazily, so arbitrarily large files can be processed.
+pub fn parse_log_file(path: &str) -> Result<LogFrames<BufReader<File>>> {
+    let file = File::open(path)?;
+    Ok(LogFrames::new(BufReader::new(file)))
+}
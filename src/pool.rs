@@ -0,0 +1,73 @@
+//! Running notification handling off the listen threads.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::logging::*;
+
+pub(crate) type Job = Box<dyn FnOnce() + Send>;
+
+/// A small pool of threads handling the notifications of one or more Monitors, so a
+/// slow callback or sink doesn't hold up reading from the connection.
+///
+/// Each Monitor is assigned to a single thread of the pool, so its notifications are
+/// still handled one at a time, in order. Share a pool between Monitors by cloning it
+/// into each of their `MonitorConfig`s. The threads exit once the pool and every
+/// Monitor using it are dropped.
+#[derive(Clone)]
+pub struct HandlerPool {
+    workers: Arc<Vec<Sender<Job>>>,
+    next: Arc<AtomicUsize>,
+}
+impl HandlerPool {
+    /// Create a HandlerPool with the given number of threads.
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "HandlerPool needs at least one thread");
+        let workers = (0..threads)
+            .map(|idx| {
+                let (tx, rx) = mpsc::channel::<Job>();
+                thread::Builder::new()
+                    .name(format!("ffmonitor-handler-{}", idx))
+                    .spawn(move || {
+                        for job in rx {
+                            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                                error!("Notification handler panicked");
+                            }
+                        }
+                    })
+                    .expect("Couldn't spawn handler thread");
+                tx
+            })
+            .collect();
+        Self {
+            workers: Arc::new(workers),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Get the number of threads in the pool.
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Pick the thread for a new Monitor, round-robin.
+    pub(crate) fn assign(&self) -> Sender<Job> {
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.workers[idx].clone()
+    }
+}
+impl fmt::Debug for HandlerPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HandlerPool")
+            .field("threads", &self.workers.len())
+            .finish()
+    }
+}
@@ -0,0 +1,190 @@
+//! Anonymizing events before they reach sinks, for deployments that may only keep
+//! aggregate stats about players.
+//!
+//! A PrivacyPolicy replaces player names with keyed hashes or pseudonyms and redacts
+//! the messages of chats and emails. Wrapping a sink in a PrivateSink applies a policy
+//! to every event it gets, so each sink of a `sink::EventPipeline` can have its own,
+//! e.g. full events for moderation logs and anonymized ones for public stats:
+//! `pipeline.with_sink("stats", PrivateSink::new(sink, policy))`.
+//!
+//! Anonymized events keep their kinds, positions, chat kinds, broadcast details and
+//! email attachments, so they can still be counted and placed on the map. Extra fields
+//! are dropped, as there's no telling what they hold, and so are custom events.
+
+use std::{collections::HashMap, fmt::Write as _};
+
+use hmac::{Hmac, KeyInit as _, Mac as _};
+use sha2::Sha256;
+
+use crate::{classify::ChatTags, sink::EventSink, Event, Result};
+
+/// What redacted messages are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// How many hex digits of the keyed hash make up a hashed name.
+const HASH_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+enum Names {
+    #[default]
+    Keep,
+    Hash(Vec<u8>),
+    Pseudonym(HashMap<String, String>),
+}
+
+/// What to anonymize in events. Keeps everything but extra fields and custom events
+/// by default.
+#[derive(Debug, Clone, Default)]
+pub struct PrivacyPolicy {
+    names: Names,
+    redact_chats: bool,
+    redact_emails: bool,
+}
+impl PrivacyPolicy {
+    /// Create a PrivacyPolicy keeping names and messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace player names with the start of their HMAC-SHA256 under the given key,
+    /// in hex. The same name always hashes the same under the same key, across restarts
+    /// and processes, so stats can be kept per player. Keep the key secret: with it,
+    /// hashes can be matched against a list of known names.
+    pub fn with_hashed_names(mut self, key: &[u8]) -> Self {
+        self.names = Names::Hash(key.to_vec());
+        self
+    }
+
+    /// Replace player names with `Player 1`, `Player 2` and so on, in the order they're
+    /// first seen. Pseudonyms only last as long as the policy, and can't be traced back
+    /// to names once it's gone.
+    pub fn with_pseudonyms(mut self) -> Self {
+        self.names = Names::Pseudonym(HashMap::new());
+        self
+    }
+
+    /// Replace the messages of chats with `REDACTED`. Off by default.
+    pub fn with_redacted_chats(mut self, redact: bool) -> Self {
+        self.redact_chats = redact;
+        self
+    }
+
+    /// Replace the subjects and bodies of emails with `REDACTED`. Attachments are kept.
+    /// Off by default.
+    pub fn with_redacted_emails(mut self, redact: bool) -> Self {
+        self.redact_emails = redact;
+        self
+    }
+
+    /// Get the name the policy replaces a player name with.
+    pub fn anonymize_name(&mut self, name: &str) -> String {
+        match &mut self.names {
+            Names::Keep => name.to_string(),
+            Names::Hash(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key");
+                mac.update(name.as_bytes());
+                let mut hash = String::with_capacity(HASH_LENGTH);
+                for byte in mac.finalize().into_bytes().iter().take(HASH_LENGTH / 2) {
+                    let _ = write!(hash, "{:02x}", byte);
+                }
+                hash
+            }
+            Names::Pseudonym(pseudonyms) => {
+                let count = pseudonyms.len();
+                pseudonyms
+                    .entry(name.to_string())
+                    .or_insert_with(|| format!("Player {}", count + 1))
+                    .clone()
+            }
+        }
+    }
+
+    /// Get an anonymized copy of an Event, or `None` for custom events.
+    pub fn apply(&mut self, event: &Event) -> Option<Event> {
+        let event = match event {
+            Event::Player(player) => {
+                let mut player = player.clone();
+                player.name = self.anonymize_name(&player.name);
+                player.extra.clear();
+                Event::Player(player)
+            }
+            Event::Chat(chat) => {
+                let mut chat = chat.clone();
+                chat.from = self.anonymize_name(&chat.from);
+                chat.to = chat.to.map(|to| self.anonymize_name(&to));
+                if self.redact_chats {
+                    chat.message = REDACTED.to_string();
+                }
+                chat.extra.clear();
+                Event::Chat(chat)
+            }
+            Event::Broadcast(broadcast) => {
+                let mut broadcast = broadcast.clone();
+                broadcast.from = self.anonymize_name(&broadcast.from);
+                broadcast.extra.clear();
+                Event::Broadcast(broadcast)
+            }
+            Event::Email(email) => {
+                let mut email = email.clone();
+                email.from = self.anonymize_name(&email.from);
+                email.to = self.anonymize_name(&email.to);
+                if self.redact_emails {
+                    if email.has_subject() {
+                        email.subject = Some(REDACTED.to_string());
+                    }
+                    if !email.body.is_empty() {
+                        email.body = vec![REDACTED.to_string()];
+                    }
+                }
+                email.extra.clear();
+                Event::Email(email)
+            }
+            Event::NameRequest(request) => {
+                let mut request = request.clone();
+                request.requested_name = self.anonymize_name(&request.requested_name);
+                request.extra.clear();
+                Event::NameRequest(request)
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+/// An EventSink getting the events sent to it anonymized by a PrivacyPolicy.
+/// Chat tags are passed on as they are, so chats can be classified before their
+/// messages are redacted.
+pub struct PrivateSink<S: EventSink> {
+    sink: S,
+    policy: PrivacyPolicy,
+}
+impl<S: EventSink> PrivateSink<S> {
+    /// Wrap a sink, anonymizing its events with the given policy.
+    pub fn new(sink: S, policy: PrivacyPolicy) -> Self {
+        Self { sink, policy }
+    }
+
+    /// Get the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+impl<S: EventSink> EventSink for PrivateSink<S> {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        match self.policy.apply(event) {
+            Some(event) => self.sink.handle(&event),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_tagged(&mut self, event: &Event, tags: &ChatTags) -> Result<()> {
+        match self.policy.apply(event) {
+            Some(event) => self.sink.handle_tagged(&event, tags),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sink.flush()
+    }
+}
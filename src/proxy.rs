@@ -0,0 +1,253 @@
+//! Connecting to monitor ports through a proxy, for servers only reachable through
+//! a bastion host.
+//!
+//! Both HTTP proxies supporting `CONNECT` and SOCKS5 proxies are supported, with
+//! optional username and password authentication. The monitor's hostname is sent to the
+//! proxy as is and resolved on the proxy's side, so hosts only known behind it work too.
+
+use std::{
+    io::{Read as _, Write as _},
+    net::{IpAddr, TcpStream},
+    time::Duration,
+};
+
+use crate::{logging::*, Result};
+
+/// The longest response header accepted from an HTTP proxy.
+const MAX_HTTP_RESPONSE_LEN: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ProxyKind {
+    HttpConnect,
+    Socks5,
+}
+
+/// A proxy to connect to monitor ports through, as set with `MonitorConfig::with_proxy()`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proxy {
+    kind: ProxyKind,
+    address: String,
+    credentials: Option<(String, String)>,
+}
+impl Proxy {
+    /// Connect through the HTTP proxy at the given `host:port` address, with `CONNECT`.
+    pub fn http(address: &str) -> Self {
+        Self {
+            kind: ProxyKind::HttpConnect,
+            address: address.to_string(),
+            credentials: None,
+        }
+    }
+
+    /// Connect through the SOCKS5 proxy at the given `host:port` address.
+    pub fn socks5(address: &str) -> Self {
+        Self {
+            kind: ProxyKind::Socks5,
+            address: address.to_string(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with the proxy with the given username and password, with Basic
+    /// authentication for HTTP proxies.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Get the address of the proxy.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Ask the proxy, over a new connection to it, to connect to the given address.
+    /// Only reads what belongs to the proxy's response, so the stream is left at the
+    /// first byte sent by the monitor.
+    pub(crate) fn handshake(
+        &self,
+        stream: &TcpStream,
+        target: &str,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        debug!("Asking proxy {} to connect to {}", self.address, target);
+        // don't wait forever for a proxy that doesn't answer
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let result = match self.kind {
+            ProxyKind::HttpConnect => self.http_connect(stream, target),
+            ProxyKind::Socks5 => self.socks5_connect(stream, target),
+        };
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        result.map_err(|err| format!("Proxy {}: {}", self.address, err).into())
+    }
+
+    fn http_connect(&self, mut stream: &TcpStream, target: &str) -> Result<()> {
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+        if let Some((username, password)) = &self.credentials {
+            let token = base64(format!("{}:{}", username, password).as_bytes());
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // read a byte at a time, so nothing after the response is consumed
+        let mut response = Vec::new();
+        let mut byte = [0];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_HTTP_RESPONSE_LEN {
+                return Err("Response too long".into());
+            }
+            if stream.read(&mut byte)? == 0 {
+                return Err("Connection closed during handshake".into());
+            }
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| format!("Malformed response: {}", status_line))?;
+        if !status.starts_with('2') {
+            return Err(format!("Refused to connect: {}", status_line).into());
+        }
+        Ok(())
+    }
+
+    fn socks5_connect(&self, mut stream: &TcpStream, target: &str) -> Result<()> {
+        const VERSION: u8 = 5;
+        const NO_AUTH: u8 = 0;
+        const USERNAME_PASSWORD: u8 = 2;
+        const CONNECT: u8 = 1;
+
+        let methods: &[u8] = match self.credentials {
+            Some(_) => &[NO_AUTH, USERNAME_PASSWORD],
+            None => &[NO_AUTH],
+        };
+        let mut greeting = vec![VERSION, methods.len() as u8];
+        greeting.extend_from_slice(methods);
+        stream.write_all(&greeting)?;
+        let mut choice = [0; 2];
+        stream.read_exact(&mut choice)?;
+        match (choice, &self.credentials) {
+            ([VERSION, NO_AUTH], _) => {}
+            ([VERSION, USERNAME_PASSWORD], Some((username, password))) => {
+                if username.len() > 255 || password.len() > 255 {
+                    return Err("Username or password over 255 bytes".into());
+                }
+                let mut auth = vec![1, username.len() as u8];
+                auth.extend_from_slice(username.as_bytes());
+                auth.push(password.len() as u8);
+                auth.extend_from_slice(password.as_bytes());
+                stream.write_all(&auth)?;
+                let mut status = [0; 2];
+                stream.read_exact(&mut status)?;
+                if status[1] != 0 {
+                    return Err("Authentication rejected".into());
+                }
+            }
+            ([VERSION, _], _) => return Err("No acceptable authentication method".into()),
+            _ => return Err("Not a SOCKS5 proxy".into()),
+        }
+
+        let (host, port) = split_host_port(target)?;
+        let mut request = vec![VERSION, CONNECT, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                if host.len() > 255 {
+                    return Err("Hostname over 255 bytes".into());
+                }
+                request.push(3);
+                request.push(host.len() as u8);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(socks5_error(reply[1]).into());
+        }
+        // skip the address the proxy connected from
+        let address_len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            other => return Err(format!("Unknown address type {}", other).into()),
+        };
+        let mut bound = vec![0; address_len + 2];
+        stream.read_exact(&mut bound)?;
+        Ok(())
+    }
+}
+impl std::fmt::Debug for Proxy {
+    // keeps the password out of logs
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Proxy")
+            .field("kind", &self.kind)
+            .field("address", &self.address)
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|(username, _)| username),
+            )
+            .finish()
+    }
+}
+
+/// Split a `host:port` or `[ipv6]:port` address, without resolving it.
+fn split_host_port(address: &str) -> Result<(&str, u16)> {
+    let (host, port) = address.rsplit_once(':').ok_or("Address has no port")?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    Ok((host, port.parse()?))
+}
+
+fn socks5_error(reply: u8) -> String {
+    let reason = match reply {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    };
+    format!("Connection failed: {}", reason)
+}
+
+/// Encode bytes as standard base64, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (idx, &byte)| {
+            group | (byte as u32) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            match idx <= chunk.len() {
+                true => encoded.push(ALPHABET[(group >> (18 - 6 * idx) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
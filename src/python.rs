@@ -0,0 +1,369 @@
+//! Python bindings, built as the `ffmonitor` extension module.
+//!
+//! Events are exposed as read-only classes mirroring the Rust types. Since `from` is
+//! a keyword in Python, senders and recipients are named `sender` and `recipient`.
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use crate::{logging::*, Event, MonitorNotification};
+
+/// How often a blocking poll wakes up to let Python handle signals such as Ctrl+C.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+fn to_py_err(err: crate::Error) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+fn unix_secs(time: Option<SystemTime>) -> Option<f64> {
+    time.map(|time| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    })
+}
+
+#[pyclass(name = "PlayerEvent", module = "ffmonitor", frozen, get_all)]
+struct PyPlayerEvent {
+    x_coord: i32,
+    y_coord: i32,
+    name: String,
+    extra: Vec<String>,
+    line: String,
+}
+
+#[pyclass(name = "ChatEvent", module = "ffmonitor", frozen, get_all)]
+struct PyChatEvent {
+    kind: String,
+    sender: String,
+    recipient: Option<String>,
+    message: String,
+    extra: Vec<String>,
+    line: String,
+}
+
+#[pyclass(name = "BroadcastEvent", module = "ffmonitor", frozen, get_all)]
+struct PyBroadcastEvent {
+    scope: String,
+    announcement_type: String,
+    duration_secs: usize,
+    sender: String,
+    message: String,
+    extra: Vec<String>,
+    line: String,
+}
+
+#[pyclass(name = "EmailEvent", module = "ffmonitor", frozen, get_all)]
+struct PyEmailEvent {
+    sender: String,
+    recipient: String,
+    /// `None` for emails without a subject, rather than the placeholder servers send.
+    subject: Option<String>,
+    body: Vec<String>,
+    taros: Option<u64>,
+    /// `(item_type, item_id, quantity)` tuples.
+    items: Vec<(u32, u32, u32)>,
+    extra: Vec<String>,
+    line: String,
+}
+
+#[pyclass(name = "NameRequestEvent", module = "ffmonitor", frozen, get_all)]
+struct PyNameRequestEvent {
+    player_uid: u64,
+    requested_name: String,
+    extra: Vec<String>,
+    line: String,
+}
+
+#[pyclass(name = "CustomEvent", module = "ffmonitor", frozen, get_all)]
+struct PyCustomEvent {
+    token: String,
+    line: String,
+}
+
+macro_rules! impl_repr {
+    ($($class:ty),*) => {$(
+        #[pymethods]
+        impl $class {
+            fn __repr__(&self) -> String {
+                self.line.clone()
+            }
+        }
+    )*};
+}
+impl_repr!(
+    PyPlayerEvent,
+    PyChatEvent,
+    PyBroadcastEvent,
+    PyEmailEvent,
+    PyNameRequestEvent,
+    PyCustomEvent
+);
+
+fn event_to_py(py: Python, event: &Event) -> PyResult<Py<PyAny>> {
+    let line = event.to_string();
+    let object = match event {
+        Event::Player(player) => Py::new(
+            py,
+            PyPlayerEvent {
+                x_coord: player.x_coord,
+                y_coord: player.y_coord,
+                name: player.name.clone(),
+                extra: player.extra.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        Event::Chat(chat) => Py::new(
+            py,
+            PyChatEvent {
+                kind: chat.kind.to_string(),
+                sender: chat.from.clone(),
+                recipient: chat.to.clone(),
+                message: chat.message.clone(),
+                extra: chat.extra.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        Event::Broadcast(bcast) => Py::new(
+            py,
+            PyBroadcastEvent {
+                scope: format!("{:?}", bcast.scope),
+                announcement_type: bcast.announcement_type.to_string(),
+                duration_secs: bcast.duration_secs,
+                sender: bcast.from.clone(),
+                message: bcast.message.clone(),
+                extra: bcast.extra.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        Event::Email(email) => Py::new(
+            py,
+            PyEmailEvent {
+                sender: email.from.clone(),
+                recipient: email.to.clone(),
+                subject: email.subject_text().map(str::to_string),
+                body: email.body.clone(),
+                taros: email.taros,
+                items: email
+                    .items
+                    .iter()
+                    .map(|item| (item.item_type, item.item_id, item.quantity))
+                    .collect(),
+                extra: email.extra.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        Event::NameRequest(namereq) => Py::new(
+            py,
+            PyNameRequestEvent {
+                player_uid: namereq.player_uid,
+                requested_name: namereq.requested_name.clone(),
+                extra: namereq.extra.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        Event::Custom(custom) => Py::new(
+            py,
+            PyCustomEvent {
+                token: custom.token.clone(),
+                line,
+            },
+        )?
+        .into_any(),
+        // events added to the protocol since, exposed by their line
+        _ => Py::new(
+            py,
+            PyCustomEvent {
+                token: line
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                line,
+            },
+        )?
+        .into_any(),
+    };
+    Ok(object)
+}
+
+/// A frame received from the monitor.
+#[pyclass(name = "MonitorUpdate", module = "ffmonitor", frozen)]
+struct PyMonitorUpdate {
+    update: Arc<crate::MonitorUpdate>,
+}
+#[pymethods]
+impl PyMonitorUpdate {
+    /// The events of the update, in order.
+    #[getter]
+    fn events(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.update
+            .events()
+            .iter()
+            .map(|event| event_to_py(py, event))
+            .collect()
+    }
+
+    #[getter]
+    fn player_count(&self) -> usize {
+        self.update.get_player_count()
+    }
+
+    #[getter]
+    fn sequence(&self) -> u64 {
+        self.update.sequence()
+    }
+
+    /// The time the server sent the update at, in unix seconds, if it said so.
+    #[getter]
+    fn server_time(&self) -> Option<f64> {
+        unix_secs(self.update.server_time())
+    }
+
+    /// The time the update was received at, in unix seconds.
+    #[getter]
+    fn received_at(&self) -> Option<f64> {
+        unix_secs(self.update.received_at())
+    }
+
+    fn __len__(&self) -> usize {
+        self.update.events().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<MonitorUpdate {} with {} events>",
+            self.update.sequence(),
+            self.update.events().len()
+        )
+    }
+}
+
+/// A connection to a monitor port, kept up by a background thread.
+///
+/// Without a callback, updates are buffered and retrieved with `poll()` or by iterating
+/// over the Monitor. With one, the callback is called with each update instead.
+#[pyclass(name = "Monitor", module = "ffmonitor", frozen)]
+struct PyMonitor {
+    monitor: Mutex<crate::Monitor>,
+    updates: Mutex<Receiver<Arc<crate::MonitorUpdate>>>,
+}
+#[pymethods]
+impl PyMonitor {
+    #[new]
+    #[pyo3(signature = (address, callback = None))]
+    fn new(address: &str, callback: Option<Py<PyAny>>) -> PyResult<Self> {
+        let (tx, rx) = mpsc::channel();
+        let monitor = crate::Monitor::new_with_callback(
+            address,
+            Box::new(move |notification| {
+                let MonitorNotification::Updated(update) = notification else {
+                    return;
+                };
+                let Some(callback) = &callback else {
+                    let _ = tx.send(update);
+                    return;
+                };
+                Python::attach(|py| {
+                    let update = PyMonitorUpdate { update };
+                    if let Err(err) = callback.call1(py, (update,)) {
+                        warn!("Python callback raised an exception");
+                        err.display(py);
+                    }
+                });
+            }),
+        )
+        .map_err(to_py_err)?;
+        Ok(Self {
+            monitor: Mutex::new(monitor),
+            updates: Mutex::new(rx),
+        })
+    }
+
+    /// Wait for the next update, for up to `timeout` seconds if given.
+    /// Returns `None` on timeout. Raises ValueError for a negative, NaN or huge timeout.
+    #[pyo3(signature = (timeout = None))]
+    fn poll(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<PyMonitorUpdate>> {
+        let updates = &self.updates;
+        let mut remaining = timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|_| {
+                PyValueError::new_err("timeout must be a non-negative number of seconds")
+            })?;
+        loop {
+            let wait = remaining.map_or(SIGNAL_CHECK_INTERVAL, |remaining| {
+                remaining.min(SIGNAL_CHECK_INTERVAL)
+            });
+            let result = py.detach(|| updates.lock().unwrap().recv_timeout(wait));
+            match result {
+                Ok(update) => return Ok(Some(PyMonitorUpdate { update })),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(PyRuntimeError::new_err("Monitor stopped"))
+                }
+            }
+            py.check_signals()?;
+            if let Some(left) = &mut remaining {
+                *left = left.saturating_sub(wait);
+                if left.is_zero() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Get the last update received, if any.
+    fn get_last_update(&self) -> Option<PyMonitorUpdate> {
+        self.monitor
+            .lock()
+            .unwrap()
+            .get_last_update()
+            .map(|update| PyMonitorUpdate {
+                update: Arc::new(update),
+            })
+    }
+
+    fn is_connected(&self) -> bool {
+        self.monitor.lock().unwrap().is_connected()
+    }
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python) -> PyResult<Option<PyMonitorUpdate>> {
+        self.poll(py, None)
+    }
+}
+
+/// Read events off an OpenFusion monitor port.
+#[pymodule]
+#[pyo3(name = "ffmonitor")]
+fn python_module(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyMonitor>()?;
+    m.add_class::<PyMonitorUpdate>()?;
+    m.add_class::<PyPlayerEvent>()?;
+    m.add_class::<PyChatEvent>()?;
+    m.add_class::<PyBroadcastEvent>()?;
+    m.add_class::<PyEmailEvent>()?;
+    m.add_class::<PyNameRequestEvent>()?;
+    m.add_class::<PyCustomEvent>()?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
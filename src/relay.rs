@@ -0,0 +1,242 @@
+//! Sharing a single monitor connection between several Monitors.
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    authenticate, connect, logging::*, validate_address, wire, MonitorConfig, Result, RETRY_DELAY,
+};
+
+/// How long a downstream connection may block the relay before it is dropped.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Downstream {
+    stream: TcpStream,
+    /// Whether the connection has been sent the start of a frame yet.
+    /// Connections only start receiving lines between frames, so they never see half of one.
+    active: bool,
+}
+
+#[derive(Default)]
+struct RelayShared {
+    clients: Mutex<Vec<Downstream>>,
+    upstream: Mutex<Option<TcpStream>>,
+    /// The version line announced upstream, sent again to each new downstream connection.
+    version_line: Mutex<Option<Vec<u8>>>,
+    stopped: AtomicBool,
+}
+impl RelayShared {
+    /// Send a line received from upstream to every downstream connection.
+    fn forward(&self, line: &[u8], between_frames: bool) {
+        let version_line = self.version_line.lock().unwrap();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| {
+            if !client.active {
+                if !between_frames {
+                    return true;
+                }
+                client.active = true;
+                if let Some(version_line) = version_line.as_deref() {
+                    if version_line != line && client.stream.write_all(version_line).is_err() {
+                        return false;
+                    }
+                }
+            }
+            match client.stream.write_all(line) {
+                Ok(()) => true,
+                Err(err) => {
+                    debug!("Dropping relay client: {}", err);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Close every downstream connection, so their Monitors notice the upstream one is gone.
+    fn disconnect_clients(&self) {
+        for client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Connects to a monitor port once and serves the same protocol on a local socket,
+/// so several Monitors can share the connection of a server that only allows one.
+///
+/// Lines from upstream are passed on untouched, so downstream Monitors behave as if they
+/// were connected to the server directly; they only start receiving at the next frame.
+/// Commands sent by downstream Monitors are passed upstream. When the upstream connection
+/// is lost, the downstream connections are closed and the relay reconnects.
+///
+/// The relay authenticates upstream if its `MonitorConfig` says so, but downstream
+/// Monitors shouldn't be configured with an auth handshake.
+pub struct Relay {
+    local_addr: SocketAddr,
+    shared: Arc<RelayShared>,
+}
+impl Relay {
+    /// Create a Relay that connects to the monitor at `upstream` and listens on `bind`,
+    /// e.g. `127.0.0.1:8004`.
+    pub fn new(upstream: &str, bind: &str) -> Result<Self> {
+        Self::new_with_config(upstream, bind, MonitorConfig::default())
+    }
+
+    /// Create a Relay that connects to the monitor at `upstream` with the given settings
+    /// and listens on `bind`. Only the connection settings of the config are used.
+    pub fn new_with_config(upstream: &str, bind: &str, config: MonitorConfig) -> Result<Self> {
+        validate_address(upstream)?;
+        let listener = TcpListener::bind(bind)?;
+        let local_addr = listener.local_addr()?;
+        info!("Relaying monitor at {} on {}", upstream, local_addr);
+        let shared = Arc::new(RelayShared::default());
+
+        let sh = shared.clone();
+        thread::spawn(move || accept(listener, &sh));
+        let sh = shared.clone();
+        let upstream = upstream.to_string();
+        thread::spawn(move || {
+            while !sh.stopped.load(Ordering::Acquire) {
+                if let Err(err) = relay(&upstream, &config, &sh) {
+                    error!("Couldn't connect to monitor: {}", err);
+                }
+                sh.upstream.lock().unwrap().take();
+                sh.disconnect_clients();
+                if !sh.stopped.load(Ordering::Acquire) {
+                    thread::sleep(RETRY_DELAY);
+                }
+            }
+        });
+
+        Ok(Self { local_addr, shared })
+    }
+
+    /// Get the address the relay listens on, e.g. to find the port picked when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Check if the relay is connected upstream.
+    pub fn is_connected(&self) -> bool {
+        self.shared.upstream.lock().unwrap().is_some()
+    }
+
+    /// Get the number of downstream connections.
+    pub fn client_count(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+}
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        if let Some(stream) = self.shared.upstream.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        // wake the accepting thread up so it notices
+        let _ = TcpStream::connect(self.local_addr);
+    }
+}
+
+fn accept(listener: TcpListener, shared: &Arc<RelayShared>) {
+    for stream in listener.incoming() {
+        if shared.stopped.load(Ordering::Acquire) {
+            shared.disconnect_clients();
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Couldn't accept relay client: {}", err);
+                continue;
+            }
+        };
+        let peer = stream.peer_addr().ok();
+        debug!("Relay client connected from {:?}", peer);
+        let (reader, writer) = match stream.try_clone() {
+            Ok(reader) => (reader, stream),
+            Err(err) => {
+                warn!("Couldn't set up relay client: {}", err);
+                continue;
+            }
+        };
+        let _ = writer.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+        shared.clients.lock().unwrap().push(Downstream {
+            stream: writer,
+            active: false,
+        });
+
+        // pass commands upstream until the client goes away
+        let sh = shared.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                match sh.upstream.lock().unwrap().as_mut() {
+                    Some(upstream) => {
+                        if let Err(err) = upstream.write_all(format!("{}\n", line).as_bytes()) {
+                            warn!("Couldn't relay command upstream: {}", err);
+                        }
+                    }
+                    None => warn!("Not connected upstream, dropping command: {}", line),
+                }
+            }
+            debug!("Relay client from {:?} disconnected", peer);
+        });
+    }
+}
+
+/// Connect upstream and forward lines until the connection is lost.
+fn relay(address: &str, config: &MonitorConfig, shared: &RelayShared) -> Result<()> {
+    let _span = connection_span(address);
+    info!("Connecting to monitor at {}", address);
+    let stream = connect(address, config)?;
+    stream.set_read_timeout(config.read_timeout)?;
+    let mut reader = BufReader::with_capacity(config.read_buffer_size, stream.try_clone()?);
+    if let Some(auth) = &config.auth {
+        authenticate(&stream, &mut reader, auth, config)?;
+    }
+    *shared.upstream.lock().unwrap() = Some(stream);
+    shared.version_line.lock().unwrap().take();
+    if shared.stopped.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    info!("Relay connected to monitor at {}", address);
+
+    let mut in_frame = false;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => {
+                warn!("Connection closed by server");
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(err) => {
+                if !shared.stopped.load(Ordering::Acquire) {
+                    warn!("Lost connection to monitor: {}", err);
+                }
+                return Ok(());
+            }
+        }
+        let between_frames = !in_frame;
+        if between_frames && buf.starts_with(b"version ") {
+            *shared.version_line.lock().unwrap() = Some(buf.clone());
+        }
+        let keyword = buf.trim_ascii();
+        if keyword.eq_ignore_ascii_case(b"begin") {
+            in_frame = true;
+        } else if std::str::from_utf8(keyword)
+            .is_ok_and(|line| wire::FrameTrailer::parse(line, true).is_some())
+        {
+            in_frame = false;
+        }
+        shared.forward(&buf, between_frames);
+    }
+}
@@ -0,0 +1,512 @@
+//! Recording MonitorUpdates to files and playing them back.
+//!
+//! A recording is a sequence of frames in the monitor protocol's own text format,
+//! each preceded by a `time <unix_millis>` line holding the time the frame was received:
+//! ```text
+//! time 1718000000000
+//! begin
+//! player 10 -20 Captain Courage
+//! end
+//! ```
+//! Frames without a `time` line are played back immediately after the previous one,
+//! so raw captures of a monitor port can be replayed as well.
+//!
+//! A ReplayMonitor can be paused, sped up or slowed down, and seeked to any time
+//! in the recording, including back to a point that was already played.
+//!
+//! A MergedStream plays back a recording and then carries on with the updates of a live
+//! Monitor, e.g. to backfill a graph before following the server in real time.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    logging::*,
+    wire::{is_keyword, FrameTrailer},
+    MonitorUpdate, Result,
+};
+
+/// A MonitorUpdate along with the time it was received, if known.
+#[derive(Debug, Clone)]
+pub struct RecordedUpdate {
+    pub time: Option<SystemTime>,
+    pub update: MonitorUpdate,
+}
+
+/// Writes MonitorUpdates to a recording.
+pub struct Recorder<W: Write> {
+    writer: W,
+}
+impl Recorder<File> {
+    /// Create a Recorder appending to the file at the given path.
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+impl<W: Write> Recorder<W> {
+    /// Create a Recorder writing to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Record a MonitorUpdate as received now.
+    pub fn record(&mut self, update: &MonitorUpdate) -> Result<()> {
+        self.record_at(update, SystemTime::now())
+    }
+
+    /// Record a MonitorUpdate as received at the given time.
+    pub fn record_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> Result<()> {
+        let millis = time.duration_since(UNIX_EPOCH)?.as_millis();
+        write!(self.writer, "time {}\n{}", millis, update)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read every frame of a recording.
+/// Frame keywords are recognized regardless of case and surrounding whitespace, like
+/// the Monitor does, so captures of modified servers can be read too. Frames whose `end`
+/// line doesn't match their line count or checksum are read anyway, with a warning.
+pub fn read_recording(reader: impl BufRead) -> Result<Vec<RecordedUpdate>> {
+    let mut recorded = Vec::new();
+    let mut time = None;
+    let mut lines = Vec::new();
+    let mut in_frame = false;
+    for line in reader.lines() {
+        let line = line?;
+        if !in_frame {
+            if let Some(millis) = line.strip_prefix("time ") {
+                let millis = millis.parse().map_err(|_| "Invalid recording time")?;
+                let recorded_at = UNIX_EPOCH.checked_add(Duration::from_millis(millis));
+                time = Some(recorded_at.ok_or("Invalid recording time")?);
+            } else if is_keyword(&line, "begin", true) {
+                in_frame = true;
+            } else if !line.trim().is_empty() {
+                warn!("Unexpected line in recording: {}", line);
+            }
+            continue;
+        }
+
+        if let Some(trailer) = FrameTrailer::parse(&line, true) {
+            if let Err(err) = trailer.verify(&lines) {
+                warn!("{} in recording", err);
+            }
+            in_frame = false;
+            let update = MonitorUpdate::from_lines(&mut lines);
+            recorded.push(RecordedUpdate {
+                time: time.take(),
+                update,
+            });
+        } else {
+            lines.push(line);
+        }
+    }
+    Ok(recorded)
+}
+
+#[derive(Debug)]
+struct PlaybackState {
+    paused: bool,
+    speed: f64,
+    seek: Option<SystemTime>,
+    /// Recording time of the last frame played back, or of the last seek.
+    position: Option<SystemTime>,
+    finished: bool,
+    /// Stop once the end of the recording is reached instead of waiting for a seek.
+    stop_at_end: bool,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct PlaybackControl {
+    state: Mutex<PlaybackState>,
+    changed: Condvar,
+}
+impl PlaybackControl {
+    fn modify(&self, f: impl FnOnce(&mut PlaybackState)) {
+        f(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// Plays back a recording with its original timing.
+/// Updates are buffered and can be pulled with `poll()`, like with a `Monitor`, and carry
+/// the time they were recorded at as their receive time.
+///
+/// Once the end of the recording is reached, playback waits for a `seek()`
+/// until the ReplayMonitor is joined or dropped.
+pub struct ReplayMonitor {
+    handle: Option<JoinHandle<()>>,
+    rx: Receiver<MonitorUpdate>,
+    control: Arc<PlaybackControl>,
+}
+impl ReplayMonitor {
+    /// Create a ReplayMonitor playing back the recording at the given path.
+    pub fn new(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        let recorded = read_recording(BufReader::new(file))?;
+        Ok(Self::from_recording(recorded))
+    }
+
+    /// Create a ReplayMonitor playing back the given frames.
+    pub fn from_recording(recorded: Vec<RecordedUpdate>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let control = Arc::new(PlaybackControl {
+            state: Mutex::new(PlaybackState {
+                paused: false,
+                speed: 1.0,
+                seek: None,
+                position: None,
+                finished: false,
+                stop_at_end: false,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let ctl = control.clone();
+        let handle = thread::spawn(move || play(recorded, &ctl, tx));
+
+        Self {
+            handle: Some(handle),
+            rx,
+            control,
+        }
+    }
+
+    /// Check if every frame of the recording has been played back.
+    /// Updates may still be waiting to be polled.
+    pub fn is_finished(&self) -> bool {
+        self.control.state.lock().unwrap().finished
+    }
+
+    /// Return a MonitorUpdate if one is available. Does not block.
+    pub fn poll(&mut self) -> Option<MonitorUpdate> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Pause the playback. Updates already played back can still be polled.
+    pub fn pause(&self) {
+        self.control.modify(|state| state.paused = true);
+    }
+
+    /// Resume a paused playback.
+    pub fn resume(&self) {
+        self.control.modify(|state| state.paused = false);
+    }
+
+    /// Check if the playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.control.state.lock().unwrap().paused
+    }
+
+    /// Set the playback speed multiplier. `2.0` plays back twice as fast as recorded.
+    /// Panics if the speed isn't a positive number.
+    pub fn set_speed(&self, speed: f64) {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "Invalid replay speed {}",
+            speed
+        );
+        self.control.modify(|state| state.speed = speed);
+    }
+
+    /// Get the playback speed multiplier.
+    pub fn speed(&self) -> f64 {
+        self.control.state.lock().unwrap().speed
+    }
+
+    /// Continue the playback from the first frame recorded at or after the given time.
+    /// Frames without a recorded time are considered to be recorded at the time of
+    /// the previous frame.
+    pub fn seek(&self, time: SystemTime) {
+        self.control.modify(|state| {
+            state.seek = Some(time);
+            state.finished = false;
+        });
+    }
+
+    /// Get the recording time of the last frame played back, or the time of the last seek.
+    pub fn position(&self) -> Option<SystemTime> {
+        self.control.state.lock().unwrap().position
+    }
+
+    /// Wait for the playback to finish. Blocks forever if the playback is paused.
+    pub fn join(mut self) -> Result<()> {
+        self.control.modify(|state| state.stop_at_end = true);
+        let handle = self.handle.take().expect("Replay thread already joined");
+        handle.join().map_err(|_| "Replay thread panicked")?;
+        Ok(())
+    }
+}
+impl Drop for ReplayMonitor {
+    fn drop(&mut self) {
+        self.control.modify(|state| state.closed = true);
+    }
+}
+
+fn play(recorded: Vec<RecordedUpdate>, control: &PlaybackControl, tx: Sender<MonitorUpdate>) {
+    // frames without a time are played back at the time of the previous frame
+    let times: Vec<Option<SystemTime>> = recorded
+        .iter()
+        .scan(None, |last, frame| {
+            *last = frame.time.or(*last);
+            Some(*last)
+        })
+        .collect();
+
+    let mut idx = 0;
+    let mut playhead: Option<SystemTime> = None;
+    let mut state = control.state.lock().unwrap();
+    loop {
+        if state.closed {
+            return;
+        }
+        if let Some(target) = state.seek.take() {
+            idx = times
+                .iter()
+                .position(|time| time.is_some_and(|time| time >= target))
+                .unwrap_or(times.len());
+            playhead = Some(target);
+            state.position = playhead;
+            continue;
+        }
+        if state.paused {
+            state = control.changed.wait(state).unwrap();
+            continue;
+        }
+        if idx == recorded.len() {
+            state.finished = true;
+            if state.stop_at_end {
+                return;
+            }
+            state = control.changed.wait(state).unwrap();
+            continue;
+        }
+
+        let time = times[idx];
+        let delay = match (playhead, time) {
+            // frames recorded out of order are played back immediately
+            (Some(playhead), Some(time)) => time.duration_since(playhead).unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        if !delay.is_zero() {
+            // wait in real time, but wake up early if the playback is changed
+            let speed = state.speed;
+            let wait = delay.div_f64(speed);
+            let started = Instant::now();
+            let (guard, result) = control.changed.wait_timeout(state, wait).unwrap();
+            state = guard;
+            playhead = if result.timed_out() {
+                time
+            } else {
+                let played = started.elapsed().mul_f64(speed).min(delay);
+                playhead.map(|playhead| playhead + played)
+            };
+            continue;
+        }
+
+        playhead = time.or(playhead);
+        state.position = playhead;
+        let mut update = recorded[idx].update.clone();
+        if update.received_at().is_none() {
+            update.set_received_at(recorded[idx].time);
+        }
+        idx += 1;
+        drop(state);
+        if tx.send(update).is_err() {
+            return;
+        }
+        state = control.state.lock().unwrap();
+    }
+}
+
+#[cfg(feature = "net")]
+pub use merged::{MergedStream, MergedUpdate};
+
+#[cfg(feature = "net")]
+mod merged {
+    use std::{
+        sync::Arc,
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::ReplayMonitor;
+    use crate::{logging::*, Monitor, MonitorUpdate};
+
+    /// How often `recv_timeout()` checks for updates while the recording plays back.
+    const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// An update from a MergedStream.
+    #[derive(Debug, Clone)]
+    pub enum MergedUpdate {
+        /// An update played back from the recording.
+        Historical(MonitorUpdate),
+        /// The recording is over, and every update from now on is live.
+        /// Sent once, between the last historical update and the first live one.
+        WentLive {
+            /// The time of the last historical update, if any had one.
+            last_historical: Option<SystemTime>,
+        },
+        /// An update received by the live Monitor.
+        Live(Arc<MonitorUpdate>),
+    }
+    impl MergedUpdate {
+        /// Get the MonitorUpdate, historical or live.
+        pub fn update(&self) -> Option<&MonitorUpdate> {
+            match self {
+                Self::Historical(update) => Some(update),
+                Self::Live(update) => Some(update),
+                Self::WentLive { .. } => None,
+            }
+        }
+    }
+
+    /// Plays back a ReplayMonitor to the end, then hands out the updates of a live
+    /// Monitor, in time order.
+    ///
+    /// The Monitor buffers the updates it receives while the recording plays back.
+    /// Once it's over, the ones no later than the last historical update are dropped,
+    /// since the recording already covered them, e.g. when it was still being recorded
+    /// as the Monitor connected. Speed the replay up with `replay()` and
+    /// `ReplayMonitor::set_speed()` to backfill faster. A paused replay holds back the
+    /// live updates until it's resumed.
+    pub struct MergedStream {
+        replay: Option<ReplayMonitor>,
+        live: Monitor,
+        last_historical: Option<SystemTime>,
+        /// Live updates up to this time are dropped, until the first one after it.
+        covered_until: Option<SystemTime>,
+    }
+    impl MergedStream {
+        /// Merge the playback of a ReplayMonitor with the updates of a live Monitor.
+        pub fn new(replay: ReplayMonitor, live: Monitor) -> Self {
+            Self {
+                replay: Some(replay),
+                live,
+                last_historical: None,
+                covered_until: None,
+            }
+        }
+
+        /// Check if the recording is over and updates come from the live Monitor.
+        pub fn is_live(&self) -> bool {
+            self.replay.is_none()
+        }
+
+        /// Get the ReplayMonitor, e.g. to change its speed, until the recording is over.
+        pub fn replay(&self) -> Option<&ReplayMonitor> {
+            self.replay.as_ref()
+        }
+
+        /// Get the live Monitor, e.g. to check its health.
+        pub fn live(&self) -> &Monitor {
+            &self.live
+        }
+
+        /// Stop merging, keeping the live Monitor. Updates it buffered during the
+        /// playback are kept, including the ones the recording already covered.
+        pub fn into_live(self) -> Monitor {
+            self.live
+        }
+
+        /// Return a MergedUpdate if one is available. Does not block.
+        pub fn poll(&mut self) -> Option<MergedUpdate> {
+            let Some(replay) = &mut self.replay else {
+                while let Some(update) = self.live.poll() {
+                    if let Some(update) = self.uncovered(update) {
+                        return Some(update);
+                    }
+                }
+                return None;
+            };
+            // everything is sent by the time playback is finished
+            let finished = replay.is_finished();
+            if let Some(update) = replay.poll() {
+                let time = update.timestamp();
+                self.last_historical = time.max(self.last_historical);
+                return Some(MergedUpdate::Historical(update));
+            }
+            if !finished {
+                return None;
+            }
+            self.replay = None;
+            self.covered_until = self.last_historical;
+            Some(MergedUpdate::WentLive {
+                last_historical: self.last_historical,
+            })
+        }
+
+        /// Wait for the next MergedUpdate, for up to the given time.
+        /// Returns `None` on timeout, or if the Monitor thread has stopped.
+        pub fn recv_timeout(&mut self, timeout: Duration) -> Option<MergedUpdate> {
+            let deadline = Instant::now() + timeout;
+            while !self.is_live() {
+                if let Some(update) = self.poll() {
+                    return Some(update);
+                }
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return None;
+                }
+                thread::sleep(left.min(REPLAY_POLL_INTERVAL));
+            }
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let update = self.live.recv_timeout(left)?;
+                if let Some(update) = self.uncovered(update) {
+                    return Some(update);
+                }
+            }
+        }
+
+        /// Drop a live update the recording already covered. Updates with no time to
+        /// compare are kept, and so is everything after the first update kept.
+        fn uncovered(&mut self, update: Arc<MonitorUpdate>) -> Option<MergedUpdate> {
+            if let Some(covered_until) = self.covered_until {
+                if update.timestamp().is_some_and(|time| time <= covered_until) {
+                    debug!("Dropping live update covered by the recording");
+                    return None;
+                }
+                self.covered_until = None;
+            }
+            Some(MergedUpdate::Live(update))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn recordings_accept_lenient_keywords() {
+        let capture = "BEGIN\nplayer 1 2 Bob\n End \ntime 1718000000000\r\nbegin\r\nend\r\n";
+        let recorded = read_recording(Cursor::new(capture)).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].update.get_player_count(), 1);
+        assert!(recorded[1].time.is_some());
+    }
+
+    #[test]
+    fn recordings_accept_frame_trailers() {
+        let trailer = FrameTrailer::for_lines(&["player 1 2 Bob"]);
+        let capture = format!("begin\nplayer 1 2 Bob\n{}\nbegin\nend 0\n", trailer);
+        let recorded = read_recording(Cursor::new(capture)).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].update.get_player_count(), 1);
+        assert!(recorded[1].update.is_empty());
+    }
+}
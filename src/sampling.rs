@@ -0,0 +1,106 @@
+//! Keeping only a share of the events of high-volume kinds, e.g. 1 in 10 player events,
+//! to cut the cost of storing and processing them while keeping every chat.
+//!
+//! A Sampler can be set on a Monitor with `MonitorConfig::with_sampling()`, so updates
+//! are sampled before they're buffered or handed to subscribers, or on a
+//! `sink::EventPipeline` with `with_sampler()`, so its sinks only get the events kept.
+//!
+//! Events are sampled in blocks: out of every N events of a kind, one picked at random
+//! is kept. Exactly 1 in N get through, and since which one changes from block to block,
+//! frames listing the same players in the same order don't always keep the same player.
+//! Counts of sampled events can be scaled back up with `Sampler::one_in()`.
+
+use std::collections::HashMap;
+
+use crate::{simulator::Rng, Event, EventKind, MonitorUpdate};
+
+#[derive(Debug, Clone)]
+struct KindSampler {
+    one_in: u32,
+    /// The position of the next event in the current block.
+    position: u32,
+    /// The position of the event kept in the current block.
+    pick: u32,
+    dropped: u64,
+}
+
+/// Decides which events to keep, kind by kind. Kinds without a rate are all kept.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    kinds: HashMap<EventKind, KindSampler>,
+    rng: Rng,
+}
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            kinds: HashMap::new(),
+            rng: Rng(0x9e37_79b9_7f4a_7c15),
+        }
+    }
+}
+impl Sampler {
+    /// Create a Sampler keeping every event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed the random choice of the event kept out of each block.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        self.rng = Rng(seed.max(1));
+        self
+    }
+
+    /// Keep 1 in `n` events of the given kind. 1 keeps every event, and 0 drops them all.
+    pub fn with_one_in(mut self, kind: EventKind, n: u32) -> Self {
+        self.kinds.insert(
+            kind,
+            KindSampler {
+                one_in: n,
+                position: 0,
+                pick: 0,
+                dropped: 0,
+            },
+        );
+        self
+    }
+
+    /// Get how many events of the given kind each kept one stands for: the `n` of
+    /// `with_one_in()`, 1 for kinds that aren't sampled, or 0 for kinds that are dropped.
+    pub fn one_in(&self, kind: EventKind) -> u32 {
+        self.kinds.get(&kind).map_or(1, |sampler| sampler.one_in)
+    }
+
+    /// Get how many events of the given kind were dropped so far.
+    pub fn dropped_count(&self, kind: EventKind) -> u64 {
+        self.kinds.get(&kind).map_or(0, |sampler| sampler.dropped)
+    }
+
+    /// Decide whether to keep an Event, counting it towards its block.
+    pub fn keep(&mut self, event: &Event) -> bool {
+        let Some(sampler) = self.kinds.get_mut(&event.kind()) else {
+            return true;
+        };
+        let keep = match sampler.one_in {
+            0 => false,
+            1 => true,
+            n => {
+                if sampler.position == 0 {
+                    sampler.pick = self.rng.below(n as u64) as u32;
+                }
+                let keep = sampler.position == sampler.pick;
+                sampler.position = (sampler.position + 1) % n;
+                keep
+            }
+        };
+        if !keep {
+            sampler.dropped += 1;
+        }
+        keep
+    }
+
+    /// Drop the events of a MonitorUpdate that aren't kept.
+    pub fn apply(&mut self, update: &mut MonitorUpdate) {
+        update.retain_events(|event| self.keep(event));
+    }
+}
@@ -0,0 +1,149 @@
+//! Monitoring several shards of a server at once.
+//!
+//! Shards can be listed in a file with one `<label> <address>` pair per line.
+//! Empty lines and lines starting with `#` are ignored:
+//! ```text
+//! # label  address
+//! shard1   10.0.0.1:8003
+//! shard2   10.0.0.2:8003
+//! ```
+
+use std::{fs, sync::Arc};
+
+use crate::{Monitor, MonitorConfig, MonitorUpdate, Result};
+
+/// The address of a shard's monitor port, along with a label naming the shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardEndpoint {
+    pub label: String,
+    pub address: String,
+}
+impl ShardEndpoint {
+    pub fn new(label: &str, address: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            address: address.to_string(),
+        }
+    }
+}
+
+/// Parse a list of shards in the format described in the module documentation.
+pub fn parse_shard_list(text: &str) -> Result<Vec<ShardEndpoint>> {
+    let mut endpoints = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let (Some(label), Some(address), None) = (tokens.next(), tokens.next(), tokens.next())
+        else {
+            return Err(format!("Malformed shard line: {}", line).into());
+        };
+        endpoints.push(ShardEndpoint::new(label, address));
+    }
+    Ok(endpoints)
+}
+
+/// A MonitorUpdate tagged with the label of the shard it came from.
+#[derive(Debug, Clone)]
+pub struct ShardedUpdate {
+    pub shard: String,
+    pub update: Arc<MonitorUpdate>,
+}
+
+/// Maintains a Monitor connection to each of several shards.
+/// Updates from every shard are buffered and can be pulled with `poll()`.
+pub struct ShardedMonitor {
+    shards: Vec<(String, Monitor)>,
+    next_shard: usize,
+}
+impl ShardedMonitor {
+    /// Create a ShardedMonitor connecting to each of the given shards.
+    pub fn new(endpoints: &[ShardEndpoint]) -> Result<Self> {
+        Self::new_with_config(endpoints, MonitorConfig::default())
+    }
+
+    /// Create a ShardedMonitor connecting to each of the given shards with the given settings.
+    pub fn new_with_config(endpoints: &[ShardEndpoint], config: MonitorConfig) -> Result<Self> {
+        let mut shards: Vec<(String, Monitor)> = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            if shards.iter().any(|(label, _)| *label == endpoint.label) {
+                return Err(format!("Duplicate shard label {}", endpoint.label).into());
+            }
+            let monitor = Monitor::new_with_config(&endpoint.address, config.clone())?;
+            shards.push((endpoint.label.clone(), monitor));
+        }
+        Ok(Self {
+            shards,
+            next_shard: 0,
+        })
+    }
+
+    /// Create a ShardedMonitor connecting to each shard listed in the file at the given path.
+    pub fn from_file(path: &str, config: MonitorConfig) -> Result<Self> {
+        let endpoints = parse_shard_list(&fs::read_to_string(path)?)?;
+        Self::new_with_config(&endpoints, config)
+    }
+
+    /// Return a ShardedUpdate if one is available from any shard. Does not block.
+    /// Shards take turns, so a busy shard can't starve the others.
+    pub fn poll(&mut self) -> Option<ShardedUpdate> {
+        let count = self.shards.len();
+        for offset in 0..count {
+            let idx = (self.next_shard + offset) % count;
+            let (label, monitor) = &mut self.shards[idx];
+            if let Some(update) = monitor.poll() {
+                self.next_shard = (idx + 1) % count;
+                return Some(ShardedUpdate {
+                    shard: label.clone(),
+                    update,
+                });
+            }
+        }
+        None
+    }
+
+    /// Get the labels of the shards, in the order they were given.
+    pub fn shard_labels(&self) -> impl Iterator<Item = &str> {
+        self.shards.iter().map(|(label, _)| label.as_str())
+    }
+
+    /// Get the Monitor of the shard with the given label.
+    pub fn get(&self, label: &str) -> Option<&Monitor> {
+        self.shards
+            .iter()
+            .find(|(shard, _)| shard == label)
+            .map(|(_, monitor)| monitor)
+    }
+
+    /// Get the number of players online on the shard with the given label,
+    /// as of its last update. Disconnected shards have no players online.
+    pub fn player_count(&self, label: &str) -> Option<usize> {
+        self.get(label).map(Self::monitor_player_count)
+    }
+
+    /// Get the number of players online on each shard, in the order the shards were given.
+    pub fn player_counts(&self) -> Vec<(&str, usize)> {
+        self.shards
+            .iter()
+            .map(|(label, monitor)| (label.as_str(), Self::monitor_player_count(monitor)))
+            .collect()
+    }
+
+    /// Get the number of players online across every shard.
+    pub fn total_player_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|(_, monitor)| Self::monitor_player_count(monitor))
+            .sum()
+    }
+
+    fn monitor_player_count(monitor: &Monitor) -> usize {
+        if !monitor.is_connected() {
+            return 0;
+        }
+        monitor
+            .get_last_update()
+            .map_or(0, |update| update.get_player_count())
+    }
+}
@@ -0,0 +1,462 @@
+//! Generating synthetic monitor traffic, for load testing dashboards, sinks and the parser.
+//!
+//! A Simulation keeps a population of made-up players moving around the map, chatting,
+//! and coming and going, and produces one MonitorUpdate per tick. Simulations are seeded,
+//! so the same settings always produce the same traffic. With the `net` feature, a
+//! SimulatedServer serves the frames of a Simulation on a local socket, so Monitors and
+//! other tools can connect to it as they would to an OpenFusion server.
+
+use crate::{
+    map::{Position, MAP_SIZE},
+    AnnouncementType, BroadcastEvent, BroadcastScope, ChatEvent, ChatKind, EmailEvent, Event,
+    MonitorUpdate, NameRequestEvent, PlayerEvent,
+};
+
+const FIRST_NAMES: &[&str] = &[
+    "Captain", "Sparky", "Jolly", "Mighty", "Lucky", "Brave", "Swift", "Dizzy", "Cosmic", "Silent",
+    "Rusty", "Sunny", "Shadow", "Turbo", "Frosty", "Bubbles",
+];
+const LAST_NAMES: &[&str] = &[
+    "Courage", "Dash", "Bolt", "Rider", "Spark", "Storm", "Blaze", "Comet", "Ace", "Nova", "Flash",
+    "Knight", "Echo", "Rocket", "Dynamo", "Gizmo",
+];
+const MESSAGES: &[&str] = &[
+    "hi",
+    "anyone want to team up?",
+    "where do I find the next nano?",
+    "lol",
+    "that boss is tough",
+    "brb",
+    "selling crates, pm me",
+    "how do I get to the Wilds?",
+    "thanks!",
+    "gg",
+];
+const ANNOUNCEMENTS: &[&str] = &[
+    "Server restart in 10 minutes",
+    "Double taros weekend is live!",
+    "Maintenance is over, have fun",
+];
+
+/// How simulated players move between ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    /// Players stay where they first appeared.
+    Stationary,
+    /// Players move by up to `max_step` units along each axis every tick.
+    RandomWalk { max_step: u32 },
+    /// Players jump to a random position every tick, the worst case for movement diffs.
+    Teleport,
+}
+
+/// A small xorshift generator, so simulations are reproducible without a dependency.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(pub(crate) u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..bound`, for a non-zero bound.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// Get a number between `min` and `max`, both included.
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + self.below((max - min) as u64 + 1) as i64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        (self.next() >> 11) as f64 / ((1u64 << 53) as f64) < probability
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    /// How many things happen in a tick, for a mean of `rate` per tick.
+    fn count(&mut self, rate: f64) -> usize {
+        let whole = rate.floor();
+        whole as usize + self.chance(rate - whole) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SimulatedPlayer {
+    name: String,
+    position: Position,
+}
+
+/// Generates the frames of a made-up server, one per tick.
+///
+/// Every rate is the mean number of events per tick; fractional rates produce an event
+/// on that fraction of ticks.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    rng: Rng,
+    players: Vec<SimulatedPlayer>,
+    player_count: usize,
+    next_name: u64,
+    movement: Movement,
+    churn: f64,
+    chat_rate: f64,
+    broadcast_rate: f64,
+    email_rate: f64,
+    name_request_rate: f64,
+}
+impl Simulation {
+    /// Create a Simulation of the given number of players walking around and chatting.
+    /// By default, players move by up to 500 units a tick, 1% of them are replaced by
+    /// new players every tick, and there are 2 chats, 0.05 emails, 0.01 name requests
+    /// and 0.002 broadcasts per tick.
+    pub fn new(player_count: usize) -> Self {
+        let mut simulation = Self {
+            rng: Rng(0x2545_f491_4f6c_dd1d),
+            players: Vec::new(),
+            player_count,
+            next_name: 0,
+            movement: Movement::RandomWalk { max_step: 500 },
+            churn: 0.01,
+            chat_rate: 2.0,
+            broadcast_rate: 0.002,
+            email_rate: 0.05,
+            name_request_rate: 0.01,
+        };
+        simulation.populate();
+        simulation
+    }
+
+    /// Seed the random choices of the Simulation, starting it over with new players.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        self.rng = Rng(seed.max(1));
+        self.players.clear();
+        self.next_name = 0;
+        self.populate();
+        self
+    }
+
+    /// Set how players move. Defaults to `Movement::RandomWalk { max_step: 500 }`.
+    pub fn with_movement(mut self, movement: Movement) -> Self {
+        self.movement = movement;
+        self
+    }
+
+    /// Set the fraction of players replaced by new ones every tick.
+    pub fn with_churn(mut self, churn: f64) -> Self {
+        self.churn = churn;
+        self
+    }
+
+    /// Set the mean number of chats per tick.
+    pub fn with_chat_rate(mut self, rate: f64) -> Self {
+        self.chat_rate = rate;
+        self
+    }
+
+    /// Set the mean number of broadcasts per tick.
+    pub fn with_broadcast_rate(mut self, rate: f64) -> Self {
+        self.broadcast_rate = rate;
+        self
+    }
+
+    /// Set the mean number of emails per tick.
+    pub fn with_email_rate(mut self, rate: f64) -> Self {
+        self.email_rate = rate;
+        self
+    }
+
+    /// Set the mean number of name requests per tick.
+    pub fn with_name_request_rate(mut self, rate: f64) -> Self {
+        self.name_request_rate = rate;
+        self
+    }
+
+    /// Get the number of players online.
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    fn populate(&mut self) {
+        while self.players.len() < self.player_count {
+            let player = self.new_player();
+            self.players.push(player);
+        }
+    }
+
+    fn new_player(&mut self) -> SimulatedPlayer {
+        // the first names are read in order so every combination is used once
+        // before numbers are added to last names
+        let combinations = (FIRST_NAMES.len() * LAST_NAMES.len()) as u64;
+        let idx = self.next_name;
+        self.next_name += 1;
+        let first = FIRST_NAMES[(idx % FIRST_NAMES.len() as u64) as usize];
+        let last = LAST_NAMES[(idx / FIRST_NAMES.len() as u64 % LAST_NAMES.len() as u64) as usize];
+        let name = match idx / combinations {
+            0 => format!("{} {}", first, last),
+            round => format!("{} {}{}", first, last, round + 1),
+        };
+        SimulatedPlayer {
+            name,
+            position: self.random_position(),
+        }
+    }
+
+    fn random_position(&mut self) -> Position {
+        Position::new(
+            self.rng.range(0, MAP_SIZE as i64 - 1) as i32,
+            self.rng.range(0, MAP_SIZE as i64 - 1) as i32,
+        )
+    }
+
+    fn random_player(&mut self) -> Option<String> {
+        if self.players.is_empty() {
+            return None;
+        }
+        let idx = self.rng.below(self.players.len() as u64) as usize;
+        Some(self.players[idx].name.clone())
+    }
+
+    fn step(&mut self) {
+        let leaving = self.rng.count(self.players.len() as f64 * self.churn);
+        for _ in 0..leaving.min(self.players.len()) {
+            let idx = self.rng.below(self.players.len() as u64) as usize;
+            self.players.swap_remove(idx);
+        }
+        self.populate();
+
+        for idx in 0..self.players.len() {
+            let position = match self.movement {
+                Movement::Stationary => continue,
+                Movement::RandomWalk { max_step } => {
+                    let position = self.players[idx].position;
+                    let max_step = max_step as i64;
+                    let mut walk = |coord: i32| {
+                        let step = self.rng.range(-max_step, max_step);
+                        (coord as i64 + step).clamp(0, MAP_SIZE as i64 - 1) as i32
+                    };
+                    Position::new(walk(position.x), walk(position.y))
+                }
+                Movement::Teleport => self.random_position(),
+            };
+            self.players[idx].position = position;
+        }
+    }
+
+    fn chat(&mut self) -> Option<Event> {
+        let from = self.random_player()?;
+        let (kind, to) = match self.rng.below(10) {
+            0..=5 => (ChatKind::FreeChat, None),
+            6 | 7 => (ChatKind::MenuChat, None),
+            8 => (ChatKind::GroupChat, None),
+            _ => (ChatKind::BuddyChat, self.random_player()),
+        };
+        Some(Event::Chat(ChatEvent {
+            kind,
+            from,
+            to,
+            message: self.rng.pick(MESSAGES).to_string(),
+            extra: Vec::new(),
+        }))
+    }
+
+    fn email(&mut self) -> Option<Event> {
+        let from = self.random_player()?;
+        let to = self.random_player()?;
+        let taros = self.rng.chance(0.3).then(|| self.rng.below(10_000) + 1);
+        Some(Event::Email(EmailEvent {
+            from,
+            to,
+            subject: Some(self.rng.pick(MESSAGES).to_string()),
+            body: vec![
+                self.rng.pick(MESSAGES).to_string(),
+                self.rng.pick(MESSAGES).to_string(),
+            ],
+            taros,
+            items: Vec::new(),
+            extra: Vec::new(),
+        }))
+    }
+
+    /// Advance the Simulation by a tick and get the frame the server would send.
+    pub fn next_update(&mut self) -> MonitorUpdate {
+        self.step();
+        let mut update = MonitorUpdate::default();
+        for player in &self.players {
+            update.add_event(Event::Player(PlayerEvent {
+                name: player.name.clone(),
+                x_coord: player.position.x,
+                y_coord: player.position.y,
+                extra: Vec::new(),
+            }));
+        }
+        for _ in 0..self.rng.count(self.chat_rate) {
+            if let Some(chat) = self.chat() {
+                update.add_event(chat);
+            }
+        }
+        for _ in 0..self.rng.count(self.broadcast_rate) {
+            update.add_event(Event::Broadcast(BroadcastEvent {
+                scope: BroadcastScope::Shard,
+                announcement_type: match self.rng.chance(0.5) {
+                    true => AnnouncementType::Box,
+                    false => AnnouncementType::Ticker,
+                },
+                duration_secs: 10,
+                from: "GM".to_string(),
+                message: self.rng.pick(ANNOUNCEMENTS).to_string(),
+                extra: Vec::new(),
+            }));
+        }
+        for _ in 0..self.rng.count(self.email_rate) {
+            if let Some(email) = self.email() {
+                update.add_event(email);
+            }
+        }
+        for _ in 0..self.rng.count(self.name_request_rate) {
+            let idx = self.next_name;
+            let requested_name = self.new_player().name;
+            self.next_name = idx;
+            update.add_event(Event::NameRequest(NameRequestEvent {
+                player_uid: self.rng.below(1_000_000) + 1,
+                requested_name,
+                extra: Vec::new(),
+            }));
+        }
+        update
+    }
+}
+impl Iterator for Simulation {
+    type Item = MonitorUpdate;
+
+    /// Never runs out.
+    fn next(&mut self) -> Option<MonitorUpdate> {
+        Some(self.next_update())
+    }
+}
+
+#[cfg(feature = "net")]
+pub use server::SimulatedServer;
+
+#[cfg(feature = "net")]
+mod server {
+    use std::{
+        io::Write as _,
+        net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use super::Simulation;
+    use crate::{logging::*, Result};
+
+    /// How long a slow client may block the server before it is dropped.
+    const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Default)]
+    struct ServerShared {
+        clients: Mutex<Vec<TcpStream>>,
+        stopped: AtomicBool,
+    }
+
+    /// Serves the frames of a Simulation on a local socket, one per tick, to every
+    /// client connected, like an OpenFusion monitor port. Stops once dropped.
+    pub struct SimulatedServer {
+        local_addr: SocketAddr,
+        shared: Arc<ServerShared>,
+    }
+    impl SimulatedServer {
+        /// Serve the frames of the given Simulation on `bind`, e.g. `127.0.0.1:0`,
+        /// sending one every `tick`. Frames carry the time they were sent at.
+        pub fn bind(mut simulation: Simulation, bind: &str, tick: Duration) -> Result<Self> {
+            let listener = TcpListener::bind(bind)?;
+            let local_addr = listener.local_addr()?;
+            info!("Serving simulated monitor traffic on {}", local_addr);
+            let shared = Arc::new(ServerShared::default());
+
+            let sh = shared.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if sh.stopped.load(Ordering::Acquire) {
+                        return;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let _ = stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+                            sh.clients.lock().unwrap().push(stream);
+                        }
+                        Err(err) => warn!("Couldn't accept simulator client: {}", err),
+                    }
+                }
+            });
+            let sh = shared.clone();
+            thread::spawn(move || {
+                while !sh.stopped.load(Ordering::Acquire) {
+                    let mut update = simulation.next_update();
+                    update.set_server_time(Some(SystemTime::now()));
+                    let frame = update.to_string();
+                    sh.clients.lock().unwrap().retain_mut(|client| {
+                        match client.write_all(frame.as_bytes()) {
+                            Ok(()) => true,
+                            Err(err) => {
+                                debug!("Dropping simulator client: {}", err);
+                                false
+                            }
+                        }
+                    });
+                    thread::sleep(tick);
+                }
+            });
+
+            Ok(Self { local_addr, shared })
+        }
+
+        /// Get the address the server listens on, e.g. to find the port picked when
+        /// binding to port 0.
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
+        /// Get the number of clients connected.
+        pub fn client_count(&self) -> usize {
+            self.shared.clients.lock().unwrap().len()
+        }
+    }
+    impl Drop for SimulatedServer {
+        fn drop(&mut self) {
+            self.shared.stopped.store(true, Ordering::Release);
+            for client in self.shared.clients.lock().unwrap().drain(..) {
+                let _ = client.shutdown(Shutdown::Both);
+            }
+            // wake the accepting thread up so it notices
+            let _ = TcpStream::connect(self.local_addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_steps_stay_on_the_map() {
+        let mut simulation =
+            Simulation::new(20).with_movement(Movement::RandomWalk { max_step: u32::MAX });
+        for _ in 0..50 {
+            for event in simulation.next_update().events() {
+                if let Event::Player(player) = event {
+                    assert!((0..MAP_SIZE).contains(&player.x_coord));
+                    assert!((0..MAP_SIZE).contains(&player.y_coord));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Fanning events out to several outputs at once.
+//!
+//! An EventPipeline runs each of its EventSinks on its own thread, so a slow sink
+//! (e.g. a rate-limited webhook) doesn't hold back the others. A sink that fails only
+//! has its error logged, and a sink that panics is dropped without affecting the rest.
+//! With a `classify::ChatClassifier`, each chat is tagged once before being sent to the
+//! sinks, which get the tags in `EventSink::handle_tagged()`.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    classify::{ChatClassifier, ChatTags},
+    enrich::EnrichedEvent,
+    logging::*,
+    sampling::Sampler,
+    Event, EventKind, MonitorUpdate, Result,
+};
+
+/// An output for events, such as a file, a database or a chat webhook.
+pub trait EventSink: Send {
+    /// Handle a single Event.
+    fn handle(&mut self, event: &Event) -> Result<()>;
+
+    /// Handle a chat along with the tags the pipeline's ChatClassifier gave it.
+    /// Defaults to `handle()`, ignoring the tags.
+    fn handle_tagged(&mut self, event: &Event, tags: &ChatTags) -> Result<()> {
+        let _ = tags;
+        self.handle(event)
+    }
+
+    /// Flush any buffered output. Called once the pipeline shuts down.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl<F: FnMut(&Event) -> Result<()> + Send> EventSink for F {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        self(event)
+    }
+}
+
+type EventFilter = Box<dyn Fn(&Event) -> bool + Send>;
+type TaggedEvent = EnrichedEvent<Option<ChatTags>>;
+
+struct SinkWorker {
+    name: String,
+    tx: Sender<Arc<TaggedEvent>>,
+    handle: JoinHandle<()>,
+    errors: Arc<AtomicU64>,
+}
+
+/// Delivers every event to a set of named EventSinks, each running on its own thread.
+#[derive(Default)]
+pub struct EventPipeline {
+    workers: Vec<SinkWorker>,
+    classifier: Mutex<Option<Box<dyn ChatClassifier>>>,
+    sampler: Mutex<Option<Sampler>>,
+}
+impl EventPipeline {
+    /// Create an EventPipeline with no sinks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tag every chat with the given classifier before it's sent to the sinks.
+    /// The classifier runs once per chat, on the thread sending events to the pipeline.
+    pub fn with_classifier(self, classifier: impl ChatClassifier + 'static) -> Self {
+        *self.classifier.lock().unwrap() = Some(Box::new(classifier));
+        self
+    }
+
+    /// Only send the events kept by the given Sampler to the sinks.
+    /// Dropped events aren't classified either.
+    pub fn with_sampler(self, sampler: Sampler) -> Self {
+        *self.sampler.lock().unwrap() = Some(sampler);
+        self
+    }
+
+    /// Get how many events of the given kind the pipeline's Sampler dropped so far.
+    pub fn sampled_out_count(&self, kind: EventKind) -> u64 {
+        self.sampler
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |sampler| sampler.dropped_count(kind))
+    }
+
+    /// Add a sink receiving every event.
+    pub fn with_sink(self, name: &str, sink: impl EventSink + 'static) -> Self {
+        self.with_filtered_sink(name, sink, |_| true)
+    }
+
+    /// Add a sink receiving only the events the filter returns `true` for.
+    pub fn with_filtered_sink(
+        mut self,
+        name: &str,
+        mut sink: impl EventSink + 'static,
+        filter: impl Fn(&Event) -> bool + Send + 'static,
+    ) -> Self {
+        let filter: EventFilter = Box::new(filter);
+        let (tx, rx) = mpsc::channel::<Arc<TaggedEvent>>();
+        let errors = Arc::new(AtomicU64::new(0));
+
+        let sink_name = name.to_string();
+        let errs = errors.clone();
+        let handle = thread::spawn(move || {
+            for tagged in rx {
+                let event = &tagged.event;
+                if !filter(event) {
+                    continue;
+                }
+                let handle = || match &tagged.metadata {
+                    Some(tags) => sink.handle_tagged(event, tags),
+                    None => sink.handle(event),
+                };
+                match panic::catch_unwind(AssertUnwindSafe(handle)) {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => {
+                        warn!(
+                            "Sink {} couldn't handle event ({}): {}",
+                            sink_name, err, event
+                        );
+                        errs.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(_) => {
+                        error!("Sink {} panicked, dropping it", sink_name);
+                        errs.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            }
+            if let Err(err) = sink.flush() {
+                warn!("Sink {} couldn't flush: {}", sink_name, err);
+            }
+        });
+
+        self.workers.push(SinkWorker {
+            name: name.to_string(),
+            tx,
+            handle,
+            errors,
+        });
+        self
+    }
+
+    /// Send an Event to every sink.
+    pub fn send_event(&self, event: Event) {
+        if let Some(sampler) = self.sampler.lock().unwrap().as_mut() {
+            if !sampler.keep(&event) {
+                return;
+            }
+        }
+        let tags = self
+            .classifier
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|classifier| classifier.classify_event(&event));
+        let tagged = Arc::new(EnrichedEvent {
+            event,
+            metadata: tags,
+        });
+        for worker in &self.workers {
+            // a sink that panicked has stopped receiving
+            let _ = worker.tx.send(tagged.clone());
+        }
+    }
+
+    /// Send every event of a MonitorUpdate to every sink.
+    pub fn send_update(&self, update: &MonitorUpdate) {
+        for event in update.events() {
+            self.send_event(event.clone());
+        }
+    }
+
+    /// Get the names of the sinks, in the order they were added.
+    pub fn sink_names(&self) -> impl Iterator<Item = &str> {
+        self.workers.iter().map(|worker| worker.name.as_str())
+    }
+
+    /// Get the number of events the sink with the given name failed to handle.
+    pub fn error_count(&self, name: &str) -> Option<u64> {
+        self.workers
+            .iter()
+            .find(|worker| worker.name == name)
+            .map(|worker| worker.errors.load(Ordering::Relaxed))
+    }
+
+    /// Send updates from the given Receiver (e.g. from `Monitor::subscribe()`)
+    /// to every sink on a background thread, until the sending side goes away.
+    pub fn spawn(self, updates: Receiver<Arc<MonitorUpdate>>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for update in updates {
+                self.send_update(&update);
+            }
+            self.shutdown();
+        })
+    }
+
+    /// Wait for every sink to handle the events sent so far, flush them, and stop them.
+    pub fn shutdown(self) {
+        for worker in self.workers {
+            drop(worker.tx);
+            if worker.handle.join().is_err() {
+                error!("Sink {} thread panicked", worker.name);
+            }
+        }
+    }
+}
+
+/// Writes events to a JSON Lines file, one serialized Event per line.
+/// Custom events are written as their token and line.
+#[cfg(feature = "json")]
+pub struct JsonlSink<W: std::io::Write + Send> {
+    writer: W,
+}
+#[cfg(feature = "json")]
+impl JsonlSink<std::io::BufWriter<std::fs::File>> {
+    /// Create a JsonlSink appending to the file at the given path.
+    pub fn create(path: &str) -> Result<Self> {
+        let file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::new(std::io::BufWriter::new(file)))
+    }
+}
+#[cfg(feature = "json")]
+impl<W: std::io::Write + Send> JsonlSink<W> {
+    /// Create a JsonlSink writing to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+#[cfg(feature = "json")]
+impl<W: std::io::Write + Send> EventSink for JsonlSink<W> {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        // keep the file usable by readers tailing it
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
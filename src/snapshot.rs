@@ -0,0 +1,162 @@
+//! Saving the state of trackers to disk and restoring it, e.g. across restarts of a daemon.
+//!
+//! A snapshot file is a JSON object naming the kind of state it holds and the version
+//! of its format, along with the state itself:
+//! ```text
+//! {"kind":"player_tracker","version":1,"data":{...}}
+//! ```
+//! When the format of a kind of state changes, its version is bumped. Snapshots with
+//! older versions can still be loaded by registering migrations with the SnapshotStore,
+//! each upgrading the data of a snapshot by one version.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::{
+    conversations::{Conversation, Conversations},
+    leaderboard::Leaderboards,
+    tracker::{PlayerTracker, SessionTracker},
+    uid::UidStore,
+    Result,
+};
+
+/// State that can be saved to and restored from a snapshot.
+pub trait Snapshot: Sized {
+    /// Name identifying the kind of state in snapshot files.
+    const KIND: &'static str;
+    /// Version of the current format of the state.
+    const VERSION: u32;
+
+    /// Serialize the state in its current format.
+    fn to_snapshot(&self) -> Result<Value>;
+
+    /// Deserialize the state from its current format.
+    fn from_snapshot(data: Value) -> Result<Self>;
+}
+
+macro_rules! serde_snapshot {
+    ($type:ty, $kind:literal, $version:literal) => {
+        impl Snapshot for $type {
+            const KIND: &'static str = $kind;
+            const VERSION: u32 = $version;
+
+            fn to_snapshot(&self) -> Result<Value> {
+                Ok(serde_json::to_value(self)?)
+            }
+
+            fn from_snapshot(data: Value) -> Result<Self> {
+                Ok(serde_json::from_value(data)?)
+            }
+        }
+    };
+}
+
+serde_snapshot!(PlayerTracker, "player_tracker", 1);
+serde_snapshot!(SessionTracker, "session_tracker", 1);
+serde_snapshot!(UidStore, "uid_store", 1);
+serde_snapshot!(Leaderboards, "leaderboards", 1);
+
+impl Snapshot for Conversations {
+    const KIND: &'static str = "conversations";
+    const VERSION: u32 = 1;
+
+    fn to_snapshot(&self) -> Result<Value> {
+        // pairs of names can't be JSON object keys, so only the conversations are stored:
+        // they hold their participants
+        Ok(json!({
+            "conversations": serde_json::to_value(self.conversations.values().collect::<Vec<_>>())?,
+            "history_limit": self.history_limit,
+        }))
+    }
+
+    fn from_snapshot(mut data: Value) -> Result<Self> {
+        let conversations: Vec<Conversation> =
+            serde_json::from_value(data["conversations"].take())?;
+        let history_limit = serde_json::from_value(data["history_limit"].take())?;
+        Ok(Self {
+            conversations: conversations
+                .into_iter()
+                .map(|conversation| (conversation.participants.clone(), conversation))
+                .collect(),
+            history_limit,
+        })
+    }
+}
+
+/// Upgrades the data of a snapshot from one version to the next.
+pub type Migration = Box<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// Saves and loads snapshots, migrating snapshots of older versions on load.
+#[derive(Default)]
+pub struct SnapshotStore {
+    migrations: HashMap<(String, u32), Migration>,
+}
+impl SnapshotStore {
+    /// Create a SnapshotStore with no migrations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a migration upgrading snapshots of the given kind from `from_version`
+    /// to `from_version + 1`.
+    pub fn with_migration<F>(mut self, kind: &str, from_version: u32, migration: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.migrations
+            .insert((kind.to_string(), from_version), Box::new(migration));
+        self
+    }
+
+    /// Save the state to a snapshot file at the given path.
+    /// The file is replaced atomically, so a crash while saving leaves the previous snapshot intact.
+    pub fn save<T: Snapshot>(&self, state: &T, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let snapshot = json!({
+            "kind": T::KIND,
+            "version": T::VERSION,
+            "data": state.to_snapshot()?,
+        });
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(&snapshot)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Load the state from the snapshot file at the given path,
+    /// migrating it to the current version if needed.
+    pub fn load<T: Snapshot>(&self, path: impl AsRef<Path>) -> Result<T> {
+        let snapshot: Value = serde_json::from_slice(&fs::read(path)?)?;
+        let kind = snapshot["kind"].as_str().ok_or("Snapshot has no kind")?;
+        if kind != T::KIND {
+            return Err(format!("Expected a {} snapshot, got {}", T::KIND, kind).into());
+        }
+        let version = snapshot["version"]
+            .as_u64()
+            .ok_or("Snapshot has no version")?;
+        let mut version = u32::try_from(version)?;
+        if version > T::VERSION {
+            return Err(
+                format!("Snapshot version {} is newer than {}", version, T::VERSION).into(),
+            );
+        }
+
+        let mut data = snapshot["data"].clone();
+        while version < T::VERSION {
+            let migration = self
+                .migrations
+                .get(&(kind.to_string(), version))
+                .ok_or_else(|| {
+                    format!(
+                        "No migration for {} snapshots from version {}",
+                        kind, version
+                    )
+                })?;
+            data = migration(data)?;
+            version += 1;
+        }
+        T::from_snapshot(data)
+    }
+}
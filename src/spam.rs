@@ -0,0 +1,204 @@
+//! Detection of players flooding chat with frequent or repeated messages.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Event, MonitorUpdate};
+
+/// A player whose recent chat looks like spam.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpamSuspected {
+    pub player: String,
+    /// How far past the thresholds the player is. Always at least 1.0.
+    pub score: f64,
+    /// The player's messages within the window, oldest first.
+    pub samples: Vec<String>,
+}
+
+/// Tracks the chat messages of each player over a sliding window of time.
+///
+/// A player is suspected of spamming once they send more than the maximum number
+/// of messages within the window, or once a message is similar to more than the
+/// maximum number of their earlier messages within the window. The score is the
+/// larger of the two counts relative to its maximum. Each player is reported at most
+/// once per window. Players who haven't chatted within the window are forgotten.
+#[derive(Debug, Clone)]
+pub struct SpamDetector {
+    window: Duration,
+    max_messages: usize,
+    max_repeats: usize,
+    similarity_threshold: f64,
+    history: HashMap<String, VecDeque<(SystemTime, String)>>,
+    last_reported: HashMap<String, SystemTime>,
+    /// When players who stopped chatting were last forgotten.
+    last_pruned: Option<SystemTime>,
+}
+impl Default for SpamDetector {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            max_messages: 5,
+            max_repeats: 2,
+            similarity_threshold: 0.8,
+            history: HashMap::new(),
+            last_reported: HashMap::new(),
+            last_pruned: None,
+        }
+    }
+}
+impl SpamDetector {
+    /// Create a SpamDetector with the default thresholds: more than 5 messages or
+    /// more than 2 repeats of a message within 10 seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length of the sliding window.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set the number of messages a player may send within the window.
+    pub fn with_max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages.max(1);
+        self
+    }
+
+    /// Set the number of earlier messages within the window a message may be similar to.
+    pub fn with_max_repeats(mut self, max_repeats: usize) -> Self {
+        self.max_repeats = max_repeats.max(1);
+        self
+    }
+
+    /// Set how similar two messages have to be to count as repeats, from 0.0 (any two
+    /// messages) to 1.0 (identical messages, ignoring case). Defaults to 0.8.
+    pub fn with_similarity_threshold(mut self, threshold: f64) -> Self {
+        self.similarity_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Process a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<SpamSuspected> {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> Vec<SpamSuspected> {
+        update
+            .events()
+            .iter()
+            .filter_map(|event| self.check_event_at(event, time))
+            .collect()
+    }
+
+    /// Process a single Event received at the given time.
+    /// Only chat events are considered.
+    pub fn check_event_at(&mut self, event: &Event, time: SystemTime) -> Option<SpamSuspected> {
+        let Event::Chat(chat) = event else {
+            return None;
+        };
+        self.prune(time);
+        let history = self.history.entry(chat.from.clone()).or_default();
+        while history
+            .front()
+            .is_some_and(|(sent, _)| time.duration_since(*sent).unwrap_or_default() > self.window)
+        {
+            history.pop_front();
+        }
+
+        let repeats = history
+            .iter()
+            .filter(|(_, message)| similarity(message, &chat.message) >= self.similarity_threshold)
+            .count();
+        history.push_back((time, chat.message.clone()));
+
+        let rate_score = history.len() as f64 / self.max_messages as f64;
+        let repeat_score = repeats as f64 / self.max_repeats as f64;
+        let exceeded = history.len() > self.max_messages || repeats > self.max_repeats;
+        if !exceeded {
+            return None;
+        }
+
+        let recently_reported = self
+            .last_reported
+            .get(&chat.from)
+            .is_some_and(|last| time.duration_since(*last).unwrap_or_default() < self.window);
+        if recently_reported {
+            return None;
+        }
+        self.last_reported.insert(chat.from.clone(), time);
+        Some(SpamSuspected {
+            player: chat.from.clone(),
+            score: rate_score.max(repeat_score),
+            samples: history.iter().map(|(_, message)| message.clone()).collect(),
+        })
+    }
+
+    /// Forget the players whose newest message or report is older than the window,
+    /// at most once per window.
+    fn prune(&mut self, time: SystemTime) {
+        let expired =
+            |sent: SystemTime| time.duration_since(sent).unwrap_or_default() > self.window;
+        if self.last_pruned.is_some_and(|last| !expired(last)) {
+            return;
+        }
+        self.last_pruned = Some(time);
+        self.history
+            .retain(|_, history| history.back().is_some_and(|(sent, _)| !expired(*sent)));
+        self.last_reported.retain(|_, reported| !expired(*reported));
+    }
+
+    /// Forget the history of every player.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.last_reported.clear();
+    }
+}
+
+/// Similarity of two messages from 0.0 to 1.0, as the Dice coefficient of their
+/// sets of character pairs, ignoring case.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> HashSet<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    let (a, b) = (bigrams(&a), bigrams(&b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(&b).count();
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire;
+
+    #[test]
+    fn quiet_players_are_forgotten() {
+        let chat = |name: &str| wire::parse_line(&format!("chat [FreeChat] {}: hi", name)).unwrap();
+        let mut detector = SpamDetector::new();
+        let start = SystemTime::UNIX_EPOCH;
+        for name in ["Alice", "Bob", "Carol"] {
+            detector.check_event_at(&chat(name), start);
+        }
+        assert_eq!(detector.history.len(), 3);
+
+        let later = start + Duration::from_secs(5);
+        detector.check_event_at(&chat("Alice"), later);
+        detector.check_event_at(&chat("Dave"), later + Duration::from_secs(6));
+        let mut remembered: Vec<&str> = detector.history.keys().map(String::as_str).collect();
+        remembered.sort();
+        assert_eq!(remembered, ["Alice", "Dave"]);
+    }
+}
@@ -0,0 +1,139 @@
+//! Encoding monitor notifications as Server-Sent Events, for live dashboards in the
+//! browser, without depending on any particular HTTP framework.
+//!
+//! An SseEncoder turns each MonitorNotification into a chunk of a `text/event-stream`
+//! body, to be written to the response as is. Each event of an update is sent as an SSE
+//! event named after its kind (see `EventKind::as_str()`), holding the event as JSON, so
+//! pages can listen for the kinds they're after:
+//! `source.addEventListener("chat", (e) => show(JSON.parse(e.data)))`.
+
+use std::{fmt::Write as _, time::Duration};
+
+use serde_json::json;
+
+use crate::{Event, MonitorNotification, MonitorUpdate};
+
+/// The content type of an SSE response.
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+/// A comment chunk, to be sent every so often while there's nothing else to send so
+/// proxies don't close idle connections. Ignored by browsers.
+pub const KEEP_ALIVE: &str = ":\n\n";
+
+/// Encodes notifications as chunks of an SSE stream. Use one per client, as event IDs
+/// and the reconnection delay are tracked per stream.
+///
+/// Besides the events of each update, the encoder sends `connected`, `disconnected`,
+/// `reconnecting`, `player_joined`, `player_left` and `anomaly` events. Other
+/// notifications are left out of the stream.
+#[derive(Debug, Clone, Default)]
+pub struct SseEncoder {
+    retry: Option<Duration>,
+    ids: bool,
+    next_id: u64,
+}
+impl SseEncoder {
+    /// Create an SseEncoder without event IDs, leaving the reconnection delay to the
+    /// browser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tell browsers how long to wait before reconnecting after losing the stream.
+    /// Sent along with the first event.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Number the events sent, starting from 1, so browsers send the ID of the last one
+    /// they got in a `Last-Event-ID` header when reconnecting. Off by default.
+    pub fn with_ids(mut self, ids: bool) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Continue numbering events after the given ID, e.g. the `Last-Event-ID` of a client
+    /// reconnecting. Turns event IDs on.
+    pub fn with_last_id(mut self, last_id: u64) -> Self {
+        self.ids = true;
+        self.next_id = last_id;
+        self
+    }
+
+    /// Encode a MonitorNotification as a chunk of the stream, with an SSE event for
+    /// each event of an update. Empty for notifications that aren't streamed, including
+    /// updates without events.
+    pub fn encode(&mut self, notification: &MonitorNotification) -> String {
+        let mut chunk = String::new();
+        match notification {
+            MonitorNotification::Updated(update) => return self.encode_update(update),
+            MonitorNotification::Connected => self.push(&mut chunk, "connected", "null"),
+            MonitorNotification::Disconnected => self.push(&mut chunk, "disconnected", "null"),
+            MonitorNotification::Reconnecting {
+                attempt,
+                last_error,
+                next_retry_in,
+            } => {
+                let data = json!({
+                    "attempt": attempt,
+                    "last_error": last_error,
+                    "next_retry_in_ms": next_retry_in.as_millis() as u64,
+                });
+                self.push(&mut chunk, "reconnecting", &data.to_string());
+            }
+            MonitorNotification::PlayerJoined(player) => {
+                self.push(&mut chunk, "player_joined", &json!(player).to_string())
+            }
+            MonitorNotification::PlayerLeft(player) => {
+                self.push(&mut chunk, "player_left", &json!(player).to_string())
+            }
+            MonitorNotification::Anomaly(anomaly) => {
+                if let Ok(data) = serde_json::to_string(anomaly) {
+                    self.push(&mut chunk, "anomaly", &data);
+                }
+            }
+            _ => {}
+        }
+        chunk
+    }
+
+    /// Encode the events of a MonitorUpdate as a chunk of the stream, e.g. for updates
+    /// from a `replay::ReplayMonitor`.
+    pub fn encode_update(&mut self, update: &MonitorUpdate) -> String {
+        let mut chunk = String::new();
+        for event in update.events() {
+            self.push_event(&mut chunk, event);
+        }
+        chunk
+    }
+
+    /// Encode a single Event as a chunk of the stream, e.g. from an `EventSink`.
+    pub fn encode_event(&mut self, event: &Event) -> String {
+        let mut chunk = String::new();
+        self.push_event(&mut chunk, event);
+        chunk
+    }
+
+    fn push_event(&mut self, chunk: &mut String, event: &Event) {
+        if let Ok(data) = serde_json::to_string(event) {
+            self.push(chunk, event.kind().as_str(), &data);
+        }
+    }
+
+    fn push(&mut self, chunk: &mut String, name: &str, data: &str) {
+        if let Some(retry) = self.retry.take() {
+            let _ = writeln!(chunk, "retry: {}", retry.as_millis());
+        }
+        if self.ids {
+            self.next_id += 1;
+            let _ = writeln!(chunk, "id: {}", self.next_id);
+        }
+        let _ = writeln!(chunk, "event: {}", name);
+        // a line break would end the field, so each line goes in a field of its own
+        for line in data.split('\n') {
+            let _ = writeln!(chunk, "data: {}", line);
+        }
+        chunk.push('\n');
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::Stream;
+
+use crate::{Monitor, MonitorNotification};
+
+#[derive(Default)]
+struct StreamState {
+    queue: VecDeque<MonitorNotification>,
+    waker: Option<Waker>,
+}
+
+struct MonitorStream {
+    // keeps the connection alive for as long as the stream is
+    _monitor: Monitor,
+    state: Arc<Mutex<StreamState>>,
+}
+impl Stream for MonitorStream {
+    type Item = MonitorNotification;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(notification) => Poll::Ready(Some(notification)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Monitor {
+    /// Turn the Monitor into a Stream of every notification received from now on.
+    /// The Stream never ends; the Monitor keeps reconnecting until the Stream is dropped.
+    /// Updates keep being delivered to the callback, if any, but are no longer buffered for `poll()`.
+    pub fn into_stream(mut self) -> impl Stream<Item = MonitorNotification> + Send + Unpin {
+        // nothing will poll the buffer anymore, so stop filling it
+        self.rx = mpsc::channel().1;
+
+        let state = Arc::new(Mutex::new(StreamState::default()));
+        let st = state.clone();
+        self.add_tap(Box::new(move |notification| {
+            let mut state = st.lock().unwrap();
+            state.queue.push_back(notification.clone());
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            true
+        }));
+        MonitorStream {
+            _monitor: self,
+            state,
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! Stateful views built up from consecutive MonitorUpdates.
+//!
+//! Every MonitorUpdate lists all players online at the time it was sent,
+//! so players missing from an update are considered to have gone offline.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    map::{Position, TILE_SIZE},
+    Event, MonitorUpdate,
+};
+
+/// Width of the cells of the spatial index of a PlayerTracker, in game units.
+const GRID_CELL_SIZE: i32 = TILE_SIZE / 4;
+
+/// The last known state of an online player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackedPlayer {
+    pub name: String,
+    pub x_coord: i32,
+    pub y_coord: i32,
+    /// When the player was first seen in the current session.
+    pub first_seen: SystemTime,
+    /// When the player was last seen.
+    pub last_seen: SystemTime,
+    /// Where and when the player was seen in the update before last, if they were online.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub previous: Option<(Position, SystemTime)>,
+}
+impl TrackedPlayer {
+    /// Get the last known position of the player.
+    pub fn position(&self) -> Position {
+        Position::new(self.x_coord, self.y_coord)
+    }
+
+    /// Get the position of the player at the given time, moving linearly from their
+    /// previous position to their last known one.
+    ///
+    /// Movement is shown one update behind: at the time of the last update, the player
+    /// is at their previous position, and they reach their last known position as much
+    /// later as the two updates were apart. They stay there until the next update.
+    ///
+    /// `at` is a SystemTime rather than an Instant to be on the same clock as the update
+    /// times given to `PlayerTracker::update_at()`, which can be recorded or server times
+    /// and are kept in snapshots. If the clock steps back, the player is held at their
+    /// previous position, and if it steps forward, they jump to their last known one.
+    pub fn interpolated_position(&self, at: SystemTime) -> Position {
+        let Some((from, from_time)) = self.previous else {
+            return self.position();
+        };
+        let interval = self.last_seen.duration_since(from_time).unwrap_or_default();
+        if interval.is_zero() {
+            return self.position();
+        }
+        let elapsed = at.duration_since(self.last_seen).unwrap_or_default();
+        let progress = (elapsed.as_secs_f64() / interval.as_secs_f64()).min(1.0);
+        let lerp = |from: i32, to: i32| {
+            (from as f64 + (to as f64 - from as f64) * progress).round() as i32
+        };
+        Position::new(lerp(from.x, self.x_coord), lerp(from.y, self.y_coord))
+    }
+}
+
+/// Players that came online or went offline between two updates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RosterChanges {
+    pub joined: Vec<String>,
+    pub left: Vec<TrackedPlayer>,
+}
+
+/// Keeps track of which players are online and where they are.
+///
+/// Players are indexed by position, so proximity queries only look at the players
+/// around the point they are about.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Roster", into = "Roster")
+)]
+pub struct PlayerTracker {
+    players: HashMap<String, TrackedPlayer>,
+    /// The names of the players in each cell of a grid over the map.
+    cells: HashMap<(i32, i32), Vec<String>>,
+}
+impl PlayerTracker {
+    /// Create a PlayerTracker with no players online.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refresh the roster from a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) -> RosterChanges {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Refresh the roster from a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> RosterChanges {
+        let mut changes = RosterChanges::default();
+        let mut players = HashMap::with_capacity(self.players.len());
+        for event in update.events() {
+            let Event::Player(player) = event else {
+                continue;
+            };
+            let (first_seen, previous) = match self.players.remove(&player.name) {
+                Some(tracked) => (
+                    tracked.first_seen,
+                    Some((tracked.position(), tracked.last_seen)),
+                ),
+                None if players.contains_key(&player.name) => continue,
+                None => {
+                    changes.joined.push(player.name.clone());
+                    (time, None)
+                }
+            };
+            players.insert(
+                player.name.clone(),
+                TrackedPlayer {
+                    name: player.name.clone(),
+                    x_coord: player.x_coord,
+                    y_coord: player.y_coord,
+                    first_seen,
+                    last_seen: time,
+                    previous,
+                },
+            );
+        }
+        changes.left = std::mem::replace(&mut self.players, players)
+            .into_values()
+            .collect();
+        self.index();
+        changes
+    }
+
+    /// Rebuild the spatial index from the players.
+    fn index(&mut self) {
+        self.cells.clear();
+        for player in self.players.values() {
+            self.cells
+                .entry(cell_of(&player.position()))
+                .or_default()
+                .push(player.name.clone());
+        }
+    }
+
+    /// Forget every player, e.g. after losing the connection.
+    /// Returns the players that were online.
+    pub fn clear(&mut self) -> Vec<TrackedPlayer> {
+        self.cells.clear();
+        self.players.drain().map(|(_, player)| player).collect()
+    }
+
+    /// Get an online player by name.
+    pub fn get(&self, name: &str) -> Option<&TrackedPlayer> {
+        self.players.get(name)
+    }
+
+    /// Iterate over the online players, in no particular order.
+    pub fn players(&self) -> impl Iterator<Item = &TrackedPlayer> {
+        self.players.values()
+    }
+
+    /// Get the number of online players.
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Get the position of every online player at the given time, interpolated
+    /// between their last two known positions for smooth movement between updates.
+    /// See `TrackedPlayer::interpolated_position()`, also for why `at` isn't an Instant.
+    pub fn interpolated_positions(
+        &self,
+        at: SystemTime,
+    ) -> impl Iterator<Item = (&TrackedPlayer, Position)> {
+        self.players
+            .values()
+            .map(move |player| (player, player.interpolated_position(at)))
+    }
+
+    /// Get the players in the given cell.
+    fn players_in(&self, cell: (i32, i32)) -> impl Iterator<Item = &TrackedPlayer> {
+        self.cells
+            .get(&cell)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| self.players.get(name))
+    }
+
+    /// Get the online players at most `radius` game units away from `center`,
+    /// at their last known positions, nearest first.
+    pub fn players_within(&self, center: Position, radius: f64) -> Vec<&TrackedPlayer> {
+        if radius.is_nan() || radius < 0.0 {
+            return Vec::new();
+        }
+        let reach = (radius / GRID_CELL_SIZE as f64).ceil().min(i32::MAX as f64) as i64;
+        let (cx, cy) = cell_of(&center);
+        let side = 2 * reach + 1;
+        let candidates: Box<dyn Iterator<Item = &TrackedPlayer>> =
+            if side.saturating_mul(side) > self.cells.len() as i64 {
+                // fewer occupied cells than cells in range
+                Box::new(self.players.values())
+            } else {
+                Box::new(
+                    cells_between(cx as i64 - reach, cx as i64 + reach)
+                        .flat_map(move |x| {
+                            cells_between(cy as i64 - reach, cy as i64 + reach).map(move |y| (x, y))
+                        })
+                        .flat_map(|cell| self.players_in(cell)),
+                )
+            };
+        let mut found: Vec<(f64, &TrackedPlayer)> = candidates
+            .map(|player| (center.distance_to(&player.position()), player))
+            .filter(|(distance, _)| *distance <= radius)
+            .collect();
+        found.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        found.into_iter().map(|(_, player)| player).collect()
+    }
+
+    /// Get the `k` online players nearest to `point`, at their last known positions,
+    /// nearest first. Fewer are returned if fewer are online.
+    pub fn nearest_players(&self, point: Position, k: usize) -> Vec<&TrackedPlayer> {
+        let by_distance = |found: &mut Vec<(f64, &TrackedPlayer)>| {
+            found.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+            found.truncate(k);
+        };
+        let (cx, cy) = cell_of(&point);
+        let mut found: Vec<(f64, &TrackedPlayer)> = Vec::new();
+        // search rings of cells around the point until the players found are nearer
+        // than anyone in the next ring could be
+        for ring in 0i64.. {
+            if found.len() >= k && k > 0 {
+                by_distance(&mut found);
+                let nearest_outside = ((ring - 1) * GRID_CELL_SIZE as i64) as f64;
+                if found[k - 1].0 <= nearest_outside {
+                    break;
+                }
+            }
+            if k == 0 || 8 * ring > self.cells.len() as i64 {
+                // fewer occupied cells than cells in the ring
+                found = self
+                    .players
+                    .values()
+                    .map(|player| (point.distance_to(&player.position()), player))
+                    .collect();
+                break;
+            }
+            for cell in ring_cells(cx as i64, cy as i64, ring) {
+                found.extend(
+                    self.players_in(cell)
+                        .map(|player| (point.distance_to(&player.position()), player)),
+                );
+            }
+        }
+        by_distance(&mut found);
+        found.into_iter().map(|(_, player)| player).collect()
+    }
+}
+
+/// The state of a PlayerTracker that is saved, without the spatial index.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Roster {
+    players: HashMap<String, TrackedPlayer>,
+}
+#[cfg(feature = "serde")]
+impl From<Roster> for PlayerTracker {
+    fn from(roster: Roster) -> Self {
+        let mut tracker = Self {
+            players: roster.players,
+            cells: HashMap::new(),
+        };
+        tracker.index();
+        tracker
+    }
+}
+#[cfg(feature = "serde")]
+impl From<PlayerTracker> for Roster {
+    fn from(tracker: PlayerTracker) -> Self {
+        Self {
+            players: tracker.players,
+        }
+    }
+}
+
+/// Get the cell of the spatial index a Position lies in.
+fn cell_of(position: &Position) -> (i32, i32) {
+    (
+        position.x.div_euclid(GRID_CELL_SIZE),
+        position.y.div_euclid(GRID_CELL_SIZE),
+    )
+}
+
+/// Get the cell coordinates from `min` to `max`, leaving out those no Position can be in.
+fn cells_between(min: i64, max: i64) -> impl Iterator<Item = i32> + Clone {
+    let bound = |coord: i64| coord.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+    bound(min)..=bound(max)
+}
+
+/// Get the cells on the border of the square `ring` cells away from the given cell.
+fn ring_cells(cx: i64, cy: i64, ring: i64) -> Vec<(i32, i32)> {
+    if ring == 0 {
+        return cells_between(cx, cx).map(|x| (x, cy as i32)).collect();
+    }
+    let mut cells = Vec::with_capacity(8 * ring as usize);
+    for x in cells_between(cx - ring, cx + ring) {
+        for y in cells_between(cy - ring, cy + ring) {
+            let (dx, dy) = ((x as i64 - cx).abs(), (y as i64 - cy).abs());
+            if dx == ring || dy == ring {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// A player's uninterrupted stretch of time online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Session {
+    pub name: String,
+    pub started_at: SystemTime,
+    pub ended_at: SystemTime,
+}
+impl Session {
+    /// Get how long the session lasted.
+    pub fn duration(&self) -> Duration {
+        self.ended_at
+            .duration_since(self.started_at)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    SessionStarted {
+        name: String,
+        at: SystemTime,
+    },
+    SessionEnded {
+        session: Session,
+        duration: Duration,
+    },
+}
+
+/// Tracks player sessions across updates.
+///
+/// A session starts in the first update a player appears in and ends at the time
+/// of the last update they appeared in, once an update without them is received.
+///
+/// Completed sessions are kept until drained with `drain_completed()`, so long-running
+/// trackers should drain them regularly or cap them with `with_max_completed()`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionTracker {
+    roster: PlayerTracker,
+    completed: Vec<Session>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_completed: Option<usize>,
+}
+impl SessionTracker {
+    /// Create a SessionTracker with no sessions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep only the given number of most recently completed sessions, dropping the
+    /// oldest ones as new sessions end.
+    pub fn with_max_completed(mut self, max: usize) -> Self {
+        self.max_completed = Some(max);
+        self.evict_completed();
+        self
+    }
+
+    /// Process a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) -> Vec<SessionEvent> {
+        self.update_at(update, SystemTime::now())
+    }
+
+    /// Process a MonitorUpdate received at the given time.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) -> Vec<SessionEvent> {
+        let changes = self.roster.update_at(update, time);
+        let mut events = self.end_sessions(changes.left);
+        events.extend(
+            changes
+                .joined
+                .into_iter()
+                .map(|name| SessionEvent::SessionStarted { name, at: time }),
+        );
+        events
+    }
+
+    /// End every active session, e.g. after losing the connection.
+    pub fn end_all(&mut self) -> Vec<SessionEvent> {
+        let players = self.roster.clear();
+        self.end_sessions(players)
+    }
+
+    fn end_sessions(&mut self, players: Vec<TrackedPlayer>) -> Vec<SessionEvent> {
+        let events = players
+            .into_iter()
+            .map(|player| {
+                let session = Session {
+                    name: player.name,
+                    started_at: player.first_seen,
+                    ended_at: player.last_seen,
+                };
+                self.completed.push(session.clone());
+                SessionEvent::SessionEnded {
+                    duration: session.duration(),
+                    session,
+                }
+            })
+            .collect();
+        self.evict_completed();
+        events
+    }
+
+    fn evict_completed(&mut self) {
+        if let Some(max) = self.max_completed {
+            let excess = self.completed.len().saturating_sub(max);
+            self.completed.drain(..excess);
+        }
+    }
+
+    /// Get the roster the sessions are derived from.
+    pub fn roster(&self) -> &PlayerTracker {
+        &self.roster
+    }
+
+    /// Get every session that has ended and is still kept, oldest first.
+    pub fn completed_sessions(&self) -> &[Session] {
+        &self.completed
+    }
+
+    /// Take every completed session, oldest first, leaving none.
+    pub fn drain_completed(&mut self) -> Vec<Session> {
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Get the total time the given player has been online since the given time,
+    /// including their current session. Only completed sessions that are still kept count.
+    /// Sessions that started before `since` only count from `since` onwards.
+    pub fn time_played_since(&self, name: &str, since: SystemTime) -> Duration {
+        let current = self.roster.get(name).map(|player| Session {
+            name: player.name.clone(),
+            started_at: player.first_seen,
+            ended_at: player.last_seen,
+        });
+        self.completed
+            .iter()
+            .filter(|session| session.name == name)
+            .chain(current.as_ref())
+            .filter(|session| session.ended_at > since)
+            .map(|session| Session {
+                started_at: session.started_at.max(since),
+                ..session.clone()
+            })
+            .map(|session| session.duration())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire;
+
+    fn roster(names: &[&str]) -> MonitorUpdate {
+        let frame: String = names
+            .iter()
+            .map(|name| format!("player 0 0 {}\n", name))
+            .collect();
+        wire::parse_frame(&frame)
+    }
+
+    fn ended(sessions: &[Session]) -> Vec<&str> {
+        sessions
+            .iter()
+            .map(|session| session.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn completed_sessions_are_capped() {
+        let mut tracker = SessionTracker::new().with_max_completed(2);
+        let start = SystemTime::UNIX_EPOCH;
+        for (i, names) in [&["A", "B"][..], &["C"], &["D"], &[]].iter().enumerate() {
+            tracker.update_at(&roster(names), start + Duration::from_secs(i as u64));
+        }
+        assert_eq!(ended(tracker.completed_sessions()), ["C", "D"]);
+
+        assert_eq!(ended(&tracker.drain_completed()), ["C", "D"]);
+        assert!(tracker.completed_sessions().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+//! Learning which player UIDs go with which names.
+//!
+//! Name requests carry the UID of the player making them, while player events only
+//! carry names. A UidStore remembers the names requested for each UID, and marks a name
+//! as confirmed once a player with it is seen online, so player events can be traced
+//! back to the account behind them. With the `snapshot` feature, the store can be saved
+//! and restored with a `snapshot::SnapshotStore`.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use crate::{name::PlayerName, Event, MonitorUpdate};
+
+/// A name a player was known by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UidName {
+    pub name: String,
+    /// When the UID was first seen with the name.
+    pub first_seen: SystemTime,
+    /// When the UID was last seen with the name, in a name request or online.
+    pub last_seen: SystemTime,
+    /// Whether a player with the name was seen online after it was requested.
+    pub confirmed: bool,
+}
+
+/// Every name a UID was seen with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UidRecord {
+    pub uid: u64,
+    /// The names, in the order they were first seen.
+    pub names: Vec<UidName>,
+}
+impl UidRecord {
+    /// Get the confirmed name the player was last seen online with.
+    pub fn current_name(&self) -> Option<&str> {
+        self.names
+            .iter()
+            .filter(|name| name.confirmed)
+            .max_by_key(|name| name.last_seen)
+            .map(|name| name.name.as_str())
+    }
+}
+
+/// Correlates player UIDs with names over time.
+///
+/// A name maps to the UID it was most recently learned for, since names freed by a
+/// rename can be taken by someone else.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<UidRecord>", into = "Vec<UidRecord>")
+)]
+pub struct UidStore {
+    records: HashMap<u64, UidRecord>,
+    by_name: HashMap<PlayerName, u64>,
+}
+impl UidStore {
+    /// Create an empty UidStore.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Learn from a MonitorUpdate received now.
+    pub fn update(&mut self, update: &MonitorUpdate) {
+        self.update_at(update, SystemTime::now());
+    }
+
+    /// Learn from a MonitorUpdate received at the given time: the names requested in
+    /// name requests, and which of them are online.
+    pub fn update_at(&mut self, update: &MonitorUpdate, time: SystemTime) {
+        for event in update.events() {
+            match event {
+                Event::NameRequest(namereq) => {
+                    self.learn(namereq.player_uid, &namereq.requested_name, time);
+                }
+                Event::Player(player) => self.confirm(&player.name, time),
+                _ => {}
+            }
+        }
+    }
+
+    /// Record that the given UID goes by the given name, e.g. from a source other than
+    /// name requests. Returns `true` if the UID wasn't known with the name before.
+    pub fn learn(&mut self, uid: u64, name: &str, time: SystemTime) -> bool {
+        let player_name = PlayerName::new(name);
+        let record = self.records.entry(uid).or_insert_with(|| UidRecord {
+            uid,
+            names: Vec::new(),
+        });
+        let new = match record
+            .names
+            .iter_mut()
+            .find(|known| PlayerName::new(&known.name) == player_name)
+        {
+            Some(known) => {
+                known.last_seen = known.last_seen.max(time);
+                false
+            }
+            None => {
+                record.names.push(UidName {
+                    name: name.to_string(),
+                    first_seen: time,
+                    last_seen: time,
+                    confirmed: false,
+                });
+                true
+            }
+        };
+        self.by_name.insert(player_name, uid);
+        new
+    }
+
+    /// Mark the given name as confirmed for its UID, having seen it online.
+    fn confirm(&mut self, name: &str, time: SystemTime) {
+        let player_name = PlayerName::new(name);
+        let Some(record) = self
+            .by_name
+            .get(&player_name)
+            .and_then(|uid| self.records.get_mut(uid))
+        else {
+            return;
+        };
+        if let Some(known) = record
+            .names
+            .iter_mut()
+            .find(|known| PlayerName::new(&known.name) == player_name)
+        {
+            known.confirmed = true;
+            known.last_seen = known.last_seen.max(time);
+        }
+    }
+
+    /// Get the UID a name was most recently learned for, ignoring case and whitespace.
+    pub fn uid_for(&self, name: &str) -> Option<u64> {
+        self.by_name.get(&PlayerName::new(name)).copied()
+    }
+
+    /// Get every name known for a UID.
+    pub fn get(&self, uid: u64) -> Option<&UidRecord> {
+        self.records.get(&uid)
+    }
+
+    /// Get the confirmed name a UID was last seen online with.
+    pub fn current_name(&self, uid: u64) -> Option<&str> {
+        self.get(uid).and_then(UidRecord::current_name)
+    }
+
+    /// Iterate over every known UID, in no particular order.
+    pub fn records(&self) -> impl Iterator<Item = &UidRecord> {
+        self.records.values()
+    }
+
+    /// Get the number of known UIDs.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Check if no UIDs are known.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+impl From<Vec<UidRecord>> for UidStore {
+    fn from(records: Vec<UidRecord>) -> Self {
+        let mut names: Vec<(&UidName, u64)> = records
+            .iter()
+            .flat_map(|record| record.names.iter().map(|name| (name, record.uid)))
+            .collect();
+        // names seen more recently win, like they would have when learning them live
+        names.sort_by_key(|(name, _)| name.last_seen);
+        let by_name = names
+            .into_iter()
+            .map(|(name, uid)| (PlayerName::new(&name.name), uid))
+            .collect();
+        Self {
+            by_name,
+            records: records
+                .into_iter()
+                .map(|record| (record.uid, record))
+                .collect(),
+        }
+    }
+}
+impl From<UidStore> for Vec<UidRecord> {
+    fn from(store: UidStore) -> Self {
+        let mut records: Vec<UidRecord> = store.records.into_values().collect();
+        records.sort_by_key(|record| record.uid);
+        records
+    }
+}
@@ -0,0 +1,204 @@
+//! Posting batches of events as JSON to any web service.
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{logging::*, sink::EventSink, Event, Result};
+
+/// The longest wait between two attempts at posting a batch.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Posts events to a URL as JSON arrays of serialized Events, in batches.
+///
+/// Events are collected on a background thread, and a batch is posted once it holds
+/// `batch_size` events or `batch_interval` after its first event arrived, whichever
+/// comes first. Failed posts are retried with exponential backoff, except for client
+/// errors other than 429 Too Many Requests, which sending the same batch again won't fix.
+/// Batches that can't be posted are logged and dropped.
+pub struct HttpSink {
+    url: String,
+    headers: Vec<(String, String)>,
+    batch_size: usize,
+    batch_interval: Duration,
+    max_retries: u32,
+    initial_backoff: Duration,
+    timeout: Duration,
+    worker: Option<(Sender<Event>, JoinHandle<()>)>,
+}
+impl HttpSink {
+    /// Create an HttpSink posting to the given URL.
+    /// By default, batches hold up to 100 events or 5 seconds of them, and failed posts
+    /// are retried 3 times, waiting 1 second before the first retry.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+            batch_size: 100,
+            batch_interval: Duration::from_secs(5),
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            worker: None,
+        }
+    }
+
+    /// Send the given header with every request, e.g. an API key.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Authenticate every request with the given bearer token.
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        self.with_header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Set the most events posted in one request.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set how long the first event of a batch waits for more before the batch is posted.
+    pub fn with_batch_interval(mut self, interval: Duration) -> Self {
+        self.batch_interval = interval;
+        self
+    }
+
+    /// Set how many times a failed post is retried, and how long to wait before the
+    /// first retry. The wait doubles after every failure, up to a minute.
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Set how long a single request may take. Defaults to 10 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn spawn_worker(&self) -> (Sender<Event>, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel();
+        let poster = Poster {
+            agent: ureq::AgentBuilder::new().timeout(self.timeout).build(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            max_retries: self.max_retries,
+            initial_backoff: self.initial_backoff,
+        };
+        let batch_size = self.batch_size;
+        let batch_interval = self.batch_interval;
+        let handle = thread::spawn(move || poster.run(rx, batch_size, batch_interval));
+        (tx, handle)
+    }
+}
+impl EventSink for HttpSink {
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        if self.worker.is_none() {
+            self.worker = Some(self.spawn_worker());
+        }
+        let (tx, _) = self.worker.as_ref().unwrap();
+        tx.send(event.clone())
+            .map_err(|_| "HttpSink worker has stopped")?;
+        Ok(())
+    }
+
+    /// Post the events still waiting and stop the background thread.
+    fn flush(&mut self) -> Result<()> {
+        if let Some((tx, handle)) = self.worker.take() {
+            drop(tx);
+            handle.join().map_err(|_| "HttpSink worker panicked")?;
+        }
+        Ok(())
+    }
+}
+impl Drop for HttpSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+struct Poster {
+    agent: ureq::Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+    max_retries: u32,
+    initial_backoff: Duration,
+}
+impl Poster {
+    /// Collect events into batches and post them until the sink goes away.
+    fn run(&self, rx: Receiver<Event>, batch_size: usize, batch_interval: Duration) {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let received = match deadline {
+                Some(deadline) => {
+                    rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(event) => {
+                    deadline.get_or_insert_with(|| Instant::now() + batch_interval);
+                    batch.push(event);
+                    if batch.len() < batch_size {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    if !batch.is_empty() {
+                        self.post(&batch);
+                    }
+                    return;
+                }
+            }
+            self.post(&batch);
+            batch.clear();
+            deadline = None;
+        }
+    }
+
+    fn post(&self, batch: &[Event]) {
+        let mut backoff = self.initial_backoff;
+        for attempt in 0..=self.max_retries {
+            let mut request = self.agent.post(&self.url);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            let err = match request.send_json(batch) {
+                Ok(_) => return,
+                Err(ureq::Error::Status(status, _)) if status != 429 && status < 500 => {
+                    warn!(
+                        "{} rejected a batch of {} events with status {}, dropping it",
+                        self.url,
+                        batch.len(),
+                        status
+                    );
+                    return;
+                }
+                Err(err) => err,
+            };
+            if attempt == self.max_retries {
+                warn!("Couldn't post a batch of events: {}", err);
+                break;
+            }
+            warn!(
+                "Couldn't post a batch of events ({}), retrying in {:?}",
+                err, backoff
+            );
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        error!(
+            "Dropping a batch of {} events after {} failed attempts",
+            batch.len(),
+            self.max_retries + 1
+        );
+    }
+}